@@ -0,0 +1,162 @@
+//! Timing benchmarks for formula evaluation. They are ignored by default;
+//! run them with
+//!
+//! ```text
+//! cargo test --release benches -- --ignored --nocapture
+//! ```
+//!
+//! They live in the crate rather than under `benches/` because they time
+//! the engine's internals, such as evaluating a compiled formula, which the
+//! `cdylib` does not export.
+
+use super::*;
+use std::hint::black_box;
+use std::time::Instant;
+
+/// Run `f` `iterations` times and return the mean time per run in
+/// microseconds.
+fn time(label: &str, iterations: u32, mut f: impl FnMut()) -> f64 {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let micros = start.elapsed().as_secs_f64() * 1e6 / iterations as f64;
+    println!("{:<40} {:>10.3} µs", label, micros);
+    micros
+}
+
+const FORMULA: &str = "=IF(SUM(A1:A10)>50,MAX(A1:A10)*(1+5%),MIN(A1:A10)/2)&\" units\"";
+
+fn engine_with_data() -> IronCalcEngine {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=10 {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64)).unwrap();
+    }
+    engine
+}
+
+#[test]
+#[ignore]
+fn bench_compiled_evaluation() {
+    let engine = engine_with_data();
+    let iterations = 50_000;
+    let id = CellId::new("Sheet1", 1, 10);
+
+    let parsed = time("parse and evaluate", iterations, || {
+        let formula = Formula::compile(black_box(FORMULA), &engine.functions).unwrap();
+        black_box(engine.evaluate_compiled(&formula, &id).unwrap());
+    });
+
+    let formula = Formula::compile(FORMULA, &engine.functions).unwrap();
+    let worksheet = engine.find_worksheet("Sheet1").unwrap();
+    let walked = time("walk the folded expression", iterations, || {
+        black_box(Evaluator::new(&engine, worksheet).at(1, 10).evaluate(black_box(formula.expr())).unwrap());
+    });
+    let lowered = time("call the lowered closures", iterations, || {
+        black_box(formula.evaluate(&Evaluator::new(&engine, worksheet).at(1, 10)).unwrap());
+    });
+    let compiled = time("evaluate compiled", iterations, || {
+        black_box(engine.evaluate_compiled(black_box(&formula), &id).unwrap());
+    });
+
+    println!("speedup over parsing: {:.1}x", parsed / compiled);
+    println!("speedup of lowering: {:.2}x", walked / lowered);
+    assert!(compiled < parsed);
+}
+
+#[test]
+#[ignore]
+fn bench_formula_column() {
+    // The same formula text entered on every row, as formula columns do
+    let formula = "=IF($A$1>5,$A$2*(1+5%),$A$3/2)&\" units\"";
+    let rows = 20_000;
+    let enter_column = |cache_size: usize| {
+        let mut engine = engine_with_data();
+        engine.compiled_formulas.set_capacity(cache_size);
+        let label = if cache_size == 0 { "enter formula column, parsing" } else { "enter formula column, compiled" };
+        time(label, 1, || {
+            for row in 1..=rows {
+                black_box(engine.evaluate(formula, "Sheet1", row, 2).unwrap());
+            }
+        })
+    };
+
+    let parsed = enter_column(0);
+    let compiled = enter_column(10_000);
+    println!("speedup: {:.1}x", parsed / compiled);
+}
+
+#[test]
+#[ignore]
+fn bench_recalculating_chain() {
+    // A2 = A1+1, A3 = A2+1, ... so that every edit of A1 recalculates the
+    // whole chain from the cells' compiled formulas
+    let length = 5_000;
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value("Sheet1", 1, 1, CellValue::Number(0.0)).unwrap();
+    for row in 2..=length {
+        engine.evaluate(&format!("=A{}*(1+0%)+1", row - 1), "Sheet1", row, 1).unwrap();
+    }
+
+    let mut n = 0.0;
+    time("recalculate 5000-cell chain", 20, || {
+        n += 1.0;
+        engine.set_cell_value("Sheet1", 1, 1, CellValue::Number(n)).unwrap();
+    });
+    assert_eq!(engine.get_cell_value("Sheet1", length, 1).unwrap(), (n + (length - 1) as f64).to_string());
+}
+
+#[test]
+#[ignore]
+fn bench_sorted_lookup() {
+    // Lookups into 100,000 sorted rows only read the cells a binary search
+    // probes, while an exact match scans the column
+    let rows = 100_000;
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=rows {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64 * 2.0)).unwrap();
+        engine.set_cell_value("Sheet1", row, 2, CellValue::Number(row as f64)).unwrap();
+    }
+    let id = CellId::new("Sheet1", 1, 4);
+    let lookup = |formula: &str| Formula::compile(formula, &engine.functions).unwrap();
+
+    let approximate = lookup("=VLOOKUP(150001,A:B,2)");
+    let binary = lookup("=XLOOKUP(150000,A1:A100000,B1:B100000,,0,2)");
+    let exact = lookup("=MATCH(150000,A:A,0)");
+    let approximate = time("VLOOKUP approximate, 100k rows", 10_000, || {
+        black_box(engine.evaluate_compiled(&approximate, &id).unwrap());
+    });
+    time("XLOOKUP binary search, 100k rows", 10_000, || {
+        black_box(engine.evaluate_compiled(&binary, &id).unwrap());
+    });
+    time("MATCH exact, 100k rows", 10, || {
+        black_box(engine.evaluate_compiled(&exact, &id).unwrap());
+    });
+
+    assert_eq!(engine.evaluate_compiled(&binary, &id).unwrap().0, CellValue::Number(75_000.0));
+    assert!(approximate < 100.0);
+}
+
+#[test]
+#[ignore]
+fn bench_lookup_column() {
+    // A lookup on every row reading the same large range, as a formula
+    // column joining two datasets does
+    let rows = 100_000;
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=rows {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64)).unwrap();
+        engine.set_cell_value("Sheet1", row, 2, CellValue::Number(row as f64 * 10.0)).unwrap();
+    }
+
+    time("enter 100000 lookups", 1, || {
+        for row in 1..=rows {
+            let formula = format!("=VLOOKUP(A{},$A$1:$B${},2)", row, rows);
+            black_box(engine.evaluate(&formula, "Sheet1", row as u32, 3).unwrap());
+        }
+    });
+    time("edit a cell read by every lookup", 1, || {
+        engine.set_cell_value("Sheet1", 5, 2, CellValue::Number(-1.0)).unwrap();
+    });
+    assert_eq!(engine.get_cell_value("Sheet1", 5, 3).unwrap(), "-1");
+}
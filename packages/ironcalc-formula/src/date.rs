@@ -0,0 +1,278 @@
+//! Excel serial dates
+//!
+//! Excel stores a date as a number of days from a day 0 and a time as a
+//! fraction of a day, so 45306.5 is noon on 2024-01-15. In the 1900 date
+//! system of Excel for Windows day 1 is 1900-01-01 and day 60 is
+//! 1900-02-29, a day that never was: Lotus 1-2-3 took 1900 for a leap year
+//! and Excel kept the mistake so that serial numbers stayed compatible.
+//! Later dates are one day further from day 0 than the calendar says, and
+//! day 0 reads as 1900-01-00. The 1904 system of older Mac workbooks has
+//! day 0 on 1904-01-01 and no missing day. Both end on 9999-12-31.
+
+use crate::error::ExcelError;
+use chrono::{Datelike, NaiveDate};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DateSystem {
+    #[default]
+    Excel1900,
+    Excel1904,
+}
+
+impl DateSystem {
+    pub fn parse(system: &str) -> Option<DateSystem> {
+        match system {
+            "1900" => Some(DateSystem::Excel1900),
+            "1904" => Some(DateSystem::Excel1904),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateSystem::Excel1900 => "1900",
+            DateSystem::Excel1904 => "1904",
+        }
+    }
+
+    /// The serial number of 9999-12-31.
+    pub fn last(&self) -> i64 {
+        match self {
+            DateSystem::Excel1900 => 2_958_465,
+            DateSystem::Excel1904 => 2_957_003,
+        }
+    }
+
+    /// The serial number of a day of a month. Months below 1 or past 12 run
+    /// into other years and days below 1 or past the end of the month into
+    /// other months, so month 14, day 0 of 2024 is 2025-01-31. Dates
+    /// outside the system, however far, are `#NUM!`.
+    pub fn serial(&self, year: i64, month: i64, day: i64) -> Result<i64, ExcelError> {
+        let months = year
+            .checked_mul(12)
+            .and_then(|months| months.checked_add(month))
+            .and_then(|months| months.checked_sub(1))
+            .ok_or(ExcelError::Num)?;
+        let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+        if !(0..=9999).contains(&year) {
+            return Err(ExcelError::Num);
+        }
+
+        let first = match self {
+            DateSystem::Excel1900 => {
+                let days = (date(year, month, 1) - date(1899, 12, 30)).num_days();
+                // Before March 1900 the missing day is not yet counted
+                if days < 61 { days - 1 } else { days }
+            }
+            DateSystem::Excel1904 => (date(year, month, 1) - date(1904, 1, 1)).num_days(),
+        };
+        let serial = first.checked_add(day).and_then(|serial| serial.checked_sub(1));
+        serial.filter(|serial| (0..=self.last()).contains(serial)).ok_or(ExcelError::Num)
+    }
+
+    /// The year, month and day of a serial number's date, including
+    /// 1900-01-00 and 1900-02-29 in the 1900 system.
+    pub fn ymd(&self, serial: i64) -> Result<(i64, i64, i64), ExcelError> {
+        if !(0..=self.last()).contains(&serial) {
+            return Err(ExcelError::Num);
+        }
+
+        let (day_0, days) = match self {
+            DateSystem::Excel1900 if serial == 0 => return Ok((1900, 1, 0)),
+            DateSystem::Excel1900 if serial == 60 => return Ok((1900, 2, 29)),
+            DateSystem::Excel1900 if serial < 60 => (date(1899, 12, 31), serial),
+            DateSystem::Excel1900 => (date(1899, 12, 30), serial),
+            DateSystem::Excel1904 => (date(1904, 1, 1), serial),
+        };
+        let date = day_0 + chrono::Days::new(days as u64);
+        Ok((date.year() as i64, date.month() as i64, date.day() as i64))
+    }
+
+    /// The number of days in a month, 29 for February 1900 in the 1900
+    /// system.
+    pub fn days_in_month(&self, year: i64, month: i64) -> i64 {
+        if *self == DateSystem::Excel1900 && year == 1900 && month == 2 {
+            return 29;
+        }
+        let next = if month == 12 { date(year + 1, 1, 1) } else { date(year, month + 1, 1) };
+        (next - date(year, month, 1)).num_days()
+    }
+
+    /// The day of the week of a serial number, 0 for Sunday to 6 for
+    /// Saturday. In the 1900 system the days before the missing one fall a
+    /// day out from the calendar, so that 1900-01-01 is a Sunday.
+    pub fn weekday(&self, serial: i64) -> i64 {
+        match self {
+            DateSystem::Excel1900 => (serial + 6).rem_euclid(7),
+            // 1904-01-01 was a Friday
+            DateSystem::Excel1904 => (serial + 5).rem_euclid(7),
+        }
+    }
+}
+
+/// A date known to exist.
+fn date(year: i64, month: i64, day: i64) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32).expect("valid date")
+}
+
+/// The hour, minute and second of a serial number's time, rounded to the
+/// nearest second as Excel shows it.
+pub fn hms(serial: f64) -> (i64, i64, i64) {
+    let seconds = ((serial - serial.floor()) * 86_400.0).round() as i64 % 86_400;
+    (seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Text such as "2024-01-15", "1/15/2024", "15-Jan-2024",
+/// "January 15, 2024", "14:30", "2:30:15 PM" or a date then a time, as a
+/// serial day and a fraction of a day. Months and days come in the US
+/// order, and two-digit years below 30 are in the 2000s. `None` when the
+/// text is neither, or its date is outside the system.
+pub fn parse(system: DateSystem, text: &str) -> Option<(Option<i64>, Option<f64>)> {
+    let text = text.trim();
+    let upper = text.to_ascii_uppercase();
+    let (rest, pm) = match upper.strip_suffix("AM").or_else(|| upper.strip_suffix("PM")) {
+        Some(rest) if rest.ends_with(|c: char| c.is_ascii_digit() || c == ' ') => {
+            (text[..rest.len()].trim_end(), Some(upper.ends_with("PM")))
+        }
+        _ => (text, None),
+    };
+
+    let (date_text, time_text) = if pm.is_some() || rest.contains(':') {
+        match rest.rfind(' ').or_else(|| rest.find('T')) {
+            Some(split) => (Some(rest[..split].trim()), Some(&rest[split + 1..])),
+            None => (None, Some(rest)),
+        }
+    } else {
+        (Some(rest), None)
+    };
+
+    let date = match date_text {
+        Some(text) => Some(parse_date(system, text)?),
+        None => None,
+    };
+    let time = match time_text {
+        Some(text) => Some(parse_time(text, pm)?),
+        None => None,
+    };
+    Some((date, time))
+}
+
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november",
+    "december",
+];
+
+fn parse_date(system: DateSystem, text: &str) -> Option<i64> {
+    let month_named = |word: &str| {
+        let word = word.to_lowercase();
+        (word.len() >= 3).then(|| MONTHS.iter().position(|month| month.starts_with(&word)))?.map(|index| index as i64 + 1)
+    };
+    let number = |word: &str| (word.len() <= 4 && word.bytes().all(|b| b.is_ascii_digit())).then(|| word.parse::<i64>().ok())?;
+    let year = |word: &str| match (word.len(), number(word)?) {
+        (1 | 2, year) if year < 30 => Some(2000 + year),
+        (1 | 2, year) => Some(1900 + year),
+        (4, year) => Some(year),
+        _ => None,
+    };
+
+    let words: Vec<&str> = text.split(['-', '/', ' ', ',']).filter(|word| !word.is_empty()).collect();
+    let (year, month, day) = match words.as_slice() {
+        [first, second, third] if first.len() == 4 => (year(first)?, number(second)?, number(third)?),
+        [first, second, third] => match (month_named(first), month_named(second)) {
+            (Some(month), _) => (year(third)?, month, number(second)?),
+            (_, Some(month)) => (year(third)?, month, number(first)?),
+            _ => (year(third)?, number(first)?, number(second)?),
+        },
+        [month, year_word] => (year(year_word)?, month_named(month)?, 1),
+        _ => return None,
+    };
+
+    if year < 1900 || !(1..=12).contains(&month) || !(1..=system.days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    system.serial(year, month, day).ok()
+}
+
+/// Hours, minutes and seconds as a fraction of a day. Hours past 24 run
+/// into the next day, as Excel reads "25:00".
+fn parse_time(text: &str, pm: Option<bool>) -> Option<f64> {
+    let parts: Vec<&str> = text.split(':').collect();
+    let whole = |part: &str| (!part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())).then(|| part.parse::<f64>().ok())?;
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [hours] if pm.is_some() => (whole(hours)?, 0.0, 0.0),
+        [hours, minutes] => (whole(hours)?, whole(minutes)?, 0.0),
+        [hours, minutes, seconds] => (whole(hours)?, whole(minutes)?, seconds.parse::<f64>().ok().filter(|s| *s >= 0.0)?),
+        _ => return None,
+    };
+    if minutes >= 60.0 || seconds >= 60.0 {
+        return None;
+    }
+
+    let hours = match pm {
+        Some(_) if hours > 12.0 => return None,
+        Some(pm) => hours % 12.0 + if pm { 12.0 } else { 0.0 },
+        None => hours,
+    };
+    Some((hours * 3600.0 + minutes * 60.0 + seconds) / 86_400.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_1900_leap_year_bug() {
+        let system = DateSystem::Excel1900;
+        assert_eq!(system.serial(1900, 1, 1), Ok(1));
+        assert_eq!(system.serial(1900, 2, 28), Ok(59));
+        assert_eq!(system.serial(1900, 2, 29), Ok(60));
+        assert_eq!(system.serial(1900, 3, 1), Ok(61));
+        assert_eq!(system.serial(2024, 1, 15), Ok(45306));
+        assert_eq!(system.serial(9999, 12, 31), Ok(system.last()));
+        assert_eq!(system.serial(1899, 12, 1), Err(ExcelError::Num));
+
+        assert_eq!(system.ymd(0), Ok((1900, 1, 0)));
+        assert_eq!(system.ymd(59), Ok((1900, 2, 28)));
+        assert_eq!(system.ymd(60), Ok((1900, 2, 29)));
+        assert_eq!(system.ymd(61), Ok((1900, 3, 1)));
+        assert_eq!(system.ymd(45306), Ok((2024, 1, 15)));
+        assert_eq!(system.weekday(1), 0);
+        assert_eq!(system.weekday(45306), 1);
+    }
+
+    #[test]
+    fn test_1904_system() {
+        let system = DateSystem::Excel1904;
+        assert_eq!(system.serial(1904, 1, 1), Ok(0));
+        assert_eq!(system.serial(2024, 1, 15), Ok(45306 - 1462));
+        assert_eq!(system.serial(1903, 12, 31), Err(ExcelError::Num));
+        assert_eq!(system.ymd(0), Ok((1904, 1, 1)));
+        assert_eq!(system.ymd(9999 * 365), Err(ExcelError::Num));
+        assert_eq!(system.weekday(0), 5);
+        assert_eq!(system.days_in_month(1900, 2), 28);
+    }
+
+    #[test]
+    fn test_parse() {
+        let system = DateSystem::Excel1900;
+        let date = |text| parse(system, text).and_then(|(date, _)| date);
+        let time = |text| parse(system, text).and_then(|(_, time)| time);
+        for text in ["2024-01-15", "2024/1/15", "1/15/2024", "1-15-24", "15-Jan-2024", "15 January 2024", "Jan 15, 2024"] {
+            assert_eq!(date(text), Some(45306), "{}", text);
+        }
+        assert_eq!(date("Feb 2024"), Some(45323));
+        assert_eq!(date("2/29/1900"), Some(60));
+        assert_eq!(date("2/30/2024"), None);
+        assert_eq!(date("13/1/2024"), None);
+        assert_eq!(date("hello"), None);
+
+        assert_eq!(time("12:00"), Some(0.5));
+        assert_eq!(time("6 PM"), Some(0.75));
+        assert_eq!(time("12:00 AM"), Some(0.0));
+        assert_eq!(time("6:00:30pm"), Some(0.75 + 30.0 / 86_400.0));
+        assert_eq!(time("25:00"), Some(25.0 / 24.0));
+        assert_eq!(time("12:60"), None);
+        assert_eq!(parse(system, "2024-01-15 12:00"), Some((Some(45306), Some(0.5))));
+        assert_eq!(parse(system, "2024-01-15T12:00"), Some((Some(45306), Some(0.5))));
+    }
+}
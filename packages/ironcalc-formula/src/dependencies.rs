@@ -0,0 +1,631 @@
+//! Dependency tracking between cells
+//!
+//! Each formula cell records its precedents (the cells, ranges and 3D spans
+//! its formula reads) in `Cell::dependencies`. [`DependencyGraph`] keeps the
+//! reverse edges so that, when a cell changes, the formulas reading it can be
+//! found and recalculated in topological order.
+//!
+//! Precedents name sheets rather than pointing at them, so a 3D span follows
+//! the current tab order: a sheet inserted between its endpoints becomes part
+//! of it without the formula being re-registered.
+//!
+//! A formula whose array result spills also supplies the cells it spills
+//! into, so the formulas reading those cells depend on it as well.
+
+use crate::parser::Expr;
+use crate::reference::{column_to_letters, quote_sheet_name, CellRef, RangeRef};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// Address of a cell in the workbook.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellId {
+    pub sheet: String,
+    pub row: i32,
+    pub col: i32,
+}
+
+impl CellId {
+    pub fn new(sheet: &str, row: i32, col: i32) -> Self {
+        Self {
+            sheet: sheet.to_string(),
+            row,
+            col,
+        }
+    }
+}
+
+impl fmt::Display for CellId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}!{}{}", quote_sheet_name(&self.sheet), column_to_letters(self.col), self.row)
+    }
+}
+
+/// Something a formula reads. Sheet names are resolved to the worksheet's
+/// own spelling when the sheet exists, and kept as written otherwise.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Precedent {
+    Cell(CellId),
+    Range { sheet: String, range: RangeRef },
+    /// The same range on every sheet from `first_sheet` to `last_sheet`
+    Span {
+        first_sheet: String,
+        last_sheet: String,
+        range: RangeRef,
+    },
+}
+
+impl Precedent {
+    /// Whether this precedent covers any cell of `range` on `sheet`, given
+    /// the sheets in tab order.
+    fn overlaps(&self, sheet: &str, range: &RangeRef, sheets: &[String]) -> bool {
+        match self {
+            Precedent::Cell(id) => id.sheet == sheet && range.contains(id.row, id.col),
+            Precedent::Range { sheet: name, range: read } => name == sheet && read.overlaps(range),
+            Precedent::Span {
+                first_sheet,
+                last_sheet,
+                range: read,
+            } => {
+                let position = |name: &str| sheets.iter().position(|other| other == name);
+                match (position(first_sheet), position(last_sheet), position(sheet)) {
+                    (Some(first), Some(last), Some(index)) => {
+                        (first.min(last)..=first.max(last)).contains(&index) && read.overlaps(range)
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /// Whether this precedent names `sheet`, ignoring case.
+    pub fn mentions_sheet(&self, sheet: &str) -> bool {
+        match self {
+            Precedent::Cell(id) => id.sheet.eq_ignore_ascii_case(sheet),
+            Precedent::Range { sheet: name, .. } => name.eq_ignore_ascii_case(sheet),
+            Precedent::Span {
+                first_sheet,
+                last_sheet,
+                ..
+            } => first_sheet.eq_ignore_ascii_case(sheet) || last_sheet.eq_ignore_ascii_case(sheet),
+        }
+    }
+}
+
+fn cell_ref(cell: &CellId) -> CellRef {
+    CellRef {
+        row: cell.row,
+        col: cell.col,
+        row_absolute: false,
+        col_absolute: false,
+    }
+}
+
+/// Collect the precedents of a parsed formula. `resolve_sheet` maps the sheet
+/// qualifier of a reference (`None` when unqualified) to a sheet name.
+pub fn precedents(expr: &Expr, resolve_sheet: &dyn Fn(Option<&str>) -> String) -> Vec<Precedent> {
+    let mut found = Vec::new();
+    collect(expr, resolve_sheet, &mut found);
+    found
+}
+
+fn collect(expr: &Expr, resolve_sheet: &dyn Fn(Option<&str>) -> String, found: &mut Vec<Precedent>) {
+    let precedent = match expr {
+        Expr::Reference { sheet, cell } => {
+            Precedent::Cell(CellId::new(&resolve_sheet(sheet.as_deref()), cell.row, cell.col))
+        }
+        Expr::Range { sheet, range } => Precedent::Range {
+            sheet: resolve_sheet(sheet.as_deref()),
+            range: *range,
+        },
+        // A spill reference reads its cell, whose spill range follows
+        Expr::SpillRef { sheet, cell } => {
+            Precedent::Cell(CellId::new(&resolve_sheet(sheet.as_deref()), cell.row, cell.col))
+        }
+        Expr::Range3D {
+            first_sheet,
+            last_sheet,
+            range,
+        } => Precedent::Span {
+            first_sheet: resolve_sheet(Some(first_sheet)),
+            last_sheet: resolve_sheet(Some(last_sheet)),
+            range: *range,
+        },
+        Expr::Unary { operand, .. } => return collect(operand, resolve_sheet, found),
+        Expr::Binary { left, right, .. } => {
+            collect(left, resolve_sheet, found);
+            return collect(right, resolve_sheet, found);
+        }
+        Expr::Function { args, .. } => {
+            for arg in args {
+                collect(arg, resolve_sheet, found);
+            }
+            return;
+        }
+        Expr::Call { callee, args } => {
+            collect(callee, resolve_sheet, found);
+            for arg in args {
+                collect(arg, resolve_sheet, found);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    if !found.contains(&precedent) {
+        found.push(precedent);
+    }
+}
+
+/// Reverse edges from precedents to the formula cells that read them.
+#[derive(Default)]
+pub struct DependencyGraph {
+    /// Formula cells reading each single cell
+    cell_dependents: HashMap<CellId, HashSet<CellId>>,
+    /// Formula cells reading each range or 3D span, checked by
+    /// containment. Formulas filled down a column often read the same
+    /// range, which is then checked once for all of them.
+    range_dependents: HashMap<Precedent, HashSet<CellId>>,
+    /// The ranges of `range_dependents` by sheet, so that a change only
+    /// checks the ranges near the cell
+    ranges: HashMap<String, RangeIndex>,
+    /// The 3D spans of `range_dependents`, whose sheets depend on the tab
+    /// order
+    spans: RangeIndex,
+    /// The ranges formula cells spill into, anchor cell included
+    spills: HashMap<CellId, RangeRef>,
+}
+
+impl DependencyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that the formula in `cell` reads `precedents`.
+    pub fn add(&mut self, cell: &CellId, precedents: &[Precedent]) {
+        for precedent in precedents {
+            match precedent {
+                Precedent::Cell(id) => {
+                    self.cell_dependents.entry(id.clone()).or_default().insert(cell.clone());
+                }
+                _ => {
+                    if !self.range_dependents.contains_key(precedent) {
+                        self.index_of(precedent).insert(precedent);
+                    }
+                    self.range_dependents.entry(precedent.clone()).or_default().insert(cell.clone());
+                }
+            }
+        }
+    }
+
+    /// Forget the edges previously added for `cell` with `precedents`.
+    pub fn remove(&mut self, cell: &CellId, precedents: &[Precedent]) {
+        for precedent in precedents {
+            let (edges, key) = match precedent {
+                Precedent::Cell(id) => (&mut self.cell_dependents, id),
+                _ => {
+                    remove_edge(&mut self.range_dependents, precedent, cell);
+                    if !self.range_dependents.contains_key(precedent) {
+                        self.index_of(precedent).remove(precedent);
+                    }
+                    continue;
+                }
+            };
+            remove_edge(edges, key, cell);
+        }
+    }
+
+    /// The index a range or 3D span is kept in.
+    fn index_of(&mut self, precedent: &Precedent) -> &mut RangeIndex {
+        match precedent {
+            Precedent::Range { sheet, .. } => self.ranges.entry(sheet.clone()).or_default(),
+            _ => &mut self.spans,
+        }
+    }
+
+    /// Formula cells reading a range or 3D span that overlaps `area` on
+    /// `sheet`.
+    fn range_readers<'s>(&'s self, sheet: &'s str, area: &'s RangeRef, sheets: &'s [String]) -> impl Iterator<Item = &'s CellId> {
+        self.ranges
+            .get(sheet)
+            .into_iter()
+            .flat_map(|index| index.near(area))
+            .chain(self.spans.near(area))
+            .filter(move |precedent| precedent.overlaps(sheet, area, sheets))
+            .filter_map(|precedent| self.range_dependents.get(precedent))
+            .flatten()
+    }
+
+    /// Record the range the formula in `cell` spills into, or that it no
+    /// longer spills.
+    pub fn set_spill(&mut self, cell: &CellId, range: Option<RangeRef>) {
+        match range {
+            Some(range) => self.spills.insert(cell.clone(), range),
+            None => self.spills.remove(cell),
+        };
+    }
+
+    /// Formula cells that read `cell` directly, or read a cell its formula
+    /// spills into.
+    pub fn dependents(&self, cell: &CellId, sheets: &[String]) -> Vec<CellId> {
+        let mut dependents: HashSet<&CellId> = self.cell_dependents.get(cell).into_iter().flatten().collect();
+
+        let area = RangeRef::new(cell_ref(cell), cell_ref(cell));
+        dependents.extend(self.range_readers(&cell.sheet, &area, sheets));
+
+        if let Some(spill) = self.spills.get(cell) {
+            // Look up the cells of the spill range, or go through the cells
+            // read when there are fewer of those
+            let (rows, cols) = (spill.start.row..=spill.end.row, spill.start.col..=spill.end.col);
+            let size = (spill.end.row - spill.start.row + 1) as usize * (spill.end.col - spill.start.col + 1) as usize;
+            if size <= self.cell_dependents.len() {
+                let cells = rows.flat_map(|row| cols.clone().map(move |col| CellId::new(&cell.sheet, row, col)));
+                dependents.extend(cells.filter_map(|id| self.cell_dependents.get(&id)).flatten());
+            } else {
+                let readers = self
+                    .cell_dependents
+                    .iter()
+                    .filter(|(id, _)| id.sheet == cell.sheet && spill.contains(id.row, id.col))
+                    .flat_map(|(_, readers)| readers);
+                dependents.extend(readers);
+            }
+            dependents.extend(self.range_readers(&cell.sheet, spill, sheets));
+        }
+
+        // Deterministic order keeps recalculation reproducible
+        let mut dependents: Vec<CellId> = dependents.into_iter().cloned().collect();
+        dependents.sort();
+        dependents
+    }
+
+    /// The cells in `start` and every formula that depends on them, directly
+    /// or transitively, ordered so that each step comes after all of its
+    /// precedents. Cells that depend on each other in a circle are grouped
+    /// into a single [`Step::Cycle`].
+    pub fn recalculation_order(&self, start: &[CellId], sheets: &[String]) -> Vec<Step> {
+        let mut search = ComponentSearch::new(self, sheets);
+        for root in start {
+            search.visit(root);
+        }
+
+        // Components are found dependents-first
+        search.steps.reverse();
+        search.steps
+    }
+
+    /// A circular chain through `from` within the cells of a cycle, in
+    /// reading order: each cell's formula refers to the next, and the chain
+    /// ends back at `from`.
+    pub fn cycle_path(&self, cycle: &[CellId], from: &CellId, sheets: &[String]) -> Vec<CellId> {
+        // Breadth-first search along dependent edges finds the shortest way
+        // back to `from`; walking it backwards follows the references
+        let mut reached_from: HashMap<CellId, CellId> = HashMap::new();
+        let mut queue = VecDeque::from([from.clone()]);
+
+        while let Some(cell) = queue.pop_front() {
+            for dependent in self.dependents(&cell, sheets) {
+                if !cycle.contains(&dependent) || reached_from.contains_key(&dependent) {
+                    continue;
+                }
+                reached_from.insert(dependent.clone(), cell.clone());
+                if dependent == *from {
+                    queue.clear();
+                    break;
+                }
+                queue.push_back(dependent);
+            }
+        }
+
+        let mut path = vec![from.clone()];
+        let mut cell = from.clone();
+        while let Some(previous) = reached_from.get(&cell) {
+            path.push(previous.clone());
+            if previous == from {
+                break;
+            }
+            cell = previous.clone();
+        }
+        path
+    }
+}
+
+/// Remove the edge from `key` to `cell`, and the key once nothing reads it.
+fn remove_edge<K: std::hash::Hash + Eq, V: std::hash::Hash + Eq>(edges: &mut HashMap<K, HashSet<V>>, key: &K, cell: &V) {
+    if let Some(dependents) = edges.get_mut(key) {
+        dependents.remove(cell);
+        if dependents.is_empty() {
+            edges.remove(key);
+        }
+    }
+}
+
+/// Ranges bucketed by position, so that those near a cell are found
+/// without going through all of them. Each range is kept in the grid of
+/// square buckets of the smallest power-of-two size at which it touches at
+/// most two buckets down and two across, so the ranges near a cell are in
+/// one bucket per size.
+#[derive(Default)]
+struct RangeIndex {
+    buckets: HashMap<(u32, i32, i32), HashSet<Precedent>>,
+}
+
+/// Bucket sizes go up to 2^21, larger than a whole column
+const BUCKET_SIZES: u32 = 22;
+
+impl RangeIndex {
+    fn insert(&mut self, precedent: &Precedent) {
+        for key in buckets(range_of(precedent)) {
+            self.buckets.entry(key).or_default().insert(precedent.clone());
+        }
+    }
+
+    fn remove(&mut self, precedent: &Precedent) {
+        for key in buckets(range_of(precedent)) {
+            remove_edge(&mut self.buckets, &key, precedent);
+        }
+    }
+
+    /// The ranges in the buckets `area` touches, which include every range
+    /// that overlaps it; a range may be given more than once.
+    fn near<'s>(&'s self, area: &'s RangeRef) -> impl Iterator<Item = &'s Precedent> {
+        (0..BUCKET_SIZES).flat_map(move |size| {
+            let rows = (area.start.row >> size)..=(area.end.row >> size);
+            rows.flat_map(move |row| {
+                let cols = (area.start.col >> size)..=(area.end.col >> size);
+                cols.filter_map(move |col| self.buckets.get(&(size, row, col))).flatten()
+            })
+        })
+    }
+}
+
+fn range_of(precedent: &Precedent) -> &RangeRef {
+    match precedent {
+        Precedent::Range { range, .. } | Precedent::Span { range, .. } => range,
+        Precedent::Cell(_) => unreachable!("single cells are not indexed"),
+    }
+}
+
+/// The buckets a range is kept in.
+fn buckets(range: &RangeRef) -> Vec<(u32, i32, i32)> {
+    let fits = |size: u32| {
+        (range.end.row >> size) - (range.start.row >> size) <= 1 && (range.end.col >> size) - (range.start.col >> size) <= 1
+    };
+    let size = (0..BUCKET_SIZES).find(|size| fits(*size)).unwrap_or(BUCKET_SIZES - 1);
+    let rows = (range.start.row >> size)..=(range.end.row >> size);
+    rows.flat_map(|row| ((range.start.col >> size)..=(range.end.col >> size)).map(move |col| (size, row, col)))
+        .collect()
+}
+
+/// One step of a recalculation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Cell(CellId),
+    /// Cells whose formulas depend on each other in a circle, including a
+    /// single cell that refers to itself
+    Cycle(Vec<CellId>),
+}
+
+/// Tarjan's strongly connected components algorithm over dependent edges,
+/// run iteratively so that long chains of formulas cannot overflow the
+/// stack.
+struct ComponentSearch<'g> {
+    graph: &'g DependencyGraph,
+    sheets: &'g [String],
+    index: HashMap<CellId, usize>,
+    lowlink: HashMap<CellId, usize>,
+    stack: Vec<CellId>,
+    on_stack: HashSet<CellId>,
+    steps: Vec<Step>,
+}
+
+impl<'g> ComponentSearch<'g> {
+    fn new(graph: &'g DependencyGraph, sheets: &'g [String]) -> Self {
+        Self {
+            graph,
+            sheets,
+            index: HashMap::new(),
+            lowlink: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, root: &CellId) {
+        if self.index.contains_key(root) {
+            return;
+        }
+
+        let mut frames = vec![self.enter(root)];
+        while let Some((cell, children, next)) = frames.last_mut() {
+            if let Some(child) = children.get(*next).cloned() {
+                *next += 1;
+                if !self.index.contains_key(&child) {
+                    let frame = self.enter(&child);
+                    frames.push(frame);
+                } else if self.on_stack.contains(&child) {
+                    let low = self.lowlink[cell].min(self.index[&child]);
+                    self.lowlink.insert(cell.clone(), low);
+                }
+                continue;
+            }
+
+            let Some((cell, children, _)) = frames.pop() else {
+                break;
+            };
+            if let Some((parent, _, _)) = frames.last() {
+                let low = self.lowlink[parent].min(self.lowlink[&cell]);
+                self.lowlink.insert(parent.clone(), low);
+            }
+
+            if self.lowlink[&cell] == self.index[&cell] {
+                let mut component = Vec::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(&member);
+                    let done = member == cell;
+                    component.push(member);
+                    if done {
+                        break;
+                    }
+                }
+
+                if component.len() == 1 && !children.contains(&cell) {
+                    self.steps.push(Step::Cell(cell));
+                } else {
+                    component.sort();
+                    self.steps.push(Step::Cycle(component));
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, cell: &CellId) -> (CellId, Vec<CellId>, usize) {
+        let index = self.index.len();
+        self.index.insert(cell.clone(), index);
+        self.lowlink.insert(cell.clone(), index);
+        self.stack.push(cell.clone());
+        self.on_stack.insert(cell.clone());
+        (cell.clone(), self.graph.dependents(cell, self.sheets), 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn id(sheet: &str, address: &str) -> CellId {
+        let cell = crate::reference::CellRef::parse(address).unwrap();
+        CellId::new(sheet, cell.row, cell.col)
+    }
+
+    fn precedents_of(formula: &str) -> Vec<Precedent> {
+        let resolve = |sheet: Option<&str>| sheet.unwrap_or("Sheet1").to_string();
+        precedents(&parse(formula).unwrap(), &resolve)
+    }
+
+    #[test]
+    fn test_collect_precedents() {
+        let found = precedents_of("A1+SUM(B1:B3,A1)*Jan:Mar!C1+'Q1 Sales'!D4");
+        assert_eq!(found.len(), 4);
+        assert_eq!(found[0], Precedent::Cell(id("Sheet1", "A1")));
+        assert!(matches!(&found[1], Precedent::Range { sheet, .. } if sheet == "Sheet1"));
+        assert!(matches!(&found[2], Precedent::Span { first_sheet, last_sheet, .. }
+            if first_sheet == "Jan" && last_sheet == "Mar"));
+        assert_eq!(found[3], Precedent::Cell(id("Q1 Sales", "D4")));
+        assert_eq!(id("Q1 Sales", "D4").to_string(), "'Q1 Sales'!D4");
+    }
+
+    #[test]
+    fn test_recalculation_order_is_topological() {
+        let sheets = vec!["Sheet1".to_string()];
+        let mut graph = DependencyGraph::new();
+
+        // C1 = A1 + B1, B1 = A1 * 2, D1 = SUM(B1:C1)
+        graph.add(&id("Sheet1", "C1"), &precedents_of("A1+B1"));
+        graph.add(&id("Sheet1", "B1"), &precedents_of("A1*2"));
+        graph.add(&id("Sheet1", "D1"), &precedents_of("SUM(B1:C1)"));
+
+        let order = graph.recalculation_order(&graph.dependents(&id("Sheet1", "A1"), &sheets), &sheets);
+        assert_eq!(
+            order,
+            vec![
+                Step::Cell(id("Sheet1", "B1")),
+                Step::Cell(id("Sheet1", "C1")),
+                Step::Cell(id("Sheet1", "D1")),
+            ]
+        );
+
+        graph.remove(&id("Sheet1", "D1"), &precedents_of("SUM(B1:C1)"));
+        assert!(graph.dependents(&id("Sheet1", "C1"), &sheets).is_empty());
+    }
+
+    #[test]
+    fn test_cycles_are_grouped() {
+        let sheets = vec!["Sheet1".to_string()];
+        let mut graph = DependencyGraph::new();
+
+        // A1 = B1, B1 = C1, C1 = A1 + E1, D1 = C1, E1 = E1
+        graph.add(&id("Sheet1", "A1"), &precedents_of("B1"));
+        graph.add(&id("Sheet1", "B1"), &precedents_of("C1"));
+        graph.add(&id("Sheet1", "C1"), &precedents_of("A1+E1"));
+        graph.add(&id("Sheet1", "D1"), &precedents_of("C1"));
+        graph.add(&id("Sheet1", "E1"), &precedents_of("E1"));
+
+        let cycle = vec![id("Sheet1", "A1"), id("Sheet1", "B1"), id("Sheet1", "C1")];
+        assert_eq!(
+            graph.recalculation_order(&[id("Sheet1", "E1")], &sheets),
+            vec![
+                Step::Cycle(vec![id("Sheet1", "E1")]),
+                Step::Cycle(cycle.clone()),
+                Step::Cell(id("Sheet1", "D1")),
+            ]
+        );
+
+        let path = graph.cycle_path(&cycle, &id("Sheet1", "A1"), &sheets);
+        let path: Vec<String> = path.iter().map(|cell| cell.to_string()).collect();
+        assert_eq!(path, ["Sheet1!A1", "Sheet1!B1", "Sheet1!C1", "Sheet1!A1"]);
+    }
+
+    #[test]
+    fn test_spans_follow_tab_order() {
+        let mut sheets = vec!["Jan".to_string(), "Mar".to_string(), "Summary".to_string()];
+        let mut graph = DependencyGraph::new();
+        graph.add(&id("Summary", "A1"), &precedents_of("SUM(Jan:Mar!B5)"));
+
+        assert!(graph.dependents(&id("Feb", "B5"), &sheets).is_empty());
+        sheets.insert(1, "Feb".to_string());
+        assert_eq!(graph.dependents(&id("Feb", "B5"), &sheets), vec![id("Summary", "A1")]);
+        assert!(graph.dependents(&id("Summary", "B5"), &sheets).is_empty());
+    }
+
+    #[test]
+    fn test_ranges_are_found_whatever_their_size() {
+        let sheets = vec!["Sheet1".to_string(), "Sheet2".to_string()];
+        let mut graph = DependencyGraph::new();
+        let formulas = ["SUM(A:A)", "SUM(3:3)", "SUM(B2:C3)", "SUM(A1:XFD1048576)", "SUM(Sheet1:Sheet2!C1:C4)", "SUM(D4:D5)"];
+        for (row, formula) in (1..).zip(formulas) {
+            graph.add(&CellId::new("Other", row, 1), &precedents_of(formula));
+        }
+        let readers = |graph: &DependencyGraph, sheet: &str, address: &str| -> Vec<i32> {
+            graph.dependents(&id(sheet, address), &sheets).iter().map(|cell| cell.row).collect()
+        };
+
+        assert_eq!(readers(&graph, "Sheet1", "A3"), [1, 2, 4]);
+        assert_eq!(readers(&graph, "Sheet1", "C3"), [2, 3, 4, 5]);
+        assert_eq!(readers(&graph, "Sheet1", "D5"), [4, 6]);
+        assert_eq!(readers(&graph, "Sheet1", "XFD1048576"), [4]);
+        assert_eq!(readers(&graph, "Sheet2", "C4"), [5]);
+
+        // Ranges nothing reads any more leave the index
+        for (row, formula) in (1..).zip(formulas) {
+            graph.remove(&CellId::new("Other", row, 1), &precedents_of(formula));
+        }
+        assert!(graph.range_dependents.is_empty());
+        assert!(graph.ranges.values().all(|index| index.buckets.is_empty()));
+        assert!(graph.spans.buckets.is_empty());
+    }
+
+    #[test]
+    fn test_spill_ranges_supply_their_readers() {
+        let sheets = vec!["Sheet1".to_string()];
+        let mut graph = DependencyGraph::new();
+
+        // A1 spills into A1:A3; C1 = A2, C2 = SUM(A3:B9), C3 = A1#, C4 = B1
+        graph.add(&id("Sheet1", "C1"), &precedents_of("A2"));
+        graph.add(&id("Sheet1", "C2"), &precedents_of("SUM(A3:B9)"));
+        graph.add(&id("Sheet1", "C3"), &precedents_of("A1#"));
+        graph.add(&id("Sheet1", "C4"), &precedents_of("B1"));
+        assert_eq!(graph.dependents(&id("Sheet1", "A1"), &sheets), vec![id("Sheet1", "C3")]);
+
+        let spill = RangeRef::new(CellRef::parse("A1").unwrap(), CellRef::parse("A3").unwrap());
+        graph.set_spill(&id("Sheet1", "A1"), Some(spill));
+        assert_eq!(
+            graph.dependents(&id("Sheet1", "A1"), &sheets),
+            vec![id("Sheet1", "C1"), id("Sheet1", "C2"), id("Sheet1", "C3")]
+        );
+
+        graph.set_spill(&id("Sheet1", "A1"), None);
+        assert_eq!(graph.dependents(&id("Sheet1", "A1"), &sheets), vec![id("Sheet1", "C3")]);
+    }
+}
@@ -0,0 +1,125 @@
+//! Excel error values
+//!
+//! Errors such as `#DIV/0!` are ordinary values in Excel: they can be typed
+//! into a formula, stored in a cell and passed to functions like `IFERROR`.
+//! Anything that reads an error where it needs a number, text or boolean
+//! produces that same error, so the first error encountered propagates to
+//! the result. Failures of the engine itself (a formula that does not parse,
+//! a sheet that does not exist) are not `ExcelError`s and are reported as
+//! `Err(String)` by the engine API instead.
+//!
+//! Besides Excel's own errors, cells caught in a circular reference hold
+//! `#CIRC!`. Excel shows such cells as 0 with a warning; an explicit error
+//! lets formulas reading them, and the UI, tell them apart.
+
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExcelError {
+    /// `#NULL!`: intersection of ranges that do not intersect
+    Null,
+    /// `#DIV/0!`: division by zero
+    Div0,
+    /// `#VALUE!`: an argument or operand of the wrong type
+    Value,
+    /// `#REF!`: a reference to a cell or sheet that does not exist
+    Ref,
+    /// `#NAME?`: an unknown function or name
+    Name,
+    /// `#NUM!`: a number that is invalid or out of range
+    Num,
+    /// `#N/A`: a value that is not available, e.g. a failed lookup
+    NA,
+    /// `#SPILL!`: an array result that cannot spill into its range
+    Spill,
+    /// `#CALC!`: a calculation the engine cannot perform, e.g. an empty array
+    Calc,
+    /// `#GETTING_DATA`: a value still being fetched
+    GettingData,
+    /// `#CIRC!`: a cell whose formula depends on its own value
+    Circular,
+}
+
+impl ExcelError {
+    /// Every Excel error, in the order `ERROR.TYPE` numbers them. `#CIRC!`
+    /// is not one of them and cannot be typed into a formula.
+    pub const ALL: [ExcelError; 10] = [
+        ExcelError::Null,
+        ExcelError::Div0,
+        ExcelError::Value,
+        ExcelError::Ref,
+        ExcelError::Name,
+        ExcelError::Num,
+        ExcelError::NA,
+        ExcelError::GettingData,
+        ExcelError::Spill,
+        ExcelError::Calc,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExcelError::Null => "#NULL!",
+            ExcelError::Div0 => "#DIV/0!",
+            ExcelError::Value => "#VALUE!",
+            ExcelError::Ref => "#REF!",
+            ExcelError::Name => "#NAME?",
+            ExcelError::Num => "#NUM!",
+            ExcelError::NA => "#N/A",
+            ExcelError::Spill => "#SPILL!",
+            ExcelError::Calc => "#CALC!",
+            ExcelError::GettingData => "#GETTING_DATA",
+            ExcelError::Circular => "#CIRC!",
+        }
+    }
+
+    /// Look an error up by its code, ignoring case as Excel does.
+    pub fn from_code(code: &str) -> Option<ExcelError> {
+        ExcelError::ALL.into_iter().find(|error| error.code().eq_ignore_ascii_case(code))
+    }
+
+    /// The number `ERROR.TYPE` returns for this error, if it has one.
+    pub fn type_number(&self) -> Option<u8> {
+        match self {
+            ExcelError::Null => Some(1),
+            ExcelError::Div0 => Some(2),
+            ExcelError::Value => Some(3),
+            ExcelError::Ref => Some(4),
+            ExcelError::Name => Some(5),
+            ExcelError::Num => Some(6),
+            ExcelError::NA => Some(7),
+            ExcelError::GettingData => Some(8),
+            ExcelError::Spill => Some(9),
+            ExcelError::Calc => Some(14),
+            ExcelError::Circular => None,
+        }
+    }
+}
+
+impl fmt::Display for ExcelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for ExcelError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_round_trip() {
+        for error in ExcelError::ALL {
+            assert_eq!(ExcelError::from_code(error.code()), Some(error));
+        }
+        assert_eq!(ExcelError::from_code("#div/0!"), Some(ExcelError::Div0));
+        assert_eq!(ExcelError::from_code("#OOPS!"), None);
+        assert_eq!(ExcelError::from_code("#CIRC!"), None);
+        assert_eq!(ExcelError::Calc.type_number(), Some(14));
+    }
+}
@@ -0,0 +1,1169 @@
+//! Expression evaluator
+//!
+//! Walks the [`Expr`] tree produced by the parser and computes its value.
+//! Excel errors such as `#DIV/0!` are returned as `Err` and short-circuit the
+//! rest of the evaluation; error values stored in referenced cells are
+//! surfaced the same way. Unqualified cell references are resolved against
+//! the worksheet the formula is evaluated on; `Sheet!A1` references look the
+//! sheet up in the engine and evaluate to `#REF!` when it does not exist.
+//! 3D references (`Jan:Dec!B5`) span every sheet between their endpoints in
+//! the engine's current tab order. Function calls are dispatched through
+//! the engine's [`FunctionRegistry`](crate::functions::FunctionRegistry).
+//!
+//! Names bound by `LET` and lambda parameters live in the evaluator's
+//! [`Scope`]; calling a lambda evaluates its body with a new evaluator whose
+//! scope binds the parameters on top of the scope the lambda captured. Other
+//! names are looked up among the engine's defined names, whose formulas are
+//! evaluated in place of the name.
+//!
+//! Structured references (`Sales[Amount]`) resolve to the cells of the
+//! engine's table they name when evaluated. `[@Amount]` reads the row of the
+//! formula being evaluated, which the evaluator knows when it is created for
+//! a cell with [`Evaluator::at`].
+//!
+//! A spill reference (`B2#`) evaluates to the array spilled from its cell.
+//! The implicit intersection operator `@` reduces a range to the cell in
+//! line with the formula, and an array to its top-left element.
+
+use crate::date::DateSystem;
+use crate::error::ExcelError;
+use crate::functions::{Arg, ArgKind, Cells};
+use crate::grid::Grid;
+use crate::parser::{BinaryOp, Expr, UnaryOp};
+use crate::reference::{CellRef, RangeRef, StructuredRef};
+use crate::scope::{Lambda, Scope};
+use crate::spill;
+use crate::value::{limited_text, CellValue};
+use crate::{IronCalcEngine, Worksheet};
+use std::cmp::Ordering;
+
+/// Lambda calls and defined names nested deeper than this, as in runaway
+/// recursion, evaluate to `#NUM!` rather than exhausting the stack.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+#[derive(Clone)]
+pub struct Evaluator<'a> {
+    engine: &'a IronCalcEngine,
+    worksheet: &'a Worksheet,
+    scope: Scope,
+    // Number of lambda calls and defined names being evaluated
+    depth: usize,
+    // Row and column of the cell whose formula is evaluated
+    position: Option<(i32, i32)>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(engine: &'a IronCalcEngine, worksheet: &'a Worksheet) -> Self {
+        Self {
+            engine,
+            worksheet,
+            scope: Scope::default(),
+            depth: 0,
+            position: None,
+        }
+    }
+
+    /// The evaluator for the formula of the cell at `row` and `col`.
+    pub fn at(self, row: i32, col: i32) -> Self {
+        Evaluator {
+            position: Some((row, col)),
+            ..self
+        }
+    }
+
+    /// An evaluator for expressions that see `name` bound to `value`.
+    pub fn bind(&self, name: &str, value: CellValue) -> Evaluator<'a> {
+        Evaluator {
+            scope: self.scope.bind(name, value),
+            ..*self
+        }
+    }
+
+    /// The workbook's date system, which date functions count days in.
+    pub fn date_system(&self) -> DateSystem {
+        self.engine.date_system
+    }
+
+    /// The names bound where the evaluator is, which a `LAMBDA` captures.
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    /// Call a lambda with argument values, which may be errors.
+    pub fn call(&self, lambda: &Lambda, args: Vec<CellValue>) -> Result<CellValue, ExcelError> {
+        if args.len() != lambda.params.len() {
+            return Err(ExcelError::Value);
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(ExcelError::Num);
+        }
+
+        let scope = lambda
+            .params
+            .iter()
+            .zip(args)
+            .fold(lambda.scope.clone(), |scope, (param, value)| scope.bind(param, value));
+        let evaluator = Evaluator {
+            scope,
+            depth: self.depth + 1,
+            ..*self
+        };
+        evaluator.evaluate(&lambda.body)
+    }
+
+    pub fn evaluate(&self, expr: &Expr) -> Result<CellValue, ExcelError> {
+        match expr {
+            Expr::Number(n) => Ok(CellValue::Number(*n)),
+            Expr::Text(s) => Ok(CellValue::Text(s.clone())),
+            Expr::Boolean(b) => Ok(CellValue::Boolean(*b)),
+            Expr::Error(e) => Err(*e),
+            Expr::Missing => Ok(CellValue::Number(0.0)),
+            // Errors in an array constant are elements like any other
+            Expr::Array(rows) => Ok(CellValue::Array(
+                rows.iter()
+                    .map(|row| row.iter().map(|element| self.evaluate(element).unwrap_or_else(CellValue::Error)).collect())
+                    .collect(),
+            )),
+            Expr::Reference { sheet, cell } => self.evaluate_reference(sheet.as_deref(), cell),
+            Expr::Range { sheet, range } => self.range_array(sheet.as_deref(), *range),
+            // A 3D span has no single rectangular value
+            Expr::Range3D { .. } => Err(ExcelError::Value),
+            Expr::Name(name) => match self.scope.get(name) {
+                Some(value) => scalar_or_array(value),
+                None => {
+                    let (evaluator, expr) = self.defined_name(name)?;
+                    evaluator.evaluate(expr)
+                }
+            },
+            Expr::StructuredRef(reference) => {
+                let (worksheet, range) = self.resolve_structured_ref(reference)?;
+                if range.start == range.end {
+                    self.evaluate_reference(Some(&worksheet.name), &range.start)
+                } else {
+                    self.range_array(Some(&worksheet.name), range)
+                }
+            }
+            Expr::SpillRef { sheet, cell } => {
+                let (worksheet, range) = self.resolve_spill_ref(sheet.as_deref(), cell)?;
+                if range.start == range.end {
+                    self.evaluate_reference(Some(&worksheet.name), &range.start)
+                } else {
+                    self.range_array(Some(&worksheet.name), range)
+                }
+            }
+            Expr::Unary { op, operand } => self.evaluate_unary(*op, operand),
+            Expr::Binary { op, left, right } => self.evaluate_binary(*op, left, right),
+            Expr::Function { name, args } => self.call_function(name, args, |_, arg| self.evaluate(arg)),
+            Expr::Call { callee, args } => {
+                let callee = self.evaluate(callee)?;
+                self.call_value(&callee, args)
+            }
+        }
+    }
+
+    /// The worksheet a reference points at: the formula's own sheet when
+    /// unqualified, `#REF!` when the named sheet does not exist.
+    fn resolve_sheet(&self, sheet: Option<&str>) -> Result<&'a Worksheet, ExcelError> {
+        match sheet {
+            None => Ok(self.worksheet),
+            Some(name) => self.engine.find_worksheet(name).ok_or(ExcelError::Ref),
+        }
+    }
+
+    /// The sheets spanned by a 3D reference, in tab order. The endpoints may
+    /// be given in either order; a missing endpoint is `#REF!`.
+    fn resolve_sheet_span(&self, first: &str, last: &str) -> Result<&'a [Worksheet], ExcelError> {
+        let first = self.engine.find_worksheet_index(first).ok_or(ExcelError::Ref)?;
+        let last = self.engine.find_worksheet_index(last).ok_or(ExcelError::Ref)?;
+        Ok(&self.engine.worksheets[first.min(last)..=first.max(last)])
+    }
+
+    /// The sheet and cells of the table a structured reference points at.
+    /// A table that does not exist is `#REF!`.
+    fn resolve_structured_ref(&self, reference: &StructuredRef) -> Result<(&'a Worksheet, RangeRef), ExcelError> {
+        let table = self
+            .engine
+            .tables
+            .find(reference, &self.worksheet.name, self.position)
+            .ok_or(ExcelError::Ref)?;
+        let worksheet = self.engine.worksheet(&table.sheet).ok_or(ExcelError::Ref)?;
+
+        // `@` only means a row to formulas on the table's own sheet
+        let this_row = self.position.filter(|_| worksheet.name == self.worksheet.name).map(|(row, _)| row);
+        Ok((worksheet, table.resolve(worksheet, reference, this_row)?))
+    }
+
+    /// The sheet and cells of the spill range of the formula in `cell`:
+    /// the formula's own cell when it did not spill. A cell without a
+    /// formula is `#REF!`, and a spill blocked by other cells `#SPILL!`.
+    fn resolve_spill_ref(&self, sheet: Option<&str>, cell: &CellRef) -> Result<(&'a Worksheet, RangeRef), ExcelError> {
+        let worksheet = self.resolve_sheet(sheet)?;
+        match worksheet.spills.get(&(cell.row, cell.col)) {
+            Some(spill) if spill.blocked => Err(ExcelError::Spill),
+            Some(spill) => Ok((worksheet, spill.range)),
+            None => match worksheet.get_cell(cell.row, cell.col) {
+                Some(anchor) if anchor.formula.is_some() => Ok((worksheet, RangeRef::new(*cell, *cell))),
+                _ => Err(ExcelError::Ref),
+            },
+        }
+    }
+
+    /// The sheet and cells of an operand of `@` that is a reference, or
+    /// `None` when it is some other expression.
+    fn intersected_range(&self, expr: &Expr) -> Option<Result<(&'a Worksheet, RangeRef), ExcelError>> {
+        match expr {
+            Expr::Range { sheet, range } => Some(self.resolve_sheet(sheet.as_deref()).map(|worksheet| (worksheet, *range))),
+            Expr::StructuredRef(reference) => Some(self.resolve_structured_ref(reference)),
+            Expr::SpillRef { sheet, cell } => Some(self.resolve_spill_ref(sheet.as_deref(), cell)),
+            _ => None,
+        }
+    }
+
+    fn evaluate_reference(&self, sheet: Option<&str>, reference: &CellRef) -> Result<CellValue, ExcelError> {
+        match self.resolve_sheet(sheet)?.get_cell(reference.row, reference.col) {
+            Some(cell) => scalar(&cell.value),
+            None => Ok(CellValue::Empty),
+        }
+    }
+
+    /// The value of a range used where a value is expected: an array of its
+    /// cells, blanks included. Whole-column and whole-row ranges are clipped
+    /// to the used area of the sheet.
+    fn range_array(&self, sheet: Option<&str>, range: RangeRef) -> Result<CellValue, ExcelError> {
+        let worksheet = self.resolve_sheet(sheet)?;
+        let range = worksheet.value_range(range);
+
+        let rows = (range.start.row..=range.end.row)
+            .map(|row| {
+                (range.start.col..=range.end.col)
+                    .map(|col| worksheet.get_cell(row, col).map(|cell| top_left(&cell.value).clone()).unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        Ok(CellValue::Array(rows))
+    }
+
+    fn evaluate_unary(&self, op: UnaryOp, operand: &Expr) -> Result<CellValue, ExcelError> {
+        // `@` picks a cell of a range by the formula's position, and the
+        // single cell of a one-cell range wherever the formula is
+        if op == UnaryOp::ImplicitIntersection {
+            if let Some(resolved) = self.intersected_range(operand) {
+                let (worksheet, range) = resolved?;
+                let cell = match self.position {
+                    Some((row, col)) => spill::intersect(&range, row, col),
+                    None => Some(range.start).filter(|_| range.start == range.end),
+                };
+                return self.evaluate_reference(Some(&worksheet.name), &cell.ok_or(ExcelError::Value)?);
+            }
+        }
+        apply_unary(op, self.evaluate(operand)?, self.date_system())
+    }
+
+    fn evaluate_binary(&self, op: BinaryOp, left: &Expr, right: &Expr) -> Result<CellValue, ExcelError> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        apply_binary(op, left, right, self.date_system())
+    }
+
+    /// Values of the populated cells in a range, in row-major order. Blank
+    /// cells are skipped, so whole-column ranges cost as much as the cells
+    /// actually present in the sheet.
+    fn range_values(
+        &self,
+        sheet: Option<&str>,
+        range: RangeRef,
+    ) -> Result<impl Iterator<Item = Result<CellValue, ExcelError>> + 'a, ExcelError> {
+        Ok(self
+            .resolve_sheet(sheet)?
+            .cells_in_range(range)
+            .map(|(_, cell)| scalar(&cell.value)))
+    }
+
+    /// Values of the populated cells of a range on each sheet of a 3D span.
+    fn range_values_3d(
+        &self,
+        first_sheet: &str,
+        last_sheet: &str,
+        range: RangeRef,
+    ) -> Result<impl Iterator<Item = Result<CellValue, ExcelError>> + 'a, ExcelError> {
+        Ok(self
+            .resolve_sheet_span(first_sheet, last_sheet)?
+            .iter()
+            .flat_map(move |worksheet| worksheet.cells_in_range(range))
+            .map(|(_, cell)| scalar(&cell.value)))
+    }
+
+    /// Call a function from the engine's registry, after checking the
+    /// number of arguments and preparing each according to its kind.
+    /// `value` gives the values of `Value` arguments by position, which
+    /// compiled formulas take from their lowered arguments.
+    pub fn call_function(
+        &self,
+        name: &str,
+        args: &[Expr],
+        value: impl Fn(usize, &Expr) -> Result<CellValue, ExcelError>,
+    ) -> Result<CellValue, ExcelError> {
+        // Names bound by LET or LAMBDA shadow functions of the same name
+        if let Some(value) = self.scope.get(name) {
+            return self.call_value(value, args);
+        }
+
+        let Some(function) = self.engine.functions.get(name) else {
+            // A defined name holding a LAMBDA is called like a function
+            let (evaluator, expr) = self.defined_name(name)?;
+            return self.call_value(&evaluator.evaluate(expr)?, args);
+        };
+        if !function.accepts(args.len()) {
+            return Err(ExcelError::Value);
+        }
+
+        let mut prepared = Vec::with_capacity(args.len());
+        for (index, arg) in args.iter().enumerate() {
+            prepared.push(match function.arg_kind(index) {
+                ArgKind::Value => Arg::Value(value(index, arg)?),
+                ArgKind::Reference => match self.reference_cells(arg) {
+                    Some(cells) => Arg::Reference(cells),
+                    None => Arg::Direct(self.evaluate(arg)),
+                },
+                ArgKind::Grid => Arg::Grid(self.grid(arg)?),
+                ArgKind::Lazy => Arg::Lazy(arg),
+            });
+        }
+
+        (function.implementation)(self, prepared).and_then(finite)
+    }
+
+    /// A range argument searched where it is stored, or the value of an
+    /// argument that is not a reference.
+    fn grid(&self, expr: &Expr) -> Result<Grid<'a>, ExcelError> {
+        match expr {
+            Expr::Reference { sheet, cell } => {
+                Ok(Grid::cells(self.resolve_sheet(sheet.as_deref())?, RangeRef::new(*cell, *cell)))
+            }
+            Expr::Range { sheet, range } => Ok(Grid::cells(self.resolve_sheet(sheet.as_deref())?, *range)),
+            Expr::StructuredRef(reference) => {
+                let (worksheet, range) = self.resolve_structured_ref(reference)?;
+                Ok(Grid::cells(worksheet, range))
+            }
+            Expr::SpillRef { sheet, cell } => {
+                let (worksheet, range) = self.resolve_spill_ref(sheet.as_deref(), cell)?;
+                Ok(Grid::cells(worksheet, range))
+            }
+            Expr::Name(name) if self.scope.get(name).is_none() => {
+                let (evaluator, expr) = self.defined_name(name)?;
+                evaluator.grid(expr)
+            }
+            _ => Ok(Grid::values(self.evaluate(expr)?)),
+        }
+    }
+
+    /// The formula of the defined name a formula on this sheet sees, with
+    /// the evaluator for it: the name's formula does not see the bindings
+    /// around its use, and counts towards the depth limit since names can
+    /// refer to themselves.
+    fn defined_name(&self, name: &str) -> Result<(Evaluator<'a>, &'a Expr), ExcelError> {
+        let defined = self.engine.names.resolve(name, &self.worksheet.name).ok_or(ExcelError::Name)?;
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(ExcelError::Num);
+        }
+
+        let evaluator = Evaluator {
+            scope: Scope::default(),
+            depth: self.depth + 1,
+            ..*self
+        };
+        Ok((evaluator, defined.formula.expr()))
+    }
+
+    /// Call a value that should be a lambda. Argument errors are passed on
+    /// as values, so that the lambda can test for them.
+    fn call_value(&self, callee: &CellValue, args: &[Expr]) -> Result<CellValue, ExcelError> {
+        let CellValue::Lambda(lambda) = callee else {
+            return Err(ExcelError::Value);
+        };
+
+        let args = args
+            .iter()
+            .map(|arg| self.evaluate(arg).unwrap_or_else(CellValue::Error))
+            .collect();
+        self.call(lambda, args)
+    }
+
+    /// The cells of a reference argument, or `None` when the argument is
+    /// not a reference. A reference to a missing sheet yields `#REF!`.
+    fn reference_cells(&self, expr: &Expr) -> Option<Cells<'a>> {
+        let cells = match expr {
+            Expr::Reference { sheet, cell } => {
+                Ok(Box::new(std::iter::once(self.evaluate_reference(sheet.as_deref(), cell))) as Cells<'a>)
+            }
+            Expr::Range { sheet, range } => {
+                self.range_values(sheet.as_deref(), *range).map(|cells| Box::new(cells) as Cells<'a>)
+            }
+            Expr::Range3D {
+                first_sheet,
+                last_sheet,
+                range,
+            } => self
+                .range_values_3d(first_sheet, last_sheet, *range)
+                .map(|cells| Box::new(cells) as Cells<'a>),
+            Expr::StructuredRef(reference) => self
+                .resolve_structured_ref(reference)
+                .and_then(|(worksheet, range)| self.range_values(Some(&worksheet.name), range))
+                .map(|cells| Box::new(cells) as Cells<'a>),
+            Expr::SpillRef { sheet, cell } => self
+                .resolve_spill_ref(sheet.as_deref(), cell)
+                .and_then(|(worksheet, range)| self.range_values(Some(&worksheet.name), range))
+                .map(|cells| Box::new(cells) as Cells<'a>),
+            // A defined name referring to cells passes them on
+            Expr::Name(name) if self.scope.get(name).is_none() => match self.defined_name(name) {
+                Ok((evaluator, expr)) => return evaluator.reference_cells(expr),
+                Err(_) => return None,
+            },
+            _ => return None,
+        };
+
+        Some(cells.unwrap_or_else(|error| Box::new(std::iter::once(Err(error)))))
+    }
+}
+
+/// Apply a prefix or postfix operator to an evaluated operand, reading
+/// dates written as text in `system`. Operators other than `@` apply to
+/// each element of an array.
+pub fn apply_unary(op: UnaryOp, value: CellValue, system: DateSystem) -> Result<CellValue, ExcelError> {
+    if let CellValue::Array(rows) = value {
+        if op != UnaryOp::ImplicitIntersection {
+            let apply = |element| match element {
+                CellValue::Error(_) => element,
+                _ => apply_unary(op, element, system).unwrap_or_else(CellValue::Error),
+            };
+            return Ok(CellValue::Array(rows.into_iter().map(|row| row.into_iter().map(apply).collect()).collect()));
+        }
+        return scalar(&CellValue::Array(rows));
+    }
+
+    match op {
+        // Unary plus is a no-op in Excel and does not even coerce text
+        UnaryOp::Plus => Ok(value),
+        UnaryOp::Negate => Ok(CellValue::Number(-value.to_number_in(system)?)),
+        UnaryOp::Percent => Ok(CellValue::Number(value.to_number_in(system)? / 100.0)),
+        UnaryOp::ImplicitIntersection => scalar(&value),
+    }
+}
+
+/// Apply a binary operator to evaluated operands, reading dates written as
+/// text in `system`. Results that overflow to infinity are `#NUM!`. With
+/// an array operand the operator applies element by element, see
+/// [`broadcast`].
+pub fn apply_binary(op: BinaryOp, left: CellValue, right: CellValue, system: DateSystem) -> Result<CellValue, ExcelError> {
+    if matches!(left, CellValue::Array(_)) || matches!(right, CellValue::Array(_)) {
+        return Ok(broadcast(&left, &right, |left, right| match (left, right) {
+            (CellValue::Error(error), _) | (_, CellValue::Error(error)) => CellValue::Error(*error),
+            _ => apply_binary(op, left.clone(), right.clone(), system).unwrap_or_else(CellValue::Error),
+        }));
+    }
+
+    let result = match op {
+        BinaryOp::Add => CellValue::Number(left.to_number_in(system)? + right.to_number_in(system)?),
+        BinaryOp::Subtract => CellValue::Number(left.to_number_in(system)? - right.to_number_in(system)?),
+        BinaryOp::Multiply => CellValue::Number(left.to_number_in(system)? * right.to_number_in(system)?),
+        BinaryOp::Divide => {
+            let (numerator, denominator) = (left.to_number_in(system)?, right.to_number_in(system)?);
+            if denominator == 0.0 {
+                return Err(ExcelError::Div0);
+            }
+            CellValue::Number(numerator / denominator)
+        }
+        BinaryOp::Power => CellValue::Number(power(left.to_number_in(system)?, right.to_number_in(system)?)?),
+        BinaryOp::Concat => limited_text(left.to_text()? + &right.to_text()?)?,
+        BinaryOp::Equal => CellValue::Boolean(left.compare(&right) == Ordering::Equal),
+        BinaryOp::NotEqual => CellValue::Boolean(left.compare(&right) != Ordering::Equal),
+        BinaryOp::Less => CellValue::Boolean(left.compare(&right) == Ordering::Less),
+        BinaryOp::LessEqual => CellValue::Boolean(left.compare(&right) != Ordering::Greater),
+        BinaryOp::Greater => CellValue::Boolean(left.compare(&right) == Ordering::Greater),
+        BinaryOp::GreaterEqual => CellValue::Boolean(left.compare(&right) != Ordering::Less),
+    };
+
+    finite(result)
+}
+
+/// Combine two values element by element into an array as large as the
+/// larger of the two in each dimension. A single value, row or column is
+/// repeated to fit; elements past the end of a smaller array are `#N/A`.
+fn broadcast(left: &CellValue, right: &CellValue, combine: impl Fn(&CellValue, &CellValue) -> CellValue) -> CellValue {
+    let ((left_rows, left_cols), (right_rows, right_cols)) = (left.dimensions(), right.dimensions());
+    let (rows, cols) = (left_rows.max(right_rows), left_cols.max(right_cols));
+
+    CellValue::Array(
+        (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .map(|col| match (left.broadcast_at(row, col), right.broadcast_at(row, col)) {
+                        (Some(left), Some(right)) => combine(left, right),
+                        _ => CellValue::Error(ExcelError::NA),
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// The value a formula sees when it reads a cell: stored errors become
+/// `Err` so they propagate, and a cell holding an array result reads as its
+/// top-left element.
+fn scalar(value: &CellValue) -> Result<CellValue, ExcelError> {
+    match value {
+        CellValue::Error(e) => Err(*e),
+        CellValue::Array(rows) => match rows.first().and_then(|row| row.first()) {
+            Some(first) => scalar(first),
+            None => Ok(CellValue::Empty),
+        },
+        _ => Ok(value.clone()),
+    }
+}
+
+/// The element a cell holding an array result shows, which is what ranges
+/// covering the cell read.
+pub fn top_left(value: &CellValue) -> &CellValue {
+    match value {
+        CellValue::Array(rows) => rows.first().and_then(|row| row.first()).map_or(&CellValue::Empty, top_left),
+        _ => value,
+    }
+}
+
+/// The value of a bound name: arrays stay whole, errors become `Err`.
+fn scalar_or_array(value: &CellValue) -> Result<CellValue, ExcelError> {
+    match value {
+        CellValue::Error(e) => Err(*e),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// A computed value as a cell can hold it: numbers that overflowed to
+/// infinity, or are not numbers at all, are `#NUM!`, in arrays element by
+/// element.
+fn finite(value: CellValue) -> Result<CellValue, ExcelError> {
+    match value {
+        CellValue::Number(n) if !n.is_finite() => Err(ExcelError::Num),
+        CellValue::Array(rows) => Ok(CellValue::Array(
+            rows.into_iter()
+                .map(|row| row.into_iter().map(|value| finite(value).unwrap_or_else(CellValue::Error)).collect())
+                .collect(),
+        )),
+        _ => Ok(value),
+    }
+}
+
+/// Excel's `^`: `0^0` and even roots of negative numbers are `#NUM!`, and
+/// a negative power of zero is `#DIV/0!`. Odd roots of negative numbers,
+/// such as `(-8)^(1/3)`, are negative as in Excel.
+fn power(base: f64, exponent: f64) -> Result<f64, ExcelError> {
+    if base == 0.0 {
+        if exponent == 0.0 {
+            return Err(ExcelError::Num);
+        }
+        if exponent < 0.0 {
+            return Err(ExcelError::Div0);
+        }
+    }
+
+    // The exponent is only close to the reciprocal of the root, as 1/3 is
+    // not exact in binary
+    let root = 1.0 / exponent;
+    if base < 0.0 && exponent.fract() != 0.0 && (root - root.round()).abs() < 1e-9 && root.round() % 2.0 != 0.0 {
+        return Ok(-(-base).powf(exponent));
+    }
+
+    let result = base.powf(exponent);
+    if result.is_nan() {
+        return Err(ExcelError::Num);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    /// Evaluate on Sheet1 of an engine whose Sheet1 cells are set to `cells`.
+    fn eval_with(cells: &[(i32, i32, &str)], formula: &str) -> Result<CellValue, ExcelError> {
+        let mut engine = IronCalcEngine::new().unwrap();
+        for (row, col, value) in cells {
+            engine.set_cell_value("Sheet1", *row, *col, CellValue::from_input(value)).unwrap();
+        }
+        eval_in(&engine, formula)
+    }
+
+    fn eval_in(engine: &IronCalcEngine, formula: &str) -> Result<CellValue, ExcelError> {
+        let worksheet = engine.find_worksheet("Sheet1").unwrap();
+        Evaluator::new(engine, worksheet).evaluate(&parse(formula).unwrap())
+    }
+
+    fn eval(formula: &str) -> Result<CellValue, ExcelError> {
+        eval_with(&[], formula)
+    }
+
+    #[test]
+    fn test_complex_formulas() {
+        assert_eq!(
+            eval("IF(SUM(1,2,3)>5,MAX(10,20,30),MIN(1,2,3))"),
+            Ok(CellValue::Number(30.0))
+        );
+        assert_eq!(eval("((10+5)*2-3)/7"), Ok(CellValue::Number(27.0 / 7.0)));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        assert_eq!(eval("1+2+3"), Ok(CellValue::Number(6.0)));
+        assert_eq!(eval("-2^2"), Ok(CellValue::Number(4.0)));
+        assert_eq!(eval("2^3^2"), Ok(CellValue::Number(64.0)));
+        assert_eq!(eval("2^-1"), Ok(CellValue::Number(0.5)));
+        assert_eq!(eval("10+50%"), Ok(CellValue::Number(10.5)));
+        assert_eq!(eval("1+2&\"x\""), Ok(CellValue::Text("3x".to_string())));
+        assert_eq!(eval("1+1=2&\"\""), Ok(CellValue::Boolean(false)));
+        assert_eq!(eval("+\"abc\""), Ok(CellValue::Text("abc".to_string())));
+    }
+
+    #[test]
+    fn test_power_errors() {
+        assert_eq!(eval("0^0"), Err(ExcelError::Num));
+        assert_eq!(eval("0^-1"), Err(ExcelError::Div0));
+        assert_eq!(eval("(-8)^0.5"), Err(ExcelError::Num));
+        assert_eq!(eval("(-8)^(1/3)"), Ok(CellValue::Number(-2.0)));
+        assert_eq!(eval("(-32)^0.2"), Ok(CellValue::Number(-2.0)));
+        assert_eq!(eval("(-8)^(2/3)"), Err(ExcelError::Num));
+        assert_eq!(eval("(-8)^(-1/3)"), Ok(CellValue::Number(-0.5)));
+        assert_eq!(eval("10^400"), Err(ExcelError::Num));
+    }
+
+    #[test]
+    fn test_if_is_lazy() {
+        assert_eq!(eval("IF(1<2,\"yes\",1/0)"), Ok(CellValue::Text("yes".to_string())));
+        assert_eq!(eval("IF(FALSE,1)"), Ok(CellValue::Boolean(false)));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("1/0"), Err(ExcelError::Div0));
+        assert_eq!(eval("1+\"abc\""), Err(ExcelError::Value));
+        assert_eq!(eval("UNKNOWN_FUNC()"), Err(ExcelError::Name));
+        assert_eq!(eval("COUNT(1,\"x\",1/0,TRUE)"), Ok(CellValue::Number(2.0)));
+
+        // Function results that overflow are #NUM!, as operator results are
+        assert_eq!(eval("SUM(1E308,1E308)"), Err(ExcelError::Num));
+        assert_eq!(eval("AVERAGE(1E308,1E308)"), Err(ExcelError::Num));
+        assert_eq!(eval("IFERROR(SUM(1E308,1E308),0)"), Ok(CellValue::Number(0.0)));
+    }
+
+    #[test]
+    fn test_function_arity() {
+        assert_eq!(eval("IF(TRUE)"), Err(ExcelError::Value));
+        assert_eq!(eval("SUM()"), Err(ExcelError::Value));
+        assert_eq!(eval("NA(1)"), Err(ExcelError::Value));
+        assert_eq!(eval("RANDBETWEEN(3,3.5)"), Ok(CellValue::Number(3.0)));
+        assert_eq!(eval("RANDBETWEEN(5,1)"), Err(ExcelError::Num));
+    }
+
+    #[test]
+    fn test_error_propagation() {
+        // The leftmost error wins, whichever operator or function meets it
+        assert_eq!(eval("#N/A+1/0"), Err(ExcelError::NA));
+        assert_eq!(eval("1/0&#REF!"), Err(ExcelError::Div0));
+        assert_eq!(eval("-#NUM!%"), Err(ExcelError::Num));
+        assert_eq!(eval("#NULL!=#NULL!"), Err(ExcelError::Null));
+        assert_eq!(eval("SUM(1,#VALUE!,1/0)"), Err(ExcelError::Value));
+        assert_eq!(eval("IF(#CALC!,1,2)"), Err(ExcelError::Calc));
+        assert_eq!(eval("IF(TRUE,1,#SPILL!)"), Ok(CellValue::Number(1.0)));
+        assert_eq!(eval("NA()"), Err(ExcelError::NA));
+        assert_eq!(eval_with(&[(1, 1, "#GETTING_DATA")], "A1*2"), Err(ExcelError::GettingData));
+    }
+
+    #[test]
+    fn test_error_functions() {
+        assert_eq!(eval("IFERROR(1/0,\"none\")"), Ok(CellValue::Text("none".to_string())));
+        assert_eq!(eval("IFERROR(5,1/0)"), Ok(CellValue::Number(5.0)));
+        assert_eq!(eval("IFNA(NA(),0)"), Ok(CellValue::Number(0.0)));
+        assert_eq!(eval("IFNA(1/0,0)"), Err(ExcelError::Div0));
+        assert_eq!(eval("ISERROR(#REF!)"), Ok(CellValue::Boolean(true)));
+        assert_eq!(eval("ISERR(NA())"), Ok(CellValue::Boolean(false)));
+        assert_eq!(eval("ISNA(NA())"), Ok(CellValue::Boolean(true)));
+        assert_eq!(eval("ISERROR(1)"), Ok(CellValue::Boolean(false)));
+        assert_eq!(eval("ERROR.TYPE(1/0)"), Ok(CellValue::Number(2.0)));
+        assert_eq!(eval("ERROR.TYPE(#CALC!)"), Ok(CellValue::Number(14.0)));
+        assert_eq!(eval("ERROR.TYPE(1)"), Err(ExcelError::NA));
+    }
+
+    #[test]
+    fn test_text_functions() {
+        let text = |s: &str| Ok(CellValue::Text(s.to_string()));
+        let n = |n: f64| Ok(CellValue::Number(n));
+
+        assert_eq!(eval("LEFT(\"héllo\",2)"), text("hé"));
+        assert_eq!(eval("RIGHT(\"abc\")"), text("c"));
+        assert_eq!(eval("MID(\"abcdef\",3,2)"), text("cd"));
+        assert_eq!(eval("MID(\"abc\",5,2)"), text(""));
+        assert_eq!(eval("MID(\"abc\",0,2)"), Err(ExcelError::Value));
+        assert_eq!(eval("LEN(1/4)"), n(4.0));
+        assert_eq!(eval("FIND(\"b\",\"abcb\",3)"), n(4.0));
+        assert_eq!(eval("FIND(\"B\",\"abc\")"), Err(ExcelError::Value));
+        assert_eq!(eval("SEARCH(\"B?\",\"abcb\")"), n(2.0));
+        assert_eq!(eval("SEARCH(\"c*\",\"ABCD\")"), n(3.0));
+        assert_eq!(eval("SUBSTITUTE(\"a-b-c\",\"-\",\"+\")"), text("a+b+c"));
+        assert_eq!(eval("SUBSTITUTE(\"a-b-c\",\"-\",\"+\",2)"), text("a-b+c"));
+        assert_eq!(eval("REPLACE(\"abcdef\",2,3,\"X\")"), text("aXef"));
+        assert_eq!(eval("TRIM(\"  a   b  \")"), text("a b"));
+        assert_eq!(eval("UPPER(\"straße\")"), text("STRAßE"));
+        assert_eq!(eval("LOWER(\"İSTANBUL\")"), text("İstanbul"));
+        assert_eq!(eval_with(&[(1, 1, "  Grüße 😀  ")], "LEN(TRIM(A1))"), n(8.0));
+        assert_eq!(eval_with(&[(1, 1, "  Grüße 😀  ")], "UPPER(LEFT(TRIM(A1),5))"), text("GRÜßE"));
+        assert_eq!(eval("PROPER(\"o'neil 2nd-hand\")"), text("O'Neil 2Nd-Hand"));
+        assert_eq!(eval("REPT(\"ab\",3)"), text("ababab"));
+        assert_eq!(eval("REPT(\"ab\",20000)"), Err(ExcelError::Value));
+        assert_eq!(eval("LEN(REPT(\"b\",32766)&\"a\")"), n(32767.0));
+        assert_eq!(eval("\"a\"&REPT(\"b\",32767)"), Err(ExcelError::Value));
+        assert_eq!(eval("EXACT(\"a\",\"A\")"), Ok(CellValue::Boolean(false)));
+        assert_eq!(eval("CHAR(128)&CHAR(65)"), text("€A"));
+        assert_eq!(eval("CODE(\"€\")"), n(128.0));
+        assert_eq!(eval("CHAR(0)"), Err(ExcelError::Value));
+        assert_eq!(eval("UNICHAR(55357)"), Err(ExcelError::NA));
+        assert_eq!(eval("CLEAN(\"a\"&CHAR(9)&\"b\")"), text("ab"));
+        assert_eq!(eval("CONCAT(\"a\",1,TRUE)"), text("a1TRUE"));
+        assert_eq!(
+            eval_with(&[(1, 1, "x"), (3, 1, "z")], "TEXTJOIN(\", \",TRUE,A1:A3,\"end\")"),
+            text("x, z, end")
+        );
+        assert_eq!(eval_with(&[(1, 1, "x"), (3, 1, "z")], "TEXTJOIN({\"-\",\"+\"},FALSE,A1:A3)"), text("x-+z"));
+        assert_eq!(eval_with(&[(1, 1, "#N/A")], "CONCAT(A1:A2)"), Err(ExcelError::NA));
+    }
+
+    #[test]
+    fn test_text_positions_count_utf16_units() {
+        let text = |s: &str| Ok(CellValue::Text(s.to_string()));
+        let n = |n: f64| Ok(CellValue::Number(n));
+
+        assert_eq!(eval("LEN(\"a😀b\")"), n(4.0));
+        assert_eq!(eval("FIND(\"b\",\"a😀b\")"), n(4.0));
+        assert_eq!(eval("SEARCH(\"B\",\"😀😀b\",2)"), n(5.0));
+        assert_eq!(eval("MID(\"a😀b\",2,2)"), text("😀"));
+        assert_eq!(eval("LEFT(\"😀\",1)"), text("\u{FFFD}"));
+        assert_eq!(eval("UNICODE(\"😀\")"), n(128512.0));
+        assert_eq!(eval("UNICHAR(128512)"), text("😀"));
+        assert_eq!(eval("CODE(\"😀\")"), n(63.0));
+        assert_eq!(eval("REPLACE(\"日本語\",2,1,\"x\")"), text("日x語"));
+    }
+
+    #[test]
+    fn test_text_functions_over_arrays() {
+        let cells = [(1, 1, "apple"), (2, 1, "kiwi")];
+        let n = CellValue::Number;
+        assert_eq!(eval_with(&cells, "LEN(A1:A2)"), Ok(CellValue::Array(vec![vec![n(5.0)], vec![n(4.0)]])));
+        assert_eq!(
+            eval_with(&cells, "LEFT(A1:A2,{1,2})"),
+            Ok(CellValue::Array(vec![
+                vec![CellValue::Text("a".to_string()), CellValue::Text("ap".to_string())],
+                vec![CellValue::Text("k".to_string()), CellValue::Text("ki".to_string())],
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_textbefore_and_textafter() {
+        let text = |s: &str| Ok(CellValue::Text(s.to_string()));
+        assert_eq!(eval("TEXTBEFORE(\"EU-DE-1234\",\"-\")"), text("EU"));
+        assert_eq!(eval("TEXTAFTER(\"EU-DE-1234\",\"-\")"), text("DE-1234"));
+        assert_eq!(eval("TEXTBEFORE(\"EU-DE-1234\",\"-\",2)"), text("EU-DE"));
+        assert_eq!(eval("TEXTAFTER(\"EU-DE-1234\",\"-\",-1)"), text("1234"));
+        assert_eq!(eval("TEXTBEFORE(\"EU-DE-1234\",\"-\",-2)"), text("EU"));
+        assert_eq!(eval("TEXTAFTER(\"EU-DE/1234\",{\"-\",\"/\"},2)"), text("1234"));
+        assert_eq!(eval("TEXTBEFORE(\"Red x Blue\",\"X\")"), Err(ExcelError::NA));
+        assert_eq!(eval("TEXTBEFORE(\"Red x Blue\",\"X\",,1)"), text("Red "));
+        assert_eq!(eval("TEXTAFTER(\"EU-DE\",\"-\",2)"), Err(ExcelError::NA));
+        assert_eq!(eval("TEXTAFTER(\"EU-DE\",\"-\",2,,1)"), text(""));
+        assert_eq!(eval("TEXTBEFORE(\"EU-DE\",\"-\",2,,1)"), text("EU-DE"));
+        assert_eq!(eval("TEXTAFTER(\"EU\",\"-\",-1,,1)"), text("EU"));
+        assert_eq!(eval("TEXTAFTER(\"EU\",\"-\",,,,\"none\")"), text("none"));
+        assert_eq!(eval("TEXTBEFORE(\"abc\",\"\")"), text(""));
+        assert_eq!(eval("TEXTAFTER(\"abc\",\"\",-1)"), text(""));
+        assert_eq!(eval("TEXTBEFORE(\"a-b\",\"-\",0)"), Err(ExcelError::Value));
+        assert_eq!(eval("TEXTBEFORE(\"a-b\",\"-\",4)"), Err(ExcelError::Value));
+        // Instances and offsets count UTF-16 code units, as FIND and MID do
+        assert_eq!(eval("TEXTAFTER(\"a😀b😀c\",\"😀\",2)"), text("c"));
+        assert_eq!(eval("TEXTBEFORE(\"😀-😀\",\"-\")"), text("😀"));
+        assert_eq!(eval("TEXTBEFORE(\"😀😀\",\"-\",3)"), Err(ExcelError::NA));
+        assert_eq!(eval("TEXTBEFORE(\"😀😀\",\"-\",5)"), Err(ExcelError::Value));
+        assert_eq!(eval("TEXTAFTER(\"xÄ😀y\",\"ä😀\",,1)"), text("y"));
+        assert_eq!(
+            eval_with(&[(1, 1, "EU-DE"), (2, 1, "US")], "TEXTBEFORE(A1:A2,\"-\")"),
+            Ok(CellValue::Array(vec![vec![CellValue::Text("EU".to_string())], vec![CellValue::Error(ExcelError::NA)]]))
+        );
+    }
+
+    #[test]
+    fn test_textsplit() {
+        let t = |s: &str| CellValue::Text(s.to_string());
+        let na = CellValue::Error(ExcelError::NA);
+        assert_eq!(eval("TEXTSPLIT(\"EU-DE-1234\",\"-\")"), Ok(CellValue::Array(vec![vec![t("EU"), t("DE"), t("1234")]])));
+        assert_eq!(eval("TEXTSPLIT(\"a😀b\",\"😀\")"), Ok(CellValue::Array(vec![vec![t("a"), t("b")]])));
+        assert_eq!(eval("TEXTSPLIT(\"EU-DE-1234\",,\"-\")"), Ok(CellValue::Array(vec![vec![t("EU")], vec![t("DE")], vec![t("1234")]])));
+        assert_eq!(
+            eval("TEXTSPLIT(\"a=1;b=2;c\",\"=\",\";\")"),
+            Ok(CellValue::Array(vec![vec![t("a"), t("1")], vec![t("b"), t("2")], vec![t("c"), na.clone()]]))
+        );
+        assert_eq!(
+            eval("TEXTSPLIT(\"a=1;c\",\"=\",\";\",,,\"-\")"),
+            Ok(CellValue::Array(vec![vec![t("a"), t("1")], vec![t("c"), t("-")]]))
+        );
+        assert_eq!(eval("TEXTSPLIT(\"a, b,,c\",{\",\",\", \"})"), Ok(CellValue::Array(vec![vec![t("a"), t("b"), t(""), t("c")]])));
+        assert_eq!(eval("TEXTSPLIT(\"a,,c\",\",\",,TRUE)"), Ok(CellValue::Array(vec![vec![t("a"), t("c")]])));
+        assert_eq!(eval("TEXTSPLIT(\"1x2X3\",\"x\")"), Ok(CellValue::Array(vec![vec![t("1"), t("2X3")]])));
+        assert_eq!(eval("TEXTSPLIT(\"1x2X3\",\"x\",,,1)"), Ok(CellValue::Array(vec![vec![t("1"), t("2"), t("3")]])));
+        assert_eq!(eval("TEXTSPLIT(\"a,b\",\",\",,,2)"), Err(ExcelError::Value));
+        assert_eq!(eval("TEXTSPLIT(\"a,b\",\"\")"), Err(ExcelError::Value));
+        assert_eq!(eval("TEXTSPLIT(\"a,b\",)"), Err(ExcelError::Value));
+        assert_eq!(eval("TEXTSPLIT(\",,\",\",\",,TRUE)"), Err(ExcelError::Calc));
+    }
+
+    #[test]
+    fn test_date_functions() {
+        let n = |n: f64| Ok(CellValue::Number(n));
+        assert_eq!(eval("DATE(2024,1,15)"), n(45306.0));
+        assert_eq!(eval("DATE(2024,14,0)"), n(45688.0));
+        assert_eq!(eval("DATE(100,1,1)"), n(36526.0));
+        assert_eq!(eval("DATE(1900,2,29)"), n(60.0));
+        assert_eq!(eval("DATE(-1,1,1)"), Err(ExcelError::Num));
+        assert_eq!(eval("DATE(10000,1,1)"), Err(ExcelError::Num));
+        assert_eq!(eval("DATE(1E18,1,1)"), Err(ExcelError::Num));
+        assert_eq!(eval("DATE(2024,1E300,1)"), Err(ExcelError::Num));
+        assert_eq!(eval("DATE(2024,-1E300,1)"), Err(ExcelError::Num));
+        assert_eq!(eval("DATE(2024,1,1E300)"), Err(ExcelError::Num));
+        assert_eq!(eval("DATE(2024,1,-1E300)"), Err(ExcelError::Num));
+        assert_eq!(eval("TIME(12,0,0)"), n(0.5));
+        assert_eq!(eval("TIME(1,-30,0)"), n(1.0 / 48.0));
+        assert_eq!(eval("TIME(25,0,0)"), n(1.0 / 24.0));
+        assert_eq!(eval("TIME(0,-1,0)"), Err(ExcelError::Num));
+        assert_eq!(eval("TIME(32767,0,0)"), n(7.0 / 24.0));
+        assert_eq!(eval("TIME(32768,0,0)"), Err(ExcelError::Num));
+        assert_eq!(eval("TIME(1E300,0,0)"), Err(ExcelError::Num));
+        assert_eq!(eval("TIME(0,-1E300,0)"), Err(ExcelError::Num));
+
+        assert_eq!(eval("YEAR(45306)+MONTH(45306)+DAY(45306)"), n(2040.0));
+        assert_eq!(eval("MONTH(60)*100+DAY(60)"), n(229.0));
+        assert_eq!(eval("DAY(0)"), n(0.0));
+        assert_eq!(eval("YEAR(\"2024-01-15\")"), n(2024.0));
+        assert_eq!(eval("YEAR(-1)"), Err(ExcelError::Num));
+        assert_eq!(eval("YEAR(\"soon\")"), Err(ExcelError::Value));
+        assert_eq!(eval("HOUR(45306.75)"), n(18.0));
+        assert_eq!(eval("MINUTE(TIME(1,2,3))*100+SECOND(TIME(1,2,3))"), n(203.0));
+        assert_eq!(eval("HOUR(\"2:30 PM\")"), n(14.0));
+        assert_eq!(eval("HOUR(0.999999)"), n(0.0));
+
+        assert_eq!(eval("EDATE(DATE(2024,1,31),1)"), n(45351.0));
+        assert_eq!(eval("EDATE(DATE(2024,3,31),-1)"), n(45351.0));
+        assert_eq!(eval("EOMONTH(45306,1)"), n(45351.0));
+        assert_eq!(eval("EOMONTH(45306,-1)"), n(45291.0));
+        assert_eq!(eval("EDATE(45306,-1500000)"), Err(ExcelError::Num));
+        assert_eq!(eval("EDATE(1,1E300)"), Err(ExcelError::Num));
+        assert_eq!(eval("EOMONTH(1,-1E300)"), Err(ExcelError::Num));
+    }
+
+    #[test]
+    fn test_weekdays_and_weeks() {
+        let n = |n: f64| Ok(CellValue::Number(n));
+        // 2024-01-15 is a Monday; serial 1 is a Sunday in Excel's calendar
+        assert_eq!(eval("WEEKDAY(45306)"), n(2.0));
+        assert_eq!(eval("WEEKDAY(45306,2)"), n(1.0));
+        assert_eq!(eval("WEEKDAY(45306,3)"), n(0.0));
+        assert_eq!(eval("WEEKDAY(45306,11)"), n(1.0));
+        assert_eq!(eval("WEEKDAY(45306,17)"), n(2.0));
+        assert_eq!(eval("WEEKDAY(1)"), n(1.0));
+        assert_eq!(eval("WEEKDAY(45306,4)"), Err(ExcelError::Num));
+
+        assert_eq!(eval("WEEKNUM(DATE(2024,1,6))"), n(1.0));
+        assert_eq!(eval("WEEKNUM(DATE(2024,1,7))"), n(2.0));
+        assert_eq!(eval("WEEKNUM(DATE(2024,1,7),2)"), n(1.0));
+        assert_eq!(eval("WEEKNUM(DATE(2024,1,8),2)"), n(2.0));
+        assert_eq!(eval("WEEKNUM(DATE(2021,1,1),21)"), n(53.0));
+        assert_eq!(eval("ISOWEEKNUM(DATE(2021,1,1))"), n(53.0));
+        assert_eq!(eval("ISOWEEKNUM(DATE(2024,12,30))"), n(1.0));
+        assert_eq!(eval("ISOWEEKNUM(45306)"), n(3.0));
+    }
+
+    #[test]
+    fn test_date_differences() {
+        let n = |n: f64| Ok(CellValue::Number(n));
+        let cells = [(1, 1, "2020-03-15"), (2, 1, "2024-01-10")];
+        let datedif = |unit: &str| eval_with(&cells, &format!("DATEDIF(DATEVALUE(A1),DATEVALUE(A2),\"{}\")", unit));
+        assert_eq!(datedif("Y"), n(3.0));
+        assert_eq!(datedif("M"), n(45.0));
+        assert_eq!(datedif("ym"), n(9.0));
+        assert_eq!(datedif("D"), eval_with(&cells, "DAYS(A2,A1)"));
+        assert_eq!(datedif("MD"), n(26.0));
+        assert_eq!(datedif("YD"), n(301.0));
+        assert_eq!(datedif("W"), Err(ExcelError::Num));
+        assert_eq!(eval("DATEDIF(2,1,\"D\")"), Err(ExcelError::Num));
+
+        assert_eq!(eval("DAYS(DATE(2024,3,1),DATE(2024,2,1))"), n(29.0));
+        assert_eq!(eval("DAYS(\"2024-03-01\",\"2024-02-01\")"), n(29.0));
+        assert_eq!(eval("DAYS360(DATE(2024,1,31),DATE(2024,3,31))"), n(60.0));
+        assert_eq!(eval("DAYS360(DATE(2024,2,29),DATE(2024,3,31))"), n(30.0));
+        assert_eq!(eval("DAYS360(DATE(2024,2,29),DATE(2024,3,31),TRUE)"), n(31.0));
+
+        assert_eq!(eval("YEARFRAC(DATE(2024,1,1),DATE(2024,7,1))"), n(0.5));
+        assert_eq!(eval("YEARFRAC(DATE(2024,7,1),DATE(2024,1,1))"), n(0.5));
+        assert_eq!(eval("YEARFRAC(DATE(2024,1,1),DATE(2024,7,1),1)"), n(182.0 / 366.0));
+        assert_eq!(eval("YEARFRAC(DATE(2023,1,1),DATE(2025,1,1),1)"), n(731.0 / (1096.0 / 3.0)));
+        assert_eq!(eval("YEARFRAC(DATE(2024,1,1),DATE(2024,7,1),2)"), n(182.0 / 360.0));
+        assert_eq!(eval("YEARFRAC(DATE(2024,1,1),DATE(2024,7,1),3)"), n(182.0 / 365.0));
+        assert_eq!(eval("YEARFRAC(1,2,5)"), Err(ExcelError::Num));
+    }
+
+    #[test]
+    fn test_datevalue_and_timevalue() {
+        let n = |n: f64| Ok(CellValue::Number(n));
+        assert_eq!(eval("DATEVALUE(\"2024-01-15\")"), n(45306.0));
+        assert_eq!(eval("DATEVALUE(\"January 15, 2024\")"), n(45306.0));
+        assert_eq!(eval("DATEVALUE(\"15-Jan-2024 10:00\")"), n(45306.0));
+        assert_eq!(eval("DATEVALUE(45306)"), Err(ExcelError::Value));
+        assert_eq!(eval("DATEVALUE(\"2/30/2024\")"), Err(ExcelError::Value));
+
+        // Operators read dates and times written as text too
+        assert_eq!(eval("\"2024-01-15\"+1"), n(45307.0));
+        assert_eq!(eval("\"12:00\"*2"), n(1.0));
+        assert_eq!(eval("-\"1/15/2024\""), n(-45306.0));
+        assert_eq!(eval("\"2/30/2024\"+1"), Err(ExcelError::Value));
+        assert_eq!(eval("TIMEVALUE(\"18:00\")"), n(0.75));
+        assert_eq!(eval("TIMEVALUE(\"2024-01-15 6:00 PM\")"), n(0.75));
+        assert_eq!(eval("TIMEVALUE(\"2024-01-15\")"), n(0.0));
+        assert_eq!(eval("TIMEVALUE(\"6 o'clock\")"), Err(ExcelError::Value));
+        assert_eq!(
+            eval("YEAR({45306,\"2/29/2028\"})"),
+            Ok(CellValue::Array(vec![vec![CellValue::Number(2024.0), CellValue::Number(2028.0)]]))
+        );
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(eval("LET(x,2,y,x*3,x+y)"), Ok(CellValue::Number(8.0)));
+        assert_eq!(eval("LET(x,1,LET(X,2,x)+x)"), Ok(CellValue::Number(3.0)));
+        assert_eq!(eval("LET(unused,1/0,5)"), Ok(CellValue::Number(5.0)));
+        assert_eq!(eval("LET(bad,1/0,bad+1)"), Err(ExcelError::Div0));
+        assert_eq!(eval_with(&[(1, 1, "2"), (2, 1, "3")], "LET(r,A1:A2,SUM(r)*COUNT(r))"), Ok(CellValue::Number(10.0)));
+        assert_eq!(eval("LET(x,1,y)"), Err(ExcelError::Name));
+        assert_eq!(eval("LET(x,1,y,2)"), Err(ExcelError::Value));
+        assert_eq!(eval("LET(1,2,3)"), Err(ExcelError::Value));
+    }
+
+    #[test]
+    fn test_lambda() {
+        assert_eq!(eval("LAMBDA(x,y,x*y)(3,4)"), Ok(CellValue::Number(12.0)));
+        assert_eq!(eval("LET(sq,LAMBDA(n,n*n),sq(5)+SQ(1))"), Ok(CellValue::Number(26.0)));
+        // Lambdas close over the names bound where they are created
+        assert_eq!(eval("LET(k,10,add,LAMBDA(x,x+k),k,1,add(k))"), Ok(CellValue::Number(11.0)));
+        assert_eq!(eval("LAMBDA(x,LAMBDA(y,x-y))(5)(2)"), Ok(CellValue::Number(3.0)));
+        assert_eq!(eval("LAMBDA(x,ISERROR(x))(1/0)"), Ok(CellValue::Boolean(true)));
+        assert_eq!(eval("LAMBDA(x,x)(1,2)"), Err(ExcelError::Value));
+        assert_eq!(eval("LAMBDA(x,x,1)"), Err(ExcelError::Value));
+        assert_eq!(eval("LET(x,1,x(2))"), Err(ExcelError::Value));
+        assert_eq!(eval("LAMBDA(x,x)+1"), Err(ExcelError::Calc));
+    }
+
+    #[test]
+    fn test_lambda_helpers() {
+        let cells = [(1, 1, "1"), (2, 1, "2"), (3, 1, "3"), (1, 2, "10"), (2, 2, "20"), (3, 2, "x")];
+        let column = |values: &[f64]| {
+            Ok(CellValue::Array(values.iter().map(|n| vec![CellValue::Number(*n)]).collect()))
+        };
+
+        assert_eq!(eval_with(&cells, "MAP(A1:A3,LAMBDA(v,v*2))"), column(&[2.0, 4.0, 6.0]));
+        assert_eq!(eval_with(&cells, "SUM(MAP(A1:A3,B1:B2,LAMBDA(a,b,a+b)))"), Err(ExcelError::NA));
+        assert_eq!(eval_with(&cells, "MAP(A1:A2,B1:B2,LAMBDA(a,b,a*b))"), column(&[10.0, 40.0]));
+        assert_eq!(eval_with(&cells, "REDUCE(0,A1:A3,LAMBDA(acc,v,acc+v))"), Ok(CellValue::Number(6.0)));
+        assert_eq!(eval_with(&cells, "REDUCE(,A1:B3,LAMBDA(acc,v,acc+v))"), Err(ExcelError::Value));
+        assert_eq!(eval_with(&cells, "SCAN(1,A1:A3,LAMBDA(acc,v,acc*v))"), column(&[1.0, 2.0, 6.0]));
+        assert_eq!(eval_with(&cells, "BYROW(A1:B2,LAMBDA(r,SUM(r)))"), column(&[11.0, 22.0]));
+        assert_eq!(
+            eval_with(&cells, "BYCOL(A1:B3,LAMBDA(c,COUNT(c)))"),
+            Ok(CellValue::Array(vec![vec![CellValue::Number(3.0), CellValue::Number(2.0)]]))
+        );
+        assert_eq!(
+            eval("MAKEARRAY(2,3,LAMBDA(r,c,r*c))"),
+            Ok(CellValue::Array(vec![
+                vec![CellValue::Number(1.0), CellValue::Number(2.0), CellValue::Number(3.0)],
+                vec![CellValue::Number(2.0), CellValue::Number(4.0), CellValue::Number(6.0)],
+            ]))
+        );
+        assert_eq!(
+            eval("BYROW(MAKEARRAY(1,2,LAMBDA(r,c,c)),LAMBDA(r,r))"),
+            Ok(CellValue::Array(vec![vec![CellValue::Error(ExcelError::Calc)]]))
+        );
+        assert_eq!(eval("MAKEARRAY(0,1,LAMBDA(r,c,1))"), Err(ExcelError::Value));
+        assert_eq!(eval("MAP(1,LAMBDA(a,b,a))"), Err(ExcelError::Value));
+        assert_eq!(eval("MAP(1,2)"), Err(ExcelError::Value));
+    }
+
+    #[test]
+    fn test_recursion_depth() {
+        // A lambda recurses by being passed to itself
+        let countdown = "LET(f,LAMBDA(self,n,IF(n=0,\"done\",self(self,n-1))),f(f,{}))";
+        let depth = |n: usize| eval(&countdown.replace("{}", &n.to_string()));
+
+        assert_eq!(depth(MAX_CALL_DEPTH - 1), Ok(CellValue::Text("done".to_string())));
+        assert_eq!(depth(MAX_CALL_DEPTH), Err(ExcelError::Num));
+        assert_eq!(depth(100_000), Err(ExcelError::Num));
+    }
+
+    #[test]
+    fn test_comparisons_across_types() {
+        assert_eq!(eval("\"abc\"=\"ABC\""), Ok(CellValue::Boolean(true)));
+        assert_eq!(eval("\"1\"=1"), Ok(CellValue::Boolean(false)));
+        assert_eq!(eval("TRUE>\"z\""), Ok(CellValue::Boolean(true)));
+    }
+
+    #[test]
+    fn test_cell_references() {
+        let cells = [(1, 1, "6"), (2, 2, "7"), (3, 1, "true"), (4, 1, "#N/A")];
+
+        assert_eq!(eval_with(&cells, "A1*B2"), Ok(CellValue::Number(42.0)));
+        assert_eq!(eval_with(&cells, "$A$1+A3"), Ok(CellValue::Number(7.0)));
+        assert_eq!(eval_with(&cells, "IF(A3,\"on\",\"off\")"), Ok(CellValue::Text("on".to_string())));
+        assert_eq!(eval_with(&cells, "A4"), Err(ExcelError::NA));
+    }
+
+    #[test]
+    fn test_empty_cells() {
+        assert_eq!(eval("Z9"), Ok(CellValue::Empty));
+        assert_eq!(eval("Z9+1"), Ok(CellValue::Number(1.0)));
+        assert_eq!(eval("Z9&\"x\""), Ok(CellValue::Text("x".to_string())));
+        assert_eq!(eval("Z9=0"), Ok(CellValue::Boolean(true)));
+        assert_eq!(eval("Z9=\"\""), Ok(CellValue::Boolean(true)));
+        assert_eq!(eval("Z9=FALSE"), Ok(CellValue::Boolean(true)));
+    }
+
+    #[test]
+    fn test_range_aggregates() {
+        let cells = [
+            (1, 1, "10"),
+            (2, 1, "20"),
+            (3, 1, "text"),
+            (4, 1, "TRUE"),
+            (6, 1, "30"),
+            (2, 2, "5"),
+            (1, 3, "100"),
+        ];
+
+        assert_eq!(eval_with(&cells, "SUM(A1:A10)"), Ok(CellValue::Number(60.0)));
+        assert_eq!(eval_with(&cells, "AVERAGE(A1:A6)"), Ok(CellValue::Number(20.0)));
+        assert_eq!(eval_with(&cells, "COUNT(A1:B6)"), Ok(CellValue::Number(4.0)));
+        assert_eq!(eval_with(&cells, "MAX(A:A)"), Ok(CellValue::Number(30.0)));
+        assert_eq!(eval_with(&cells, "MIN(2:2)"), Ok(CellValue::Number(5.0)));
+        assert_eq!(eval_with(&cells, "SUM(A1:B2,1:1)"), Ok(CellValue::Number(145.0)));
+        assert_eq!(eval_with(&cells, "SUM(A3)"), Ok(CellValue::Number(0.0)));
+        assert_eq!(eval_with(&cells, "SUM(\"3\",TRUE)"), Ok(CellValue::Number(4.0)));
+        assert_eq!(eval_with(&cells, "MAX(D:D)"), Ok(CellValue::Number(0.0)));
+        assert_eq!(eval_with(&cells, "AVERAGE(D1:D5)"), Err(ExcelError::Div0));
+    }
+
+    #[test]
+    fn test_range_errors_propagate() {
+        let cells = [(1, 1, "1"), (2, 1, "#REF!")];
+
+        assert_eq!(eval_with(&cells, "SUM(A:A)"), Err(ExcelError::Ref));
+        assert_eq!(eval_with(&cells, "COUNT(A:A)"), Ok(CellValue::Number(1.0)));
+        assert_eq!(
+            eval_with(&cells, "A1:A2"),
+            Ok(CellValue::Array(vec![
+                vec![CellValue::Number(1.0)],
+                vec![CellValue::Error(ExcelError::Ref)],
+            ]))
+        );
+        // Operators apply to each element, leaving errors in place
+        assert_eq!(
+            eval_with(&cells, "A1:A2+1"),
+            Ok(CellValue::Array(vec![
+                vec![CellValue::Number(2.0)],
+                vec![CellValue::Error(ExcelError::Ref)],
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_array_operations() {
+        let cells = [(1, 1, "1"), (2, 1, "2"), (3, 1, "3"), (1, 2, "10"), (2, 2, "x"), (3, 2, "30")];
+        let numbers = |rows: &[&[f64]]| {
+            CellValue::Array(rows.iter().map(|row| row.iter().map(|n| CellValue::Number(*n)).collect()).collect())
+        };
+
+        assert_eq!(eval_with(&[], "{1,2;3,4}*10"), Ok(numbers(&[&[10.0, 20.0], &[30.0, 40.0]])));
+        assert_eq!(eval_with(&[], "{1,2}+{10;20}"), Ok(numbers(&[&[11.0, 12.0], &[21.0, 22.0]])));
+        assert_eq!(eval_with(&[], "-{1,-2}%"), Ok(numbers(&[&[-0.01, 0.02]])));
+        assert_eq!(
+            eval_with(&cells, "A1:A3*B1:B3"),
+            Ok(CellValue::Array(vec![
+                vec![CellValue::Number(10.0)],
+                vec![CellValue::Error(ExcelError::Value)],
+                vec![CellValue::Number(90.0)],
+            ]))
+        );
+        assert_eq!(
+            eval_with(&[], "{1,2,3}>{2,2}"),
+            Ok(CellValue::Array(vec![vec![
+                CellValue::Boolean(false),
+                CellValue::Boolean(false),
+                CellValue::Error(ExcelError::NA),
+            ]]))
+        );
+        assert_eq!(eval_with(&[], "SUM({1,2;3,#N/A})"), Err(ExcelError::NA));
+        assert_eq!(eval_with(&[], "@{5,6}"), Ok(CellValue::Number(5.0)));
+
+        // Ranges keep their size past the last populated row; only whole
+        // columns and rows are cut there
+        let sparse = [(1, 1, "1"), (2, 1, "2")];
+        assert_eq!(eval_with(&sparse, "A1:A3*{1;2;3}"), Ok(numbers(&[&[1.0], &[4.0], &[0.0]])));
+        assert_eq!(eval_with(&sparse, "SUMPRODUCT(A1:A3,{1;2;3})"), Ok(CellValue::Number(5.0)));
+        assert_eq!(eval_with(&sparse, "A:A*2"), Ok(numbers(&[&[2.0], &[4.0]])));
+        assert_eq!(eval_with(&sparse, "TEXTJOIN(\"-\",FALSE,A1:A3)"), Ok(CellValue::Text("1-2-".to_string())));
+        assert_eq!(eval_with(&sparse, "INDEX(A1:B4,0,1)*1"), Ok(numbers(&[&[1.0], &[2.0], &[0.0], &[0.0]])));
+    }
+
+    #[test]
+    fn test_cross_sheet_references() {
+        let mut engine = IronCalcEngine::new().unwrap();
+        engine.create_sheet("Q1 Sales").unwrap();
+        engine.create_sheet("O'Brien").unwrap();
+        engine.set_cell_value("Sheet1", 1, 1, CellValue::Number(1.0)).unwrap();
+        for row in 2..=20 {
+            engine.set_cell_value("Q1 Sales", row, 2, CellValue::Number(10.0)).unwrap();
+        }
+        engine.set_cell_value("O'Brien", 1, 1, CellValue::Number(7.0)).unwrap();
+
+        assert!(matches!(eval_in(&engine, "'Q1 Sales'!B2:B20"), Ok(CellValue::Array(rows)) if rows.len() == 19));
+        assert_eq!(eval_in(&engine, "SUM('Q1 Sales'!B2:B20)"), Ok(CellValue::Number(190.0)));
+        assert_eq!(eval_in(&engine, "'O''Brien'!A1*A1"), Ok(CellValue::Number(7.0)));
+        assert_eq!(eval_in(&engine, "sheet1!A1"), Ok(CellValue::Number(1.0)));
+        assert_eq!(eval_in(&engine, "Missing!A1"), Err(ExcelError::Ref));
+        assert_eq!(eval_in(&engine, "SUM(Missing!A1:A3)"), Err(ExcelError::Ref));
+        assert_eq!(eval_in(&engine, "COUNT(Missing!A1:A3)"), Ok(CellValue::Number(0.0)));
+    }
+
+    #[test]
+    fn test_3d_references() {
+        let mut engine = IronCalcEngine::new().unwrap();
+        for (index, month) in ["Jan", "Feb", "Mar"].iter().enumerate() {
+            engine.create_sheet(month).unwrap();
+            engine.set_cell_value(month, 5, 2, CellValue::Number(((index + 1) * 10) as f64)).unwrap();
+        }
+
+        assert_eq!(eval_in(&engine, "SUM(Jan:Mar!B5)"), Ok(CellValue::Number(60.0)));
+        assert_eq!(eval_in(&engine, "SUM(mar:Jan!B1:B10)"), Ok(CellValue::Number(60.0)));
+        assert_eq!(eval_in(&engine, "AVERAGE(Feb:Mar!B5)"), Ok(CellValue::Number(25.0)));
+        assert_eq!(eval_in(&engine, "COUNT(Jan:Feb!B:B)"), Ok(CellValue::Number(2.0)));
+        assert_eq!(eval_in(&engine, "Jan:Mar!B5"), Err(ExcelError::Value));
+        assert_eq!(eval_in(&engine, "SUM(Jan:Dec!B5)"), Err(ExcelError::Ref));
+
+        // A sheet inserted between the endpoints joins the span
+        engine.insert_sheet("Extra", 2).unwrap();
+        engine.set_cell_value("Extra", 5, 2, CellValue::Number(100.0)).unwrap();
+        assert_eq!(eval_in(&engine, "SUM(Jan:Mar!B5)"), Ok(CellValue::Number(160.0)));
+        assert_eq!(eval_in(&engine, "SUM(Feb:Mar!B5)"), Ok(CellValue::Number(50.0)));
+    }
+}
@@ -0,0 +1,245 @@
+//! Compiled formulas
+//!
+//! A [`Formula`] is parsed once, when it is entered, and then evaluated as
+//! often as recalculation needs without going back to the text. Before it is
+//! compiled, subexpressions built only from literals are folded, so
+//! `=A1*(1+5%)` becomes `A1*1.05`; what remains is the work that depends on
+//! cells.
+//!
+//! Compiling lowers the folded tree to a tree of closures, one per operator,
+//! literal or function call, so evaluating a formula calls straight into the
+//! operator and function implementations instead of matching on the
+//! expression at every node. Expressions whose value depends on where they
+//! are evaluated, such as references and names, are left to the
+//! [`Evaluator`], as are the arguments functions take as references or
+//! evaluate lazily. The folded expression is kept alongside for dependency
+//! analysis and for defined names, which are evaluated in place.
+//!
+//! Cells share a compiled formula through an `Rc`, and the engine keeps the
+//! most recently compiled formulas keyed by their text, so a formula column
+//! entering the same text on thousands of rows parses it once.
+
+use crate::date::{self, DateSystem};
+use crate::error::ExcelError;
+use crate::evaluator::{apply_binary, apply_unary, Evaluator};
+use crate::functions::FunctionRegistry;
+use crate::parser::{self, Expr, UnaryOp};
+use crate::value::CellValue;
+use std::fmt;
+
+/// A lowered expression, evaluated by calling it.
+type Node = Box<dyn Fn(&Evaluator) -> Result<CellValue, ExcelError>>;
+
+pub struct Formula {
+    text: String,
+    expr: Expr,
+    volatile: bool,
+    node: Node,
+}
+
+impl fmt::Debug for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Formula")
+            .field("text", &self.text)
+            .field("expr", &self.expr)
+            .field("volatile", &self.volatile)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Formula {
+    /// Parse, fold and lower a formula, with or without its leading `=`,
+    /// looking up the functions it calls in `functions`.
+    pub fn compile(text: &str, functions: &FunctionRegistry) -> Result<Formula, String> {
+        let body = text.strip_prefix('=').unwrap_or(text);
+        let expr = fold(parser::parse(body)?);
+
+        Ok(Formula {
+            text: text.to_string(),
+            volatile: functions.is_volatile(&expr),
+            node: lower(&expr),
+            expr,
+        })
+    }
+
+    /// Evaluate the compiled formula where `evaluator` is.
+    pub fn evaluate(&self, evaluator: &Evaluator) -> Result<CellValue, ExcelError> {
+        (self.node)(evaluator)
+    }
+
+    /// The formula as it was entered.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    /// Whether the formula calls a volatile function such as `RAND`.
+    pub fn is_volatile(&self) -> bool {
+        self.volatile
+    }
+}
+
+/// Replace operators whose operands are all literals by the literal they
+/// evaluate to. An error result becomes an error literal, which evaluates
+/// to the same error, so `IFERROR(1/0,0)` still catches it.
+fn fold(expr: Expr) -> Expr {
+    match expr {
+        Expr::Unary { op, operand } => {
+            let operand = fold(*operand);
+            match literal_value(&operand) {
+                Some(value) if !is_date_text(&value) => into_literal(apply_unary(op, value, DateSystem::default())),
+                _ => Expr::Unary {
+                    op,
+                    operand: Box::new(operand),
+                },
+            }
+        }
+        Expr::Binary { op, left, right } => {
+            let (left, right) = (fold(*left), fold(*right));
+            match (literal_value(&left), literal_value(&right)) {
+                (Some(left), Some(right)) if !is_date_text(&left) && !is_date_text(&right) => {
+                    into_literal(apply_binary(op, left, right, DateSystem::default()))
+                }
+                _ => Expr::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            }
+        }
+        Expr::Function { name, args } => Expr::Function {
+            name,
+            args: args.into_iter().map(fold).collect(),
+        },
+        Expr::Call { callee, args } => Expr::Call {
+            callee: Box::new(fold(*callee)),
+            args: args.into_iter().map(fold).collect(),
+        },
+        expr => expr,
+    }
+}
+
+/// Lower an expression to closures. Operands and the arguments functions
+/// take as values are lowered too; everything else is evaluated by the
+/// evaluator from the expression, as the tree walk would.
+fn lower(expr: &Expr) -> Node {
+    match expr {
+        Expr::Number(n) => {
+            let n = *n;
+            Box::new(move |_| Ok(CellValue::Number(n)))
+        }
+        Expr::Text(s) => {
+            let s = s.clone();
+            Box::new(move |_| Ok(CellValue::Text(s.clone())))
+        }
+        Expr::Boolean(b) => {
+            let b = *b;
+            Box::new(move |_| Ok(CellValue::Boolean(b)))
+        }
+        Expr::Error(error) => {
+            let error = *error;
+            Box::new(move |_| Err(error))
+        }
+        // `@` looks at the expression its operand is, not only its value
+        Expr::Unary { op, operand } if *op != UnaryOp::ImplicitIntersection => {
+            let (op, operand) = (*op, lower(operand));
+            Box::new(move |evaluator| apply_unary(op, operand(evaluator)?, evaluator.date_system()))
+        }
+        Expr::Binary { op, left, right } => {
+            let (op, left, right) = (*op, lower(left), lower(right));
+            Box::new(move |evaluator| apply_binary(op, left(evaluator)?, right(evaluator)?, evaluator.date_system()))
+        }
+        Expr::Function { name, args } => {
+            let (name, args) = (name.clone(), args.clone());
+            let nodes: Vec<Node> = args.iter().map(lower).collect();
+            Box::new(move |evaluator| evaluator.call_function(&name, &args, |index, _| nodes[index](evaluator)))
+        }
+        expr => {
+            let expr = expr.clone();
+            Box::new(move |evaluator| evaluator.evaluate(&expr))
+        }
+    }
+}
+
+/// The value of a literal expression. Error literals are left alone so
+/// that they keep propagating through evaluation.
+fn literal_value(expr: &Expr) -> Option<CellValue> {
+    match expr {
+        Expr::Number(n) => Some(CellValue::Number(*n)),
+        Expr::Text(s) => Some(CellValue::Text(s.clone())),
+        Expr::Boolean(b) => Some(CellValue::Boolean(*b)),
+        _ => None,
+    }
+}
+
+/// Whether `value` is text that operators read as a date or time. Its
+/// serial number depends on the date system of the workbook, so operators
+/// on it are not folded.
+fn is_date_text(value: &CellValue) -> bool {
+    let systems = [DateSystem::Excel1900, DateSystem::Excel1904];
+    matches!(value, CellValue::Text(text) if systems.iter().any(|system| date::parse(*system, text).is_some()))
+}
+
+fn into_literal(result: Result<CellValue, ExcelError>) -> Expr {
+    match result {
+        Ok(CellValue::Number(n)) => Expr::Number(n),
+        Ok(CellValue::Text(s)) => Expr::Text(s),
+        Ok(CellValue::Boolean(b)) => Expr::Boolean(b),
+        Ok(_) => unreachable!("operators on literals produce scalars"),
+        Err(error) => Expr::Error(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IronCalcEngine;
+
+    fn compiled(text: &str) -> Expr {
+        Formula::compile(text, &FunctionRegistry::new()).unwrap().expr
+    }
+
+    #[test]
+    fn test_folds_literal_subexpressions() {
+        assert_eq!(compiled("=1+2*3"), Expr::Number(7.0));
+        assert_eq!(compiled("=\"a\"&1=\"A1\""), Expr::Boolean(true));
+        assert_eq!(compiled("=A1*(1+5%)"), parser::parse("A1*1.05").unwrap());
+        assert_eq!(compiled("=SUM(A1,2^3)"), parser::parse("SUM(A1,8)").unwrap());
+        assert_eq!(compiled("=\"2024-01-15\"+1"), parser::parse("\"2024-01-15\"+1").unwrap());
+    }
+
+    #[test]
+    fn test_folded_errors_stay_errors() {
+        assert_eq!(compiled("=1/0"), Expr::Error(ExcelError::Div0));
+        assert_eq!(compiled("=IFERROR(-\"x\",1)"), parser::parse("IFERROR(#VALUE!,1)").unwrap());
+        assert_eq!(compiled("=#N/A+1"), parser::parse("#N/A+1").unwrap());
+        assert!(Formula::compile("=1+", &FunctionRegistry::new()).is_err());
+    }
+
+    #[test]
+    fn test_lowered_formulas_evaluate_as_their_expression() {
+        let mut engine = IronCalcEngine::new().unwrap();
+        for (row, value) in [(1, "4"), (2, "abc"), (3, "#N/A")] {
+            engine.set_cell_value("Sheet1", row, 1, CellValue::from_input(value)).unwrap();
+        }
+        let worksheet = engine.find_worksheet("Sheet1").unwrap();
+        let evaluator = Evaluator::new(&engine, worksheet).at(5, 2);
+
+        for text in [
+            "=A1*(1+5%)",
+            "=IF(A1>3,\"big\",\"small\")&\"!\"",
+            "=-SUM(A1:A2,{1,2}*2)",
+            "=IFERROR(A3+1,LEN(A2))",
+            "=LET(x,A1,x*x)",
+            "=@A1:A3",
+            "=1/0",
+            "=NOSUCH(1)",
+        ] {
+            let formula = Formula::compile(text, &engine.functions).unwrap();
+            assert_eq!(formula.evaluate(&evaluator), evaluator.evaluate(formula.expr()), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Aggregate functions: `SUM`, `AVERAGE`, `MAX`, `MIN` and `COUNT`
+
+use super::{Arg, ArgKind, Category, Function, FunctionRegistry, Implementation};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::value::CellValue;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    let functions: [(&str, Implementation); 5] = [
+        ("SUM", sum),
+        ("AVERAGE", average),
+        ("MAX", max),
+        ("MIN", min),
+        ("COUNT", count),
+    ];
+
+    for (name, implementation) in functions {
+        registry.register(Function::new(
+            name,
+            Category::Aggregate,
+            (1, Some(255)),
+            &[ArgKind::Reference],
+            implementation,
+        ));
+    }
+}
+
+fn sum(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    Ok(CellValue::Number(numbers(args)?.iter().sum()))
+}
+
+fn average(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let numbers = numbers(args)?;
+    if numbers.is_empty() {
+        return Err(ExcelError::Div0);
+    }
+    Ok(CellValue::Number(numbers.iter().sum::<f64>() / numbers.len() as f64))
+}
+
+fn max(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    Ok(CellValue::Number(numbers(args)?.into_iter().reduce(f64::max).unwrap_or(0.0)))
+}
+
+fn min(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    Ok(CellValue::Number(numbers(args)?.into_iter().reduce(f64::min).unwrap_or(0.0)))
+}
+
+/// `COUNT` never fails: arguments that error or are not numeric are skipped.
+fn count(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let count = args
+        .into_iter()
+        .map(|arg| match arg {
+            Arg::Reference(cells) => cells.filter(|value| matches!(value, Ok(CellValue::Number(_)))).count(),
+            Arg::Direct(Ok(CellValue::Array(rows))) => {
+                rows.iter().flatten().filter(|value| matches!(value, CellValue::Number(_))).count()
+            }
+            Arg::Direct(value) => matches!(value.map(|v| v.to_number()), Ok(Ok(_))) as usize,
+            Arg::Value(value) => value.to_number().is_ok() as usize,
+            Arg::Grid(_) | Arg::Lazy(_) => 0,
+        })
+        .sum::<usize>();
+    Ok(CellValue::Number(count as f64))
+}
+
+/// Collect the numbers aggregate functions operate on. Values typed
+/// directly into the argument list are coerced to numbers, while text,
+/// booleans and blanks read from referenced cells or array values are
+/// ignored. Errors propagate either way.
+fn numbers(args: Vec<Arg>) -> Result<Vec<f64>, ExcelError> {
+    let mut numbers = Vec::new();
+
+    for arg in args {
+        match arg {
+            Arg::Reference(cells) => {
+                for value in cells {
+                    if let CellValue::Number(n) = value? {
+                        numbers.push(n);
+                    }
+                }
+            }
+            Arg::Direct(Ok(CellValue::Array(rows))) => {
+                for value in rows.into_iter().flatten() {
+                    match value {
+                        CellValue::Number(n) => numbers.push(n),
+                        CellValue::Error(error) => return Err(error),
+                        _ => {}
+                    }
+                }
+            }
+            Arg::Direct(value) => numbers.push(value?.to_number()?),
+            Arg::Value(value) => numbers.push(value.to_number()?),
+            Arg::Grid(_) | Arg::Lazy(_) => return Err(ExcelError::Value),
+        }
+    }
+
+    Ok(numbers)
+}
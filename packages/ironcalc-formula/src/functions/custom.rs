@@ -0,0 +1,87 @@
+//! Functions implemented in JavaScript
+//!
+//! Arguments are passed as JS values: numbers, strings, booleans, `null`
+//! for blanks and arrays of rows for ranges, with error values inside a
+//! range as their codes. An argument that is itself an error is returned
+//! without calling the function, as built-in functions do. The return value
+//! is converted back the same way, reading strings that are error codes as
+//! errors; a value that cannot be converted, or an exception, is `#VALUE!`.
+
+use super::{Arg, ArgKind, Category, Function};
+use crate::error::ExcelError;
+use crate::value::CellValue;
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
+pub fn js_function(name: &str, arity: (usize, Option<usize>), callback: js_sys::Function) -> Function {
+    Function::new(name, Category::Custom, arity, &[ArgKind::Value], move |_, args| call(&callback, args))
+}
+
+fn call(callback: &js_sys::Function, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let js_args = js_sys::Array::new();
+    for arg in args {
+        let Arg::Value(value) = arg else {
+            return Err(ExcelError::Value);
+        };
+        let value = value
+            .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .map_err(|_| ExcelError::Value)?;
+        js_args.push(&value);
+    }
+
+    let result = callback.apply(&JsValue::NULL, &js_args).map_err(|_| ExcelError::Value)?;
+    from_js(&result)
+}
+
+fn from_js(value: &JsValue) -> Result<CellValue, ExcelError> {
+    if js_sys::Array::is_array(value) {
+        return array_from_js(&js_sys::Array::from(value));
+    }
+
+    match scalar_from_js(value)? {
+        CellValue::Error(error) => Err(error),
+        value => Ok(value),
+    }
+}
+
+fn scalar_from_js(value: &JsValue) -> Result<CellValue, ExcelError> {
+    if value.is_null() || value.is_undefined() {
+        Ok(CellValue::Empty)
+    } else if let Some(n) = value.as_f64() {
+        if n.is_finite() {
+            Ok(CellValue::Number(n))
+        } else {
+            Ok(CellValue::Error(ExcelError::Num))
+        }
+    } else if let Some(b) = value.as_bool() {
+        Ok(CellValue::Boolean(b))
+    } else if let Some(s) = value.as_string() {
+        Ok(ExcelError::from_code(&s).map_or(CellValue::Text(s), CellValue::Error))
+    } else {
+        Err(ExcelError::Value)
+    }
+}
+
+/// An array of rows, or a flat array read as a single row. Short rows are
+/// padded with `#N/A`, as Excel pads arrays of mismatched sizes.
+fn array_from_js(array: &js_sys::Array) -> Result<CellValue, ExcelError> {
+    let rows: Vec<js_sys::Array> = if array.iter().all(|row| js_sys::Array::is_array(&row)) {
+        array.iter().map(|row| js_sys::Array::from(&row)).collect()
+    } else {
+        vec![array.clone()]
+    };
+
+    let mut rows = rows
+        .iter()
+        .map(|row| row.iter().map(|value| scalar_from_js(&value)).collect::<Result<Vec<_>, _>>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if width == 0 {
+        return Err(ExcelError::Calc);
+    }
+    for row in &mut rows {
+        row.resize(width, CellValue::Error(ExcelError::NA));
+    }
+    Ok(CellValue::Array(rows))
+}
@@ -0,0 +1,368 @@
+//! Date and time functions: `DATE`, `TIME`, `YEAR`, `MONTH`, `DAY`,
+//! `HOUR`, `MINUTE`, `SECOND`, `EDATE`, `EOMONTH`, `WEEKDAY`, `WEEKNUM`,
+//! `ISOWEEKNUM`, `DATEDIF`, `DAYS`, `DAYS360`, `YEARFRAC`, `DATEVALUE` and
+//! `TIMEVALUE`
+//!
+//! Dates are serial numbers in the workbook's date system and times are
+//! fractions of a day; see [`crate::date`]. A date argument may also be
+//! text that `DATEVALUE` reads, such as "2024-01-15", and dates outside
+//! the system are `#NUM!`. Functions apply to each element of an array
+//! argument.
+
+use super::{lift, Arg, ArgKind, Category, Function, FunctionRegistry, Implementation};
+use crate::date::{self, DateSystem};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::value::CellValue;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    let functions: [(&str, usize, usize, Implementation); 19] = [
+        ("DATE", 3, 3, date),
+        ("TIME", 3, 3, time),
+        ("YEAR", 1, 1, year),
+        ("MONTH", 1, 1, month),
+        ("DAY", 1, 1, day),
+        ("HOUR", 1, 1, hour),
+        ("MINUTE", 1, 1, minute),
+        ("SECOND", 1, 1, second),
+        ("EDATE", 2, 2, edate),
+        ("EOMONTH", 2, 2, eomonth),
+        ("WEEKDAY", 1, 2, weekday),
+        ("WEEKNUM", 1, 2, weeknum),
+        ("ISOWEEKNUM", 1, 1, isoweeknum),
+        ("DATEDIF", 3, 3, datedif),
+        ("DAYS", 2, 2, days),
+        ("DAYS360", 2, 3, days360),
+        ("YEARFRAC", 2, 3, yearfrac),
+        ("DATEVALUE", 1, 1, datevalue),
+        ("TIMEVALUE", 1, 1, timevalue),
+    ];
+    for (name, min_args, max_args, implementation) in functions {
+        registry.register(Function::new(
+            name,
+            Category::Date,
+            (min_args, Some(max_args)),
+            &[ArgKind::Value],
+            implementation,
+        ));
+    }
+}
+
+/// A date argument as a serial number, time included.
+fn date_time(system: DateSystem, value: &CellValue) -> Result<f64, ExcelError> {
+    let serial = match (value, value.to_number()) {
+        (CellValue::Text(text), Err(error)) => date::parse(system, text)
+            .map(|(date, time)| date.unwrap_or(0) as f64 + time.unwrap_or(0.0))
+            .ok_or(error)?,
+        (_, number) => number?,
+    };
+    if serial < 0.0 || serial >= (system.last() + 1) as f64 {
+        return Err(ExcelError::Num);
+    }
+    Ok(serial)
+}
+
+/// A date argument as a whole serial day, its time dropped.
+fn serial(system: DateSystem, value: &CellValue) -> Result<i64, ExcelError> {
+    Ok(date_time(system, value)?.floor() as i64)
+}
+
+/// A whole-number argument, truncated.
+fn integer(value: &CellValue) -> Result<i64, ExcelError> {
+    Ok(value.to_number()?.trunc() as i64)
+}
+
+fn number(number: i64) -> CellValue {
+    CellValue::Number(number as f64)
+}
+
+/// `DATE(year, month, day)`. Years below 1900 are counted from 1900, and
+/// months and days out of range run into the next or previous year or
+/// month.
+fn date(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let year = match integer(&args[0])? {
+            year @ 0..=1899 => year + 1900,
+            year => year,
+        };
+        Ok(number(system.serial(year, integer(&args[1])?, integer(&args[2])?)?))
+    })
+}
+
+/// `TIME(hour, minute, second)` as a fraction of a day; times past a day
+/// wrap around and negative times are `#NUM!`, as are parts past 32,767
+/// either way.
+fn time(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let parts = [integer(&args[0])?, integer(&args[1])?, integer(&args[2])?];
+        if parts.iter().any(|part| !(-32_767..=32_767).contains(part)) {
+            return Err(ExcelError::Num);
+        }
+        let seconds = parts[0] * 3600 + parts[1] * 60 + parts[2];
+        if seconds < 0 {
+            return Err(ExcelError::Num);
+        }
+        Ok(CellValue::Number((seconds % 86_400) as f64 / 86_400.0))
+    })
+}
+
+/// A function of the year, month and day of a date argument.
+fn date_part(evaluator: &Evaluator, args: Vec<Arg>, part: fn((i64, i64, i64)) -> i64) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| Ok(number(part(system.ymd(serial(system, &args[0])?)?))))
+}
+
+fn year(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    date_part(evaluator, args, |(year, _, _)| year)
+}
+
+fn month(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    date_part(evaluator, args, |(_, month, _)| month)
+}
+
+fn day(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    date_part(evaluator, args, |(_, _, day)| day)
+}
+
+/// A function of the hour, minute and second of a time argument.
+fn time_part(evaluator: &Evaluator, args: Vec<Arg>, part: fn((i64, i64, i64)) -> i64) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| Ok(number(part(date::hms(date_time(system, &args[0])?)))))
+}
+
+fn hour(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    time_part(evaluator, args, |(hour, _, _)| hour)
+}
+
+fn minute(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    time_part(evaluator, args, |(_, minute, _)| minute)
+}
+
+fn second(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    time_part(evaluator, args, |(_, _, second)| second)
+}
+
+/// The date some months from a date, on the same day or the last day of
+/// a shorter month, or on the last day when `end_of_month`.
+fn add_months(system: DateSystem, serial: i64, months: i64, end_of_month: bool) -> Result<i64, ExcelError> {
+    let (year, month, day) = system.ymd(serial)?;
+    let months = (year * 12 + month - 1).checked_add(months).ok_or(ExcelError::Num)?;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+    if !(0..=9999).contains(&year) {
+        return Err(ExcelError::Num);
+    }
+    let last = system.days_in_month(year, month);
+    system.serial(year, month, if end_of_month { last } else { day.min(last) })
+}
+
+/// `EDATE(start_date, months)`, so that `EDATE(DATE(2024,1,31),1)` is
+/// 2024-02-29.
+fn edate(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| Ok(number(add_months(system, serial(system, &args[0])?, integer(&args[1])?, false)?)))
+}
+
+/// `EOMONTH(start_date, months)` is the last day of the month some months
+/// away.
+fn eomonth(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| Ok(number(add_months(system, serial(system, &args[0])?, integer(&args[1])?, true)?)))
+}
+
+/// `WEEKDAY(date, [return_type])`: with type 1, the default, Sunday is 1
+/// and Saturday 7; with 2 Monday is 1; with 3 Monday is 0; and with 11 to
+/// 17 Monday to Sunday is 1.
+fn weekday(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let serial = serial(system, &args[0])?;
+        let (first, base) = match args.get(1).map_or(Ok(1), integer)? {
+            1 => (0, 1),
+            2 => (1, 1),
+            3 => (1, 0),
+            kind @ 11..=17 => ((kind - 10) % 7, 1),
+            _ => return Err(ExcelError::Num),
+        };
+        Ok(number((system.weekday(serial) - first).rem_euclid(7) + base))
+    })
+}
+
+/// The ISO 8601 week of a date: weeks start on Monday, and week 1 is the
+/// one holding the year's first Thursday.
+fn iso_week(system: DateSystem, serial: i64) -> Result<i64, ExcelError> {
+    let thursday = serial - (system.weekday(serial) + 6) % 7 + 3;
+    let (year, _, _) = system.ymd(thursday)?;
+    Ok((thursday - system.serial(year, 1, 1)?) / 7 + 1)
+}
+
+/// `WEEKNUM(date, [return_type])` counts weeks from the one holding
+/// January 1, starting on Sunday with type 1 or 17, on Monday with 2 or
+/// 11, and on Tuesday to Saturday with 12 to 16. Type 21 is the ISO week.
+fn weeknum(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let serial = serial(system, &args[0])?;
+        let first = match args.get(1).map_or(Ok(1), integer)? {
+            1 | 17 => 0,
+            2 | 11 => 1,
+            kind @ 12..=16 => kind - 10,
+            21 => return Ok(number(iso_week(system, serial)?)),
+            _ => return Err(ExcelError::Num),
+        };
+        let (year, _, _) = system.ymd(serial)?;
+        let january_1 = system.serial(year, 1, 1)?;
+        let offset = (system.weekday(january_1) - first).rem_euclid(7);
+        Ok(number((serial - january_1 + offset).div_euclid(7) + 1))
+    })
+}
+
+fn isoweeknum(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| Ok(number(iso_week(system, serial(system, &args[0])?)?)))
+}
+
+/// `DATEDIF(start_date, end_date, unit)` counts whole years ("Y"), months
+/// ("M") or days ("D") between two dates, or the months left over after
+/// the years ("YM"), the days after the months ("MD") or the days after
+/// the years ("YD"). A start after the end is `#NUM!`.
+fn datedif(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let (start, end) = (serial(system, &args[0])?, serial(system, &args[1])?);
+        if start > end {
+            return Err(ExcelError::Num);
+        }
+        let (start_year, start_month, start_day) = system.ymd(start)?;
+        let (end_year, end_month, end_day) = system.ymd(end)?;
+        let months = (end_year - start_year) * 12 + end_month - start_month - i64::from(end_day < start_day);
+
+        let difference = match args[2].to_text()?.to_uppercase().as_str() {
+            "Y" => months / 12,
+            "M" => months,
+            "D" => end - start,
+            "YM" => months % 12,
+            "MD" if end_day >= start_day => end_day - start_day,
+            "MD" => end - system.serial(end_year, end_month - 1, start_day)?,
+            "YD" => {
+                let mut anniversary = system.serial(end_year, start_month, start_day)?;
+                if anniversary > end {
+                    anniversary = system.serial(end_year - 1, start_month, start_day)?;
+                }
+                end - anniversary
+            }
+            _ => return Err(ExcelError::Num),
+        };
+        Ok(number(difference))
+    })
+}
+
+/// `DAYS(end_date, start_date)` is the number of days between two dates.
+fn days(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| Ok(number(serial(system, &args[0])? - serial(system, &args[1])?)))
+}
+
+/// The days between two dates counting 30 days to every month. By the US
+/// (NASD) method a start on the last day of its month counts as the 30th,
+/// and so does an end on the 31st when the start does. By the European
+/// method any 31st counts as the 30th.
+fn days_360(system: DateSystem, start: i64, end: i64, european: bool) -> Result<i64, ExcelError> {
+    let (start_year, start_month, mut start_day) = system.ymd(start)?;
+    let (end_year, end_month, mut end_day) = system.ymd(end)?;
+    if european {
+        start_day = start_day.min(30);
+        end_day = end_day.min(30);
+    } else {
+        if start_day == system.days_in_month(start_year, start_month) {
+            start_day = 30;
+        }
+        if end_day == 31 && start_day == 30 {
+            end_day = 30;
+        }
+    }
+    Ok((end_year - start_year) * 360 + (end_month - start_month) * 30 + end_day - start_day)
+}
+
+/// `DAYS360(start_date, end_date, [european])`
+fn days360(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let european = args.get(2).map_or(Ok(false), CellValue::to_bool)?;
+        Ok(number(days_360(system, serial(system, &args[0])?, serial(system, &args[1])?, european)?))
+    })
+}
+
+/// `YEARFRAC(start_date, end_date, [basis])` is the fraction of a year
+/// between two dates, counting days by basis 0, US 30/360 and the default;
+/// 1, actual days over actual years; 2, actual/360; 3, actual/365; or 4,
+/// European 30/360.
+fn yearfrac(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let (first, second) = (serial(system, &args[0])?, serial(system, &args[1])?);
+        let (start, end) = (first.min(second), first.max(second));
+        let days = (end - start) as f64;
+        let fraction = match args.get(2).map_or(Ok(0), integer)? {
+            0 => days_360(system, start, end, false)? as f64 / 360.0,
+            1 => days / actual_year_length(system, start, end)?,
+            2 => days / 360.0,
+            3 => days / 365.0,
+            4 => days_360(system, start, end, true)? as f64 / 360.0,
+            _ => return Err(ExcelError::Num),
+        };
+        Ok(CellValue::Number(fraction))
+    })
+}
+
+/// The length of a year for `YEARFRAC`'s actual/actual basis: within a
+/// year, 366 days when the dates span a February 29; across years, the
+/// average length of the years they touch.
+fn actual_year_length(system: DateSystem, start: i64, end: i64) -> Result<f64, ExcelError> {
+    let (start_year, start_month, start_day) = system.ymd(start)?;
+    let (end_year, end_month, end_day) = system.ymd(end)?;
+    let leap = |year: i64| system.days_in_month(year, 2) == 29;
+
+    let within_a_year = start_year == end_year
+        || start_year + 1 == end_year && (start_month, start_day) >= (end_month, end_day);
+    if !within_a_year {
+        let days: i64 = (start_year..=end_year).map(|year| if leap(year) { 366 } else { 365 }).sum();
+        return Ok(days as f64 / (end_year - start_year + 1) as f64);
+    }
+
+    let spans_february_29 = (start_year..=end_year)
+        .filter(|year| leap(*year))
+        .map(|year| system.serial(year, 2, 29))
+        .any(|february_29| february_29.is_ok_and(|day| (start..=end).contains(&day)));
+    Ok(if spans_february_29 { 366.0 } else { 365.0 })
+}
+
+/// `DATEVALUE(text)` is the serial number of a date written as text; a
+/// time after the date is ignored.
+fn datevalue(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let CellValue::Text(text) = &args[0] else {
+            return Err(ExcelError::Value);
+        };
+        match date::parse(system, text) {
+            Some((Some(date), _)) => Ok(number(date)),
+            _ => Err(ExcelError::Value),
+        }
+    })
+}
+
+/// `TIMEVALUE(text)` is the fraction of a day of a time written as text;
+/// a date before the time is ignored.
+fn timevalue(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let system = evaluator.date_system();
+    lift(args, |args| {
+        let CellValue::Text(text) = &args[0] else {
+            return Err(ExcelError::Value);
+        };
+        match date::parse(system, text) {
+            Some((_, time)) => Ok(CellValue::Number(time.unwrap_or(0.0).fract())),
+            None => Err(ExcelError::Value),
+        }
+    })
+}
@@ -0,0 +1,56 @@
+//! Information functions about errors: `ISERROR`, `ISERR`, `ISNA`,
+//! `ERROR.TYPE` and `NA`
+
+use super::{Arg, ArgKind, Category, Function, FunctionRegistry, Implementation};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::value::CellValue;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    // These look at the error an argument evaluates to, so they take it
+    // unevaluated rather than letting the error propagate
+    let functions: [(&str, Implementation); 4] = [
+        ("ISERROR", iserror),
+        ("ISERR", iserr),
+        ("ISNA", isna),
+        ("ERROR.TYPE", error_type),
+    ];
+    for (name, implementation) in functions {
+        registry.register(Function::new(name, Category::Information, (1, Some(1)), &[ArgKind::Lazy], implementation));
+    }
+
+    registry.register(Function::new("NA", Category::Information, (0, Some(0)), &[], |_, _| Err(ExcelError::NA)));
+}
+
+fn iserror(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    is_error(evaluator, args, |_| true)
+}
+
+fn iserr(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    is_error(evaluator, args, |error| error != ExcelError::NA)
+}
+
+fn isna(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    is_error(evaluator, args, |error| error == ExcelError::NA)
+}
+
+/// Whether the argument is an error accepted by `matches`. These never
+/// propagate the error they test for.
+fn is_error(evaluator: &Evaluator, args: Vec<Arg>, matches: fn(ExcelError) -> bool) -> Result<CellValue, ExcelError> {
+    let [Arg::Lazy(arg)] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    Ok(CellValue::Boolean(matches!(evaluator.evaluate(arg), Err(error) if matches(error))))
+}
+
+fn error_type(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let [Arg::Lazy(arg)] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    match evaluator.evaluate(arg).err().and_then(|error| error.type_number()) {
+        Some(number) => Ok(CellValue::Number(number as f64)),
+        None => Err(ExcelError::NA),
+    }
+}
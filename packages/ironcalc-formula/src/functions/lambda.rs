@@ -0,0 +1,232 @@
+//! `LET`, `LAMBDA` and the lambda helpers `MAP`, `REDUCE`, `SCAN`, `BYROW`,
+//! `BYCOL` and `MAKEARRAY`
+//!
+//! The helpers call a lambda for each element, row or column of an array.
+//! Each call must produce a single value; a call that returns an array or a
+//! lambda puts `#CALC!` in its place, as Excel does.
+
+use super::{Arg, ArgKind, Category, Function, FunctionRegistry};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::parser::Expr;
+use crate::scope::{same_name, Lambda};
+use crate::value::CellValue;
+use std::rc::Rc;
+
+// The size of an Excel sheet, which bounds MAKEARRAY
+const MAX_ROWS: f64 = 1_048_576.0;
+const MAX_COLS: f64 = 16_384.0;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.register(Function::new("LET", Category::Logical, (3, Some(253)), &[ArgKind::Lazy], r#let));
+    registry.register(Function::new("LAMBDA", Category::Logical, (1, Some(254)), &[ArgKind::Lazy], lambda));
+    registry.register(Function::new("MAP", Category::Logical, (2, Some(254)), &[ArgKind::Value], map));
+    registry.register(Function::new("REDUCE", Category::Logical, (3, Some(3)), &[ArgKind::Value], reduce));
+    registry.register(Function::new("SCAN", Category::Logical, (3, Some(3)), &[ArgKind::Value], scan));
+    registry.register(Function::new("BYROW", Category::Logical, (2, Some(2)), &[ArgKind::Value], byrow));
+    registry.register(Function::new("BYCOL", Category::Logical, (2, Some(2)), &[ArgKind::Value], bycol));
+    registry.register(Function::new("MAKEARRAY", Category::Logical, (3, Some(3)), &[ArgKind::Value], makearray));
+}
+
+/// `LET(name1, value1, [name2, value2, ...], calculation)`. Each value is
+/// evaluated once and can use the names bound before it. A value that is an
+/// error only surfaces if its name is used.
+fn r#let(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let Some((Arg::Lazy(calculation), bindings)) = args.split_last() else {
+        return Err(ExcelError::Value);
+    };
+    if bindings.len() % 2 != 0 {
+        return Err(ExcelError::Value);
+    }
+
+    let mut scoped = evaluator.clone();
+    for pair in bindings.chunks(2) {
+        let [Arg::Lazy(Expr::Name(name)), Arg::Lazy(value)] = pair else {
+            return Err(ExcelError::Value);
+        };
+        let value = scoped.evaluate(value).unwrap_or_else(CellValue::Error);
+        scoped = scoped.bind(name, value);
+    }
+    scoped.evaluate(calculation)
+}
+
+/// `LAMBDA([param1, ...], calculation)` evaluates to a function value that
+/// remembers the names bound where it was created.
+fn lambda(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let Some((Arg::Lazy(body), params)) = args.split_last() else {
+        return Err(ExcelError::Value);
+    };
+
+    let mut names: Vec<String> = Vec::with_capacity(params.len());
+    for param in params {
+        match param {
+            Arg::Lazy(Expr::Name(name)) if !names.iter().any(|seen| same_name(seen, name)) => {
+                names.push(name.clone())
+            }
+            _ => return Err(ExcelError::Value),
+        }
+    }
+
+    Ok(CellValue::Lambda(Rc::new(Lambda {
+        params: names,
+        body: (*body).clone(),
+        scope: evaluator.scope().clone(),
+    })))
+}
+
+/// `MAP(array1, [array2, ...], lambda)` calls the lambda with the elements
+/// at the same position in each array. Positions missing from a smaller
+/// array give `#N/A`.
+fn map(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lambda, arrays) = lambda_and_values(args, None)?;
+    if lambda.params.len() != arrays.len() {
+        return Err(ExcelError::Value);
+    }
+
+    let arrays: Vec<_> = arrays.into_iter().map(CellValue::into_rows).collect();
+    let height = arrays.iter().map(Vec::len).max().unwrap_or(0);
+    let width = arrays.iter().flatten().map(Vec::len).max().unwrap_or(0);
+
+    let rows = (0..height)
+        .map(|row| {
+            (0..width)
+                .map(|col| {
+                    let values = arrays
+                        .iter()
+                        .map(|array| match array.get(row).and_then(|cells| cells.get(col)) {
+                            Some(value) => value.clone(),
+                            None => CellValue::Error(ExcelError::NA),
+                        })
+                        .collect();
+                    element(evaluator.call(&lambda, values))
+                })
+                .collect()
+        })
+        .collect();
+    Ok(CellValue::Array(rows))
+}
+
+/// `REDUCE(initial, array, lambda)` folds the elements of the array in
+/// row-major order, calling `lambda(accumulator, value)`.
+fn reduce(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lambda, mut values) = lambda_and_values(args, Some(2))?;
+    let array = values.pop().unwrap_or_default();
+    let initial = values.pop().unwrap_or_default();
+
+    array
+        .into_rows()
+        .into_iter()
+        .flatten()
+        .try_fold(initial, |accumulator, value| evaluator.call(&lambda, vec![accumulator, value]))
+}
+
+/// `SCAN(initial, array, lambda)` is `REDUCE` returning every intermediate
+/// accumulator, in an array the shape of `array`.
+fn scan(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lambda, mut values) = lambda_and_values(args, Some(2))?;
+    let array = values.pop().unwrap_or_default();
+    let mut accumulator = values.pop().unwrap_or_default();
+
+    let rows = array
+        .into_rows()
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|value| {
+                    accumulator = element(evaluator.call(&lambda, vec![accumulator.clone(), value]));
+                    accumulator.clone()
+                })
+                .collect()
+        })
+        .collect();
+    Ok(CellValue::Array(rows))
+}
+
+/// `BYROW(array, lambda)` calls the lambda with each row, as a one-row
+/// array, and returns a column of the results.
+fn byrow(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lambda, mut values) = lambda_and_values(args, Some(1))?;
+    let rows = values.pop().unwrap_or_default().into_rows();
+
+    let results = rows
+        .into_iter()
+        .map(|row| vec![element(evaluator.call(&lambda, vec![CellValue::Array(vec![row])]))])
+        .collect();
+    Ok(CellValue::Array(results))
+}
+
+/// `BYCOL(array, lambda)` calls the lambda with each column, as a
+/// one-column array, and returns a row of the results.
+fn bycol(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lambda, mut values) = lambda_and_values(args, Some(1))?;
+    let rows = values.pop().unwrap_or_default().into_rows();
+    let width = rows.first().map_or(0, Vec::len);
+
+    let results = (0..width)
+        .map(|col| {
+            let column = rows.iter().map(|row| vec![row.get(col).cloned().unwrap_or_default()]).collect();
+            element(evaluator.call(&lambda, vec![CellValue::Array(column)]))
+        })
+        .collect();
+    Ok(CellValue::Array(vec![results]))
+}
+
+/// `MAKEARRAY(rows, cols, lambda)` builds an array by calling
+/// `lambda(row, col)` with 1-based positions.
+fn makearray(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lambda, values) = lambda_and_values(args, Some(2))?;
+    let [rows, cols] = values.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+    let (rows, cols) = (rows.to_number()?.trunc(), cols.to_number()?.trunc());
+    if !(1.0..=MAX_ROWS).contains(&rows) || !(1.0..=MAX_COLS).contains(&cols) {
+        return Err(ExcelError::Value);
+    }
+
+    let array = (1..=rows as usize)
+        .map(|row| {
+            (1..=cols as usize)
+                .map(|col| {
+                    let position = vec![CellValue::Number(row as f64), CellValue::Number(col as f64)];
+                    element(evaluator.call(&lambda, position))
+                })
+                .collect()
+        })
+        .collect();
+    Ok(CellValue::Array(array))
+}
+
+/// Split the arguments of a helper into its trailing lambda and the values
+/// before it, checking that the lambda takes `params` parameters when the
+/// helper decides how many it passes.
+fn lambda_and_values(mut args: Vec<Arg>, params: Option<usize>) -> Result<(Rc<Lambda>, Vec<CellValue>), ExcelError> {
+    let Some(Arg::Value(CellValue::Lambda(lambda))) = args.pop() else {
+        return Err(ExcelError::Value);
+    };
+    if params.is_some_and(|params| params != lambda.params.len()) {
+        return Err(ExcelError::Value);
+    }
+
+    let values = args
+        .into_iter()
+        .map(|arg| match arg {
+            Arg::Value(value) => Ok(value),
+            _ => Err(ExcelError::Value),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((lambda, values))
+}
+
+/// The element a lambda call contributes to a helper's result array.
+fn element(result: Result<CellValue, ExcelError>) -> CellValue {
+    match result {
+        Ok(CellValue::Array(rows)) => match rows.as_slice() {
+            [row] if row.len() == 1 => row[0].clone(),
+            _ => CellValue::Error(ExcelError::Calc),
+        },
+        Ok(CellValue::Lambda(_)) => CellValue::Error(ExcelError::Calc),
+        Ok(CellValue::Empty) => CellValue::Number(0.0),
+        Ok(value) => value,
+        Err(error) => CellValue::Error(error),
+    }
+}
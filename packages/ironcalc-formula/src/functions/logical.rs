@@ -0,0 +1,51 @@
+//! Logical functions: `IF`, `IFERROR` and `IFNA`
+
+use super::{Arg, ArgKind, Category, Function, FunctionRegistry};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::value::CellValue;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.register(Function::new(
+        "IF",
+        Category::Logical,
+        (2, Some(3)),
+        &[ArgKind::Value, ArgKind::Lazy],
+        r#if,
+    ));
+    registry.register(Function::new("IFERROR", Category::Logical, (2, Some(2)), &[ArgKind::Lazy], iferror));
+    registry.register(Function::new("IFNA", Category::Logical, (2, Some(2)), &[ArgKind::Lazy], ifna));
+}
+
+/// Only the selected branch is evaluated.
+fn r#if(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    match args.as_slice() {
+        [Arg::Value(condition), Arg::Lazy(if_true), rest @ ..] => match (condition.to_bool()?, rest) {
+            (true, _) => evaluator.evaluate(if_true),
+            (false, [Arg::Lazy(if_false)]) => evaluator.evaluate(if_false),
+            (false, _) => Ok(CellValue::Boolean(false)),
+        },
+        _ => Err(ExcelError::Value),
+    }
+}
+
+fn iferror(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    if_error(evaluator, args, |_| true)
+}
+
+fn ifna(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    if_error(evaluator, args, |error| error == ExcelError::NA)
+}
+
+/// The first argument, or the second when the first evaluates to an error
+/// accepted by `catches`. The fallback is only evaluated when it is needed.
+fn if_error(evaluator: &Evaluator, args: Vec<Arg>, catches: fn(ExcelError) -> bool) -> Result<CellValue, ExcelError> {
+    let [Arg::Lazy(value), Arg::Lazy(fallback)] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    match evaluator.evaluate(value) {
+        Err(error) if catches(error) => evaluator.evaluate(fallback),
+        result => result,
+    }
+}
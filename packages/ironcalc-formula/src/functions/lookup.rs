@@ -0,0 +1,518 @@
+//! Lookup functions: `VLOOKUP`, `HLOOKUP`, `XLOOKUP`, `INDEX`, `MATCH` and
+//! `XMATCH`
+//!
+//! Searches follow Excel's rules: a value only matches values of its own
+//! type, text compares case-insensitively and blanks never match. Exact
+//! matches of text may use the wildcards `*` and `?`, with `~` escaping
+//! them. Approximate matches, and the binary search modes of `XLOOKUP` and
+//! `XMATCH`, expect sorted data and read only the cells a binary search
+//! probes, so they stay fast on ranges of any length.
+
+use super::{optional, Arg, ArgKind, Category, Function, FunctionRegistry};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::grid::Grid;
+use crate::parser::Expr;
+use crate::value::CellValue;
+use std::cmp::Ordering;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    use ArgKind::{Grid, Lazy, Value};
+
+    registry.register(Function::new("VLOOKUP", Category::Lookup, (3, Some(4)), &[Value, Grid, Value], vlookup));
+    registry.register(Function::new("HLOOKUP", Category::Lookup, (3, Some(4)), &[Value, Grid, Value], hlookup));
+    // The optional arguments are lazy so that omitted ones take their
+    // defaults rather than 0
+    registry.register(Function::new("XLOOKUP", Category::Lookup, (3, Some(6)), &[Value, Grid, Grid, Lazy], xlookup));
+    registry.register(Function::new("INDEX", Category::Lookup, (2, Some(3)), &[Grid, Value], index));
+    registry.register(Function::new("MATCH", Category::Lookup, (2, Some(3)), &[Value, Grid, Value], r#match));
+    registry.register(Function::new("XMATCH", Category::Lookup, (2, Some(4)), &[Value, Grid, Lazy], xmatch));
+}
+
+/// What counts as a match besides an equal value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MatchMode {
+    Exact,
+    /// The largest value below the lookup value
+    NextSmaller,
+    /// The smallest value above the lookup value
+    NextLarger,
+    /// Text lookup values are wildcard patterns
+    Wildcard,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SearchMode {
+    FirstToLast,
+    LastToFirst,
+    /// Binary search of values sorted in ascending order
+    Ascending,
+    /// Binary search of values sorted in descending order
+    Descending,
+}
+
+/// A row or column of a grid, searched by position.
+struct Line<'g, 'a> {
+    grid: &'g Grid<'a>,
+    vertical: bool,
+    // The column of a vertical line or the row of a horizontal one
+    offset: usize,
+    len: usize,
+}
+
+impl<'g, 'a> Line<'g, 'a> {
+    /// The grid as a line, or `None` when it has several rows and columns.
+    fn of(grid: &'g Grid<'a>) -> Option<Self> {
+        match grid.dimensions() {
+            (_, 1) => Some(Line::column(grid, 0)),
+            (1, _) => Some(Line::row(grid, 0)),
+            _ => None,
+        }
+    }
+
+    fn column(grid: &'g Grid<'a>, offset: usize) -> Self {
+        let len = grid.searched().0;
+        Line { grid, vertical: true, offset, len }
+    }
+
+    fn row(grid: &'g Grid<'a>, offset: usize) -> Self {
+        let len = grid.searched().1;
+        Line { grid, vertical: false, offset, len }
+    }
+
+    fn at(&self, position: usize) -> CellValue {
+        if self.vertical {
+            self.grid.get(position, self.offset)
+        } else {
+            self.grid.get(self.offset, position)
+        }
+    }
+
+    /// The position of the value matching `lookup`.
+    fn find(&self, lookup: &CellValue, match_mode: MatchMode, search_mode: SearchMode) -> Option<usize> {
+        match search_mode {
+            SearchMode::FirstToLast => self.scan(self.populated(), lookup, match_mode),
+            SearchMode::LastToFirst => self.scan(self.populated().rev(), lookup, match_mode),
+            SearchMode::Ascending => self.bisect(lookup, match_mode, false),
+            SearchMode::Descending => self.bisect(lookup, match_mode, true),
+        }
+    }
+
+    /// The positions and values of the cells of the line holding something;
+    /// blanks never match.
+    fn populated(&self) -> Box<dyn DoubleEndedIterator<Item = (usize, CellValue)> + 'g> {
+        self.grid.populated(self.vertical, self.offset, self.len)
+    }
+
+    /// Look at every value in turn. An equal value wins straight away;
+    /// otherwise the closest value on the wanted side does, the first one
+    /// found among equals.
+    fn scan(&self, values: impl Iterator<Item = (usize, CellValue)>, lookup: &CellValue, match_mode: MatchMode) -> Option<usize> {
+        let pattern = match (match_mode, lookup) {
+            (MatchMode::Wildcard, CellValue::Text(text)) => Some(Pattern::new(text)),
+            _ => None,
+        };
+        let wanted = match match_mode {
+            MatchMode::NextSmaller => Ordering::Less,
+            MatchMode::NextLarger => Ordering::Greater,
+            MatchMode::Exact | MatchMode::Wildcard => Ordering::Equal,
+        };
+
+        let mut best: Option<(usize, CellValue)> = None;
+        for (position, value) in values {
+            if let Some(pattern) = &pattern {
+                if matches!(&value, CellValue::Text(text) if pattern.matches(text)) {
+                    return Some(position);
+                }
+                continue;
+            }
+
+            match compare(&value, lookup) {
+                Some(Ordering::Equal) => return Some(position),
+                Some(ordering) if ordering == wanted => {
+                    let closer = best.as_ref().is_none_or(|(_, best)| compare(&value, best) == Some(wanted.reverse()));
+                    if closer {
+                        best = Some((position, value));
+                    }
+                }
+                _ => {}
+            }
+        }
+        best.map(|(position, _)| position)
+    }
+
+    /// Binary search of sorted values for the last one not past `lookup`
+    /// in the sort order. Blanks and values of other types are stepped over
+    /// towards the start of the line, which keeps the search going when a
+    /// column has gaps.
+    fn bisect(&self, lookup: &CellValue, match_mode: MatchMode, descending: bool) -> Option<usize> {
+        let not_past = |ordering: Ordering| if descending { ordering != Ordering::Less } else { ordering != Ordering::Greater };
+
+        let (mut low, mut high) = (0, self.len);
+        let mut found: Option<(usize, Ordering)> = None;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let probe = (low..=middle).rev().find_map(|position| Some((position, compare(&self.at(position), lookup)?)));
+            match probe {
+                Some((position, ordering)) if not_past(ordering) => {
+                    found = Some((position, ordering));
+                    low = middle + 1;
+                }
+                Some((position, _)) => high = position,
+                None => low = middle + 1,
+            }
+        }
+
+        let following = || {
+            let start = found.map_or(0, |(position, _)| position + 1);
+            (start..self.len).find(|&position| compare(&self.at(position), lookup).is_some())
+        };
+        match (found, match_mode) {
+            (Some((position, Ordering::Equal)), _) => Some(position),
+            (_, MatchMode::Exact | MatchMode::Wildcard) => None,
+            (_, MatchMode::NextSmaller) if !descending => found.map(|(position, _)| position),
+            (_, MatchMode::NextLarger) if descending => found.map(|(position, _)| position),
+            _ => following(),
+        }
+    }
+}
+
+/// How a value in the searched range compares with the lookup value, or
+/// `None` when they have different types and never match.
+fn compare(value: &CellValue, lookup: &CellValue) -> Option<Ordering> {
+    match (value, lookup) {
+        (CellValue::Number(a), CellValue::Number(b)) => a.partial_cmp(b),
+        (CellValue::Text(a), CellValue::Text(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+        (CellValue::Boolean(a), CellValue::Boolean(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Char(char),
+    /// `?`
+    One,
+    /// `*`
+    Any,
+}
+
+/// A text pattern with Excel's wildcards, matched case-insensitively.
+#[derive(Debug)]
+pub struct Pattern(Vec<Token>);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::Any,
+                '?' => Token::One,
+                '~' => match chars.clone().next() {
+                    Some(escaped @ ('*' | '?' | '~')) => {
+                        chars.next();
+                        Token::Char(escaped)
+                    }
+                    _ => Token::Char('~'),
+                },
+                c => Token::Char(c),
+            });
+        }
+        Pattern(tokens)
+    }
+
+    /// Whether the whole of `text` matches.
+    pub fn matches(&self, text: &str) -> bool {
+        self.matches_chars(&text.chars().collect::<Vec<_>>(), false)
+    }
+
+    /// Whether the start of `text` matches, as `SEARCH` looks for it.
+    pub fn matches_start(&self, text: &[char]) -> bool {
+        self.matches_chars(text, true)
+    }
+
+    /// When the pattern stops matching, the last `*` seen takes one more
+    /// character and matching resumes after it.
+    fn matches_chars(&self, text: &[char], prefix: bool) -> bool {
+        let (mut t, mut p) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+
+        while t < text.len() {
+            if prefix && p == self.0.len() {
+                return true;
+            }
+            match self.0.get(p) {
+                Some(Token::Any) => {
+                    star = Some((p, t));
+                    p += 1;
+                }
+                Some(Token::One) => (p, t) = (p + 1, t + 1),
+                Some(Token::Char(c)) if c.to_lowercase().eq(text[t].to_lowercase()) => (p, t) = (p + 1, t + 1),
+                _ => match star {
+                    Some((star_p, star_t)) => {
+                        star = Some((star_p, star_t + 1));
+                        (p, t) = (star_p + 1, star_t + 1);
+                    }
+                    None => return false,
+                },
+            }
+        }
+        self.0[p..].iter().all(|token| matches!(token, Token::Any))
+    }
+}
+
+/// Apply a lookup to each element of an array of lookup values, as when a
+/// lookup is given a range of values to find, or to the single value.
+fn each(lookup: CellValue, find: impl Fn(&CellValue) -> Result<CellValue, ExcelError>) -> Result<CellValue, ExcelError> {
+    let CellValue::Array(rows) = lookup else {
+        return find(&lookup);
+    };
+
+    let element = |value: &CellValue| match value {
+        CellValue::Error(_) => value.clone(),
+        _ => match find(value) {
+            Ok(CellValue::Array(_)) => CellValue::Error(ExcelError::Value),
+            result => result.unwrap_or_else(CellValue::Error),
+        },
+    };
+    Ok(CellValue::Array(rows.iter().map(|row| row.iter().map(element).collect()).collect()))
+}
+
+/// A whole-number argument such as a column index, truncated as Excel does.
+fn integer(value: &CellValue) -> Result<i64, ExcelError> {
+    Ok(value.to_number()?.trunc() as i64)
+}
+
+/// An optional lazy argument as a whole number, `default` when omitted.
+fn optional_integer(evaluator: &Evaluator, arg: Option<&Arg>, default: i64) -> Result<i64, ExcelError> {
+    optional(evaluator, arg)?.map_or(Ok(default), |value| integer(&value))
+}
+
+fn vlookup(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    table_lookup(args, true)
+}
+
+fn hlookup(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    table_lookup(args, false)
+}
+
+/// `VLOOKUP(lookup_value, table, col_index, [approximate])` finds the value
+/// in the first column of the table and returns the one in the column
+/// given, counted from 1; `HLOOKUP` does the same across the first row. An
+/// approximate match, the default, looks for the largest value not above
+/// the lookup value in sorted data.
+fn table_lookup(args: Vec<Arg>, vertical: bool) -> Result<CellValue, ExcelError> {
+    let (lookup, table, index, approximate) = match args.as_slice() {
+        [Arg::Value(lookup), Arg::Grid(table), Arg::Value(index)] => (lookup, table, index, true),
+        [Arg::Value(lookup), Arg::Grid(table), Arg::Value(index), Arg::Value(approximate)] => {
+            (lookup, table, index, approximate.to_bool()?)
+        }
+        _ => return Err(ExcelError::Value),
+    };
+
+    let index = integer(index)?;
+    let (rows, cols) = table.dimensions();
+    if index < 1 {
+        return Err(ExcelError::Value);
+    }
+    if index as usize > if vertical { cols } else { rows } {
+        return Err(ExcelError::Ref);
+    }
+
+    let line = if vertical { Line::column(table, 0) } else { Line::row(table, 0) };
+    let (match_mode, search_mode) = if approximate {
+        (MatchMode::NextSmaller, SearchMode::Ascending)
+    } else {
+        (MatchMode::Wildcard, SearchMode::FirstToLast)
+    };
+    each(lookup.clone(), |lookup| {
+        let position = line.find(lookup, match_mode, search_mode).ok_or(ExcelError::NA)?;
+        Ok(if vertical { table.get(position, index as usize - 1) } else { table.get(index as usize - 1, position) })
+    })
+}
+
+/// `MATCH(lookup_value, array, [match_type])` gives the position, from 1,
+/// of the value in a row or column: with the default match type 1 the
+/// largest value not above it in ascending data, with -1 the smallest not
+/// below it in descending data, and with 0 an exact match.
+fn r#match(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (lookup, array, match_type) = match args.as_slice() {
+        [Arg::Value(lookup), Arg::Grid(array)] => (lookup, array, 1),
+        [Arg::Value(lookup), Arg::Grid(array), Arg::Value(match_type)] => (lookup, array, integer(match_type)?),
+        _ => return Err(ExcelError::Value),
+    };
+
+    let line = Line::of(array).ok_or(ExcelError::NA)?;
+    let (match_mode, search_mode) = match match_type.signum() {
+        1 => (MatchMode::NextSmaller, SearchMode::Ascending),
+        0 => (MatchMode::Wildcard, SearchMode::FirstToLast),
+        _ => (MatchMode::NextLarger, SearchMode::Descending),
+    };
+    each(lookup.clone(), |lookup| position(line.find(lookup, match_mode, search_mode)))
+}
+
+/// `XMATCH(lookup_value, array, [match_mode], [search_mode])` is `MATCH`
+/// with the modes of `XLOOKUP`, an exact match by default.
+fn xmatch(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let [Arg::Value(lookup), Arg::Grid(array), options @ ..] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    let (match_mode, search_mode) = modes(evaluator, options.first(), options.get(1))?;
+    let line = Line::of(array).ok_or(ExcelError::Value)?;
+    each(lookup.clone(), |lookup| position(line.find(lookup, match_mode, search_mode)))
+}
+
+/// `XLOOKUP(lookup_value, lookup_array, return_array, [if_not_found],
+/// [match_mode], [search_mode])` finds the value in a row or column and
+/// returns what is at the same position in the return array, a whole row
+/// or column of it when it is two-dimensional. Without `if_not_found`, a
+/// value not found is `#N/A`.
+fn xlookup(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let [Arg::Value(lookup), Arg::Grid(lookup_array), Arg::Grid(return_array), options @ ..] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    let (match_mode, search_mode) = modes(evaluator, options.get(1), options.get(2))?;
+    let line = Line::of(lookup_array).ok_or(ExcelError::Value)?;
+    let ((rows, cols), (used_rows, used_cols)) = (return_array.dimensions(), return_array.used());
+    let (lookup_rows, lookup_cols) = lookup_array.dimensions();
+    if (line.vertical && rows != lookup_rows) || (!line.vertical && cols != lookup_cols) {
+        return Err(ExcelError::Value);
+    }
+
+    each(lookup.clone(), |lookup| match line.find(lookup, match_mode, search_mode) {
+        Some(position) if line.vertical => Ok(return_array.slice(position..position + 1, 0..used_cols)),
+        Some(position) => Ok(return_array.slice(0..used_rows, position..position + 1)),
+        None => match options.first() {
+            None | Some(Arg::Lazy(Expr::Missing)) => Err(ExcelError::NA),
+            Some(Arg::Lazy(if_not_found)) => evaluator.evaluate(if_not_found),
+            Some(_) => Err(ExcelError::Value),
+        },
+    })
+}
+
+/// The match and search modes of `XLOOKUP` and `XMATCH`. Wildcards cannot
+/// be used with a binary search.
+fn modes(evaluator: &Evaluator, match_mode: Option<&Arg>, search_mode: Option<&Arg>) -> Result<(MatchMode, SearchMode), ExcelError> {
+    let match_mode = match optional_integer(evaluator, match_mode, 0)? {
+        0 => MatchMode::Exact,
+        -1 => MatchMode::NextSmaller,
+        1 => MatchMode::NextLarger,
+        2 => MatchMode::Wildcard,
+        _ => return Err(ExcelError::Value),
+    };
+    let search_mode = match optional_integer(evaluator, search_mode, 1)? {
+        1 => SearchMode::FirstToLast,
+        -1 => SearchMode::LastToFirst,
+        2 => SearchMode::Ascending,
+        -2 => SearchMode::Descending,
+        _ => return Err(ExcelError::Value),
+    };
+
+    let binary = matches!(search_mode, SearchMode::Ascending | SearchMode::Descending);
+    if binary && match_mode == MatchMode::Wildcard {
+        return Err(ExcelError::Value);
+    }
+    Ok((match_mode, search_mode))
+}
+
+/// A position found by a search, counted from 1, or `#N/A`.
+fn position(found: Option<usize>) -> Result<CellValue, ExcelError> {
+    found.map(|position| CellValue::Number(position as f64 + 1.0)).ok_or(ExcelError::NA)
+}
+
+/// `INDEX(array, row, [column])` is the value at a position counted from 1.
+/// A row or column of 0 selects all of them, giving a whole column or row
+/// of the array. A single row or column can be indexed by position alone.
+fn index(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let (array, row, col) = match args.as_slice() {
+        [Arg::Grid(array), Arg::Value(row)] => (array, integer(row)?, None),
+        [Arg::Grid(array), Arg::Value(row), Arg::Value(col)] => (array, integer(row)?, Some(integer(col)?)),
+        _ => return Err(ExcelError::Value),
+    };
+
+    let (rows, cols) = array.dimensions();
+    let (row, col) = match col {
+        Some(col) => (row, col),
+        None if rows == 1 => (1, row),
+        None if cols == 1 => (row, 1),
+        None => (row, 0),
+    };
+    if row < 0 || col < 0 {
+        return Err(ExcelError::Value);
+    }
+    if row as usize > rows || col as usize > cols {
+        return Err(ExcelError::Ref);
+    }
+
+    let (used_rows, used_cols) = array.used();
+    let selected = |index: i64, used: usize| match index {
+        0 => 0..used,
+        index => index as usize - 1..index as usize,
+    };
+    Ok(array.slice(selected(row, used_rows), selected(col, used_cols)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(values: &[f64]) -> Grid<'static> {
+        Grid::Values(values.iter().map(|n| vec![CellValue::Number(*n)]).collect())
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(Pattern::new("a*c").matches("ABBC"));
+        assert!(Pattern::new("a?c").matches("abc"));
+        assert!(!Pattern::new("a?c").matches("ac"));
+        assert!(Pattern::new("*b*").matches("abc"));
+        assert!(Pattern::new("~*").matches("*"));
+        assert!(!Pattern::new("~*").matches("a"));
+        assert!(Pattern::new("a*b*c").matches("aXbYbc"));
+        assert!(Pattern::new("*").matches(""));
+    }
+
+    #[test]
+    fn test_searching() {
+        let grid = column(&[1.0, 3.0, 3.0, 5.0, 7.0]);
+        let line = Line::of(&grid).unwrap();
+        let n = CellValue::Number;
+
+        assert_eq!(line.find(&n(3.0), MatchMode::Exact, SearchMode::FirstToLast), Some(1));
+        assert_eq!(line.find(&n(3.0), MatchMode::Exact, SearchMode::LastToFirst), Some(2));
+        assert_eq!(line.find(&n(4.0), MatchMode::Exact, SearchMode::FirstToLast), None);
+        assert_eq!(line.find(&n(4.0), MatchMode::NextSmaller, SearchMode::FirstToLast), Some(1));
+        assert_eq!(line.find(&n(4.0), MatchMode::NextLarger, SearchMode::LastToFirst), Some(3));
+        assert_eq!(line.find(&n(6.0), MatchMode::NextSmaller, SearchMode::Ascending), Some(3));
+        assert_eq!(line.find(&n(6.0), MatchMode::NextLarger, SearchMode::Ascending), Some(4));
+        assert_eq!(line.find(&n(0.0), MatchMode::NextSmaller, SearchMode::Ascending), None);
+        assert_eq!(line.find(&n(9.0), MatchMode::NextLarger, SearchMode::Ascending), None);
+        assert_eq!(line.find(&n(7.0), MatchMode::Exact, SearchMode::Ascending), Some(4));
+
+        let descending = column(&[9.0, 6.0, 4.0, 1.0]);
+        let line = Line::of(&descending).unwrap();
+        assert_eq!(line.find(&n(5.0), MatchMode::NextLarger, SearchMode::Descending), Some(1));
+        assert_eq!(line.find(&n(5.0), MatchMode::NextSmaller, SearchMode::Descending), Some(2));
+        assert_eq!(line.find(&n(4.0), MatchMode::Exact, SearchMode::Descending), Some(2));
+    }
+
+    #[test]
+    fn test_binary_search_skips_other_types() {
+        let text = |s: &str| CellValue::Text(s.to_string());
+        let grid = Grid::Values(
+            [CellValue::Number(1.0), text("x"), CellValue::Empty, CellValue::Number(4.0), CellValue::Empty]
+                .into_iter()
+                .map(|value| vec![value])
+                .collect(),
+        );
+        let line = Line::of(&grid).unwrap();
+
+        assert_eq!(line.find(&CellValue::Number(3.0), MatchMode::NextSmaller, SearchMode::Ascending), Some(0));
+        assert_eq!(line.find(&CellValue::Number(9.0), MatchMode::NextSmaller, SearchMode::Ascending), Some(3));
+        assert_eq!(line.find(&CellValue::Number(2.0), MatchMode::NextLarger, SearchMode::Ascending), Some(3));
+        assert_eq!(line.find(&text("X"), MatchMode::Exact, SearchMode::Ascending), Some(1));
+    }
+}
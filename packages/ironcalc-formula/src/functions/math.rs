@@ -0,0 +1,112 @@
+//! Math functions: `RAND`, `RANDBETWEEN` and `SUMPRODUCT`
+
+use super::{Arg, ArgKind, Category, Function, FunctionRegistry};
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::value::CellValue;
+
+pub fn register(registry: &mut FunctionRegistry) {
+    registry.register(Function::new("RAND", Category::Math, (0, Some(0)), &[], rand).volatile());
+    registry.register(
+        Function::new("RANDBETWEEN", Category::Math, (2, Some(2)), &[ArgKind::Value], randbetween).volatile(),
+    );
+    registry.register(Function::new("SUMPRODUCT", Category::Math, (1, Some(255)), &[ArgKind::Value], sumproduct));
+}
+
+fn rand(_: &Evaluator, _: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    Ok(CellValue::Number(random()))
+}
+
+/// A random integer between the bounds, which are rounded inwards.
+fn randbetween(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let [Arg::Value(bottom), Arg::Value(top)] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    let (bottom, top) = (bottom.to_number()?.ceil(), top.to_number()?.floor());
+    if bottom > top {
+        return Err(ExcelError::Num);
+    }
+    Ok(CellValue::Number((random() * (top - bottom + 1.0)).floor() + bottom))
+}
+
+/// The sum of the products of the corresponding elements of arrays of the
+/// same size, which is `#VALUE!` otherwise. Elements that are not numbers
+/// count as 0, and an error in any array is the result.
+fn sumproduct(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let arrays: Vec<CellValue> = args
+        .into_iter()
+        .map(|arg| match arg {
+            Arg::Value(value) => Ok(value),
+            _ => Err(ExcelError::Value),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let (rows, cols) = arrays[0].dimensions();
+    if arrays.iter().any(|array| array.dimensions() != (rows, cols)) {
+        return Err(ExcelError::Value);
+    }
+
+    let mut total = 0.0;
+    for row in 0..rows {
+        for col in 0..cols {
+            let mut product = 1.0;
+            for array in &arrays {
+                product *= match array.broadcast_at(row, col) {
+                    Some(CellValue::Number(n)) => *n,
+                    Some(CellValue::Error(error)) => return Err(*error),
+                    _ => 0.0,
+                };
+            }
+            total += product;
+        }
+    }
+    Ok(CellValue::Number(total))
+}
+
+/// A uniformly distributed number in [0, 1), from the JS generator when
+/// running as WASM
+fn random() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Math::random()
+    }
+
+    // Native builds (unit tests) use a xorshift generator seeded from the
+    // clock
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::cell::Cell;
+
+        thread_local! {
+            static STATE: Cell<u64> = Cell::new(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or(0)
+                    | 1,
+            );
+        }
+
+        STATE.with(|state| {
+            let mut x = state.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+            (x >> 11) as f64 / (1u64 << 53) as f64
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_range() {
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&random()));
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Function registry
+//!
+//! Every function a formula can call is described by a [`Function`]: its
+//! name, category, arity, how each argument is passed, whether it is
+//! volatile, and the implementation. The evaluator checks the arity and
+//! prepares the arguments according to their [`ArgKind`], so
+//! implementations only deal with values they can use. Adding a function
+//! means writing its implementation and registering it in one of the
+//! category modules.
+
+mod aggregate;
+pub mod custom;
+mod date;
+mod information;
+mod lambda;
+mod logical;
+mod lookup;
+mod math;
+mod text;
+
+use crate::error::ExcelError;
+use crate::evaluator::Evaluator;
+use crate::grid::Grid;
+use crate::parser::Expr;
+use crate::value::CellValue;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Function categories, reported as the `formula_type` of formulas whose
+/// outermost expression calls the function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Aggregate,
+    Date,
+    Information,
+    Logical,
+    Lookup,
+    Math,
+    Text,
+    /// Registered at runtime, e.g. from JavaScript
+    Custom,
+}
+
+impl Category {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Aggregate => "aggregate",
+            Category::Date => "date",
+            Category::Information => "information",
+            Category::Logical => "logical",
+            Category::Lookup => "lookup",
+            Category::Math => "math",
+            Category::Text => "text",
+            Category::Custom => "custom",
+        }
+    }
+}
+
+/// How an argument is passed to the implementation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgKind {
+    /// Evaluated before the call. An error stops the call and becomes its
+    /// result, as for most Excel functions.
+    Value,
+    /// The cells of a cell, range or 3D reference, which functions such as
+    /// `SUM` treat differently from values typed into the argument list.
+    /// Any other expression is passed as its value. Errors are left to the
+    /// function.
+    Reference,
+    /// A range searched cell by cell, as lookup functions do. References
+    /// are read where their cells are stored instead of being copied into
+    /// an array; any other expression is passed as its value. Errors stop
+    /// the call as for `Value`.
+    Grid,
+    /// Not evaluated; the function evaluates it only if it needs it, as
+    /// `IF` does with its branches.
+    Lazy,
+}
+
+/// The values of the populated cells a reference argument covers.
+pub type Cells<'a> = Box<dyn Iterator<Item = Result<CellValue, ExcelError>> + 'a>;
+
+/// An argument prepared according to its [`ArgKind`].
+pub enum Arg<'a> {
+    Value(CellValue),
+    Reference(Cells<'a>),
+    /// A `Reference` argument that is not a reference
+    Direct(Result<CellValue, ExcelError>),
+    Grid(Grid<'a>),
+    Lazy(&'a Expr),
+}
+
+pub type Implementation = fn(&Evaluator, Vec<Arg>) -> Result<CellValue, ExcelError>;
+
+type Callable = dyn Fn(&Evaluator, Vec<Arg>) -> Result<CellValue, ExcelError>;
+
+pub struct Function {
+    pub name: String,
+    pub category: Category,
+    pub min_args: usize,
+    /// `None` for functions taking any number of arguments
+    pub max_args: Option<usize>,
+    /// Kinds of the arguments by position; the last one applies to any
+    /// further arguments
+    pub arg_kinds: &'static [ArgKind],
+    /// Volatile functions, such as `RAND`, give a different result each
+    /// time they are calculated, so their cells are recalculated by every
+    /// recalculation and their results are never cached
+    pub volatile: bool,
+    pub implementation: Rc<Callable>,
+}
+
+impl Function {
+    pub fn new(
+        name: &str,
+        category: Category,
+        arity: (usize, Option<usize>),
+        arg_kinds: &'static [ArgKind],
+        implementation: impl Fn(&Evaluator, Vec<Arg>) -> Result<CellValue, ExcelError> + 'static,
+    ) -> Function {
+        Function {
+            name: name.to_string(),
+            category,
+            min_args: arity.0,
+            max_args: arity.1,
+            arg_kinds,
+            volatile: false,
+            implementation: Rc::new(implementation),
+        }
+    }
+
+    pub fn volatile(mut self) -> Function {
+        self.volatile = true;
+        self
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min_args && self.max_args.is_none_or(|max| count <= max)
+    }
+
+    pub fn arg_kind(&self, index: usize) -> ArgKind {
+        self.arg_kinds
+            .get(index)
+            .or(self.arg_kinds.last())
+            .copied()
+            .unwrap_or(ArgKind::Value)
+    }
+}
+
+pub struct FunctionRegistry {
+    functions: HashMap<String, Function>,
+}
+
+impl FunctionRegistry {
+    /// A registry holding the built-in functions.
+    pub fn new() -> Self {
+        let mut registry = FunctionRegistry {
+            functions: HashMap::new(),
+        };
+        aggregate::register(&mut registry);
+        date::register(&mut registry);
+        information::register(&mut registry);
+        lambda::register(&mut registry);
+        logical::register(&mut registry);
+        lookup::register(&mut registry);
+        math::register(&mut registry);
+        text::register(&mut registry);
+        registry
+    }
+
+    /// Add a function, replacing any function of the same name.
+    pub fn register(&mut self, function: Function) {
+        self.functions.insert(function.name.to_uppercase(), function);
+    }
+
+    /// Look a function up by its name as the parser produces it, in upper
+    /// case.
+    pub fn get(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// Whether an expression calls a volatile function anywhere.
+    pub fn is_volatile(&self, expr: &Expr) -> bool {
+        calls_any(expr, &|name| self.get(name).is_some_and(|function| function.volatile))
+    }
+}
+
+/// Apply a function of single values to `Value` arguments, element by
+/// element when any of them is an array, as Excel does when such functions
+/// are given ranges. Arrays are stretched as operators stretch them, and
+/// each element that fails holds its error.
+pub fn lift(args: Vec<Arg>, apply: impl Fn(&[CellValue]) -> Result<CellValue, ExcelError>) -> Result<CellValue, ExcelError> {
+    let values = args
+        .into_iter()
+        .map(|arg| match arg {
+            Arg::Value(value) => Ok(value),
+            _ => Err(ExcelError::Value),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !values.iter().any(|value| matches!(value, CellValue::Array(_))) {
+        return apply(&values);
+    }
+
+    let (rows, cols) = values
+        .iter()
+        .map(CellValue::dimensions)
+        .fold((1, 1), |(rows, cols), (height, width)| (rows.max(height), cols.max(width)));
+    let element = |row: usize, col: usize| {
+        let elements: Vec<CellValue> = values
+            .iter()
+            .map(|value| value.broadcast_at(row, col).cloned().unwrap_or(CellValue::Error(ExcelError::NA)))
+            .collect();
+        apply(&elements).unwrap_or_else(CellValue::Error)
+    };
+    Ok(CellValue::Array((0..rows).map(|row| (0..cols).map(|col| element(row, col)).collect()).collect()))
+}
+
+/// The value of an optional `Lazy` argument, `None` when it is left out
+/// or left empty, as in `XMATCH(1, A:A, , 2)`.
+pub fn optional(evaluator: &Evaluator, arg: Option<&Arg>) -> Result<Option<CellValue>, ExcelError> {
+    match arg {
+        None | Some(Arg::Lazy(Expr::Missing)) => Ok(None),
+        Some(Arg::Lazy(expr)) => evaluator.evaluate(expr).map(Some),
+        Some(_) => Err(ExcelError::Value),
+    }
+}
+
+/// Whether an expression calls, anywhere, a function whose name satisfies
+/// `matches`.
+pub fn calls_any(expr: &Expr, matches: &dyn Fn(&str) -> bool) -> bool {
+    match expr {
+        Expr::Unary { operand, .. } => calls_any(operand, matches),
+        Expr::Binary { left, right, .. } => calls_any(left, matches) || calls_any(right, matches),
+        Expr::Function { name, args } => matches(name) || args.iter().any(|arg| calls_any(arg, matches)),
+        Expr::Call { callee, args } => calls_any(callee, matches) || args.iter().any(|arg| calls_any(arg, matches)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn test_registry_metadata() {
+        let registry = FunctionRegistry::new();
+
+        let sum = registry.get("SUM").unwrap();
+        assert_eq!(sum.category, Category::Aggregate);
+        assert!(!sum.accepts(0));
+        assert!(sum.accepts(255));
+        assert_eq!(sum.arg_kind(7), ArgKind::Reference);
+
+        let iferror = registry.get("IFERROR").unwrap();
+        assert_eq!((iferror.min_args, iferror.max_args), (2, Some(2)));
+        assert_eq!(iferror.arg_kind(1), ArgKind::Lazy);
+        assert!(registry.get("sum").is_none());
+    }
+
+    #[test]
+    fn test_volatility() {
+        let registry = FunctionRegistry::new();
+
+        assert!(registry.is_volatile(&parse("IF(A1,1,RAND()*2)").unwrap()));
+        assert!(!registry.is_volatile(&parse("SUM(A1:A3)+1").unwrap()));
+    }
+}
@@ -0,0 +1,593 @@
+//! Text functions: `LEFT`, `RIGHT`, `MID`, `LEN`, `FIND`, `SEARCH`,
+//! `SUBSTITUTE`, `REPLACE`, `TRIM`, `UPPER`, `LOWER`, `PROPER`, `REPT`,
+//! `CONCAT`, `TEXTJOIN`, `EXACT`, `CHAR`, `CODE`, `UNICHAR`, `UNICODE`,
+//! `CLEAN`, `TEXTBEFORE`, `TEXTAFTER` and `TEXTSPLIT`
+//!
+//! Lengths and positions count UTF-16 code units, as Excel's do: a
+//! character outside the Basic Multilingual Plane, such as most emoji,
+//! counts as 2, and half of one cut off by `LEFT` or `MID` becomes U+FFFD.
+//! Changing case never changes a length either. `CHAR` and `CODE` use the
+//! Windows-1252 code page. Text results are limited to Excel's 32,767
+//! characters. Functions of single values apply to each element of an
+//! array argument.
+
+use super::lookup::Pattern;
+use super::{lift, optional, Arg, ArgKind, Category, Function, FunctionRegistry, Implementation};
+use crate::error::ExcelError;
+use crate::evaluator::{top_left, Evaluator};
+use crate::value::{limited_text, CellValue, MAX_TEXT};
+
+pub fn register(registry: &mut FunctionRegistry) {
+    let functions: [(&str, usize, usize, Implementation); 19] = [
+        ("LEFT", 1, 2, left),
+        ("RIGHT", 1, 2, right),
+        ("MID", 3, 3, mid),
+        ("LEN", 1, 1, len),
+        ("FIND", 2, 3, find),
+        ("SEARCH", 2, 3, search),
+        ("SUBSTITUTE", 3, 4, substitute),
+        ("REPLACE", 4, 4, replace),
+        ("TRIM", 1, 1, trim),
+        ("UPPER", 1, 1, upper),
+        ("LOWER", 1, 1, lower),
+        ("PROPER", 1, 1, proper),
+        ("REPT", 2, 2, rept),
+        ("EXACT", 2, 2, exact),
+        ("CHAR", 1, 1, char),
+        ("CODE", 1, 1, code),
+        ("UNICHAR", 1, 1, unichar),
+        ("UNICODE", 1, 1, unicode),
+        ("CLEAN", 1, 1, clean),
+    ];
+    for (name, min_args, max_args, implementation) in functions {
+        registry.register(Function::new(
+            name,
+            Category::Text,
+            (min_args, Some(max_args)),
+            &[ArgKind::Value],
+            implementation,
+        ));
+    }
+
+    registry.register(Function::new("CONCAT", Category::Text, (1, Some(253)), &[ArgKind::Grid], concat));
+    registry.register(Function::new(
+        "TEXTJOIN",
+        Category::Text,
+        (3, Some(252)),
+        &[ArgKind::Value, ArgKind::Value, ArgKind::Grid],
+        textjoin,
+    ));
+
+    let around: [(&str, Implementation); 2] = [("TEXTBEFORE", textbefore), ("TEXTAFTER", textafter)];
+    for (name, implementation) in around {
+        registry.register(Function::new(
+            name,
+            Category::Text,
+            (2, Some(6)),
+            &[ArgKind::Value, ArgKind::Value, ArgKind::Lazy],
+            implementation,
+        ));
+    }
+    registry.register(Function::new(
+        "TEXTSPLIT",
+        Category::Text,
+        (2, Some(6)),
+        &[ArgKind::Value, ArgKind::Lazy],
+        textsplit,
+    ));
+}
+
+fn utf16(value: &CellValue) -> Result<Vec<u16>, ExcelError> {
+    Ok(value.to_text()?.encode_utf16().collect())
+}
+
+fn from_utf16(units: &[u16]) -> CellValue {
+    CellValue::Text(String::from_utf16_lossy(units))
+}
+
+/// A number of characters, truncated; negative counts are `#VALUE!`.
+fn count(value: &CellValue) -> Result<usize, ExcelError> {
+    let count = value.to_number()?.trunc();
+    if count < 0.0 {
+        return Err(ExcelError::Value);
+    }
+    Ok(count as usize)
+}
+
+/// A position counted from 1, truncated; positions below 1 are `#VALUE!`.
+fn position(value: &CellValue) -> Result<usize, ExcelError> {
+    let position = value.to_number()?.trunc();
+    if position < 1.0 {
+        return Err(ExcelError::Value);
+    }
+    Ok(position as usize)
+}
+
+/// `LEFT(text, [count])`, 1 character by default.
+fn left(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let units = utf16(&args[0])?;
+        let count = args.get(1).map_or(Ok(1), count)?;
+        Ok(from_utf16(&units[..count.min(units.len())]))
+    })
+}
+
+/// `RIGHT(text, [count])`, 1 character by default.
+fn right(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let units = utf16(&args[0])?;
+        let count = args.get(1).map_or(Ok(1), count)?;
+        Ok(from_utf16(&units[units.len() - count.min(units.len())..]))
+    })
+}
+
+/// `MID(text, start, count)`; empty when `start` is past the end.
+fn mid(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let units = utf16(&args[0])?;
+        let start = (position(&args[1])? - 1).min(units.len());
+        let end = start + count(&args[2])?.min(units.len() - start);
+        Ok(from_utf16(&units[start..end]))
+    })
+}
+
+fn len(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| Ok(CellValue::Number(args[0].to_text()?.encode_utf16().count() as f64)))
+}
+
+/// `FIND(find_text, within_text, [start])` is the position of the first
+/// occurrence from `start` on, matching case; `#VALUE!` when there is none.
+fn find(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let (needle, within) = (utf16(&args[0])?, utf16(&args[1])?);
+        let start = args.get(2).map_or(Ok(1), position)?;
+        if start > within.len() + 1 {
+            return Err(ExcelError::Value);
+        }
+
+        (start - 1..=within.len())
+            .find(|&index| within[index..].starts_with(&needle))
+            .map(|index| CellValue::Number(index as f64 + 1.0))
+            .ok_or(ExcelError::Value)
+    })
+}
+
+/// `SEARCH(find_text, within_text, [start])` is `FIND` ignoring case, with
+/// the wildcards `*` and `?`.
+fn search(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let pattern = Pattern::new(&args[0].to_text()?);
+        let within: Vec<char> = args[1].to_text()?.chars().collect();
+        let start = args.get(2).map_or(Ok(1), position)?;
+        if start > within.iter().map(|c| c.len_utf16()).sum::<usize>() + 1 {
+            return Err(ExcelError::Value);
+        }
+
+        // Candidates start at each character, counted in UTF-16 units
+        let mut unit = 0;
+        for index in 0..=within.len() {
+            if unit + 1 >= start && pattern.matches_start(&within[index..]) {
+                return Ok(CellValue::Number(unit as f64 + 1.0));
+            }
+            unit += within.get(index).map_or(0, |c| c.len_utf16());
+        }
+        Err(ExcelError::Value)
+    })
+}
+
+/// `SUBSTITUTE(text, old_text, new_text, [instance])` replaces every
+/// occurrence of `old_text`, matching case, or only the one given.
+fn substitute(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let (text, old, new) = (args[0].to_text()?, args[1].to_text()?, args[2].to_text()?);
+        let instance = args.get(3).map(position).transpose()?;
+        if old.is_empty() {
+            return Ok(CellValue::Text(text));
+        }
+
+        match instance {
+            None => limited_text(text.replace(&old, &new)),
+            Some(instance) => match text.match_indices(&old).nth(instance - 1) {
+                Some((index, _)) => limited_text(format!("{}{}{}", &text[..index], new, &text[index + old.len()..])),
+                None => Ok(CellValue::Text(text)),
+            },
+        }
+    })
+}
+
+/// `REPLACE(text, start, count, new_text)` replaces `count` characters
+/// from `start` on.
+fn replace(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let mut units = utf16(&args[0])?;
+        let start = (position(&args[1])? - 1).min(units.len());
+        let end = start + count(&args[2])?.min(units.len() - start);
+        units.splice(start..end, utf16(&args[3])?);
+        limited_text(String::from_utf16_lossy(&units))
+    })
+}
+
+/// `TRIM(text)` removes spaces from both ends and all but one between
+/// words. Only the ASCII space counts, as in Excel.
+fn trim(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let text = args[0].to_text()?;
+        Ok(CellValue::Text(text.split(' ').filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")))
+    })
+}
+
+fn upper(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| Ok(CellValue::Text(args[0].to_text()?.chars().map(to_upper).collect())))
+}
+
+fn lower(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| Ok(CellValue::Text(args[0].to_text()?.chars().map(to_lower).collect())))
+}
+
+/// The upper case of a character. Changing case never changes the length
+/// of a text in Excel, so a character whose upper case is several, such as
+/// "ß", is kept as it is.
+fn to_upper(c: char) -> char {
+    single_char(c, c.to_uppercase())
+}
+
+/// The lower case of a character, kept as it is when that is several.
+fn to_lower(c: char) -> char {
+    single_char(c, c.to_lowercase())
+}
+
+fn single_char(c: char, mut mapped: impl Iterator<Item = char>) -> char {
+    match (mapped.next(), mapped.next()) {
+        (Some(mapped), None) if mapped.len_utf16() == c.len_utf16() => mapped,
+        _ => c,
+    }
+}
+
+/// `PROPER(text)` capitalises every letter that follows a character other
+/// than a letter and lowers the rest, so "o'neil" becomes "O'Neil".
+fn proper(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let text = args[0].to_text()?;
+        let mut proper = String::with_capacity(text.len());
+        let mut after_letter = false;
+        for c in text.chars() {
+            proper.push(if after_letter { to_lower(c) } else { to_upper(c) });
+            after_letter = c.is_alphabetic();
+        }
+        limited_text(proper)
+    })
+}
+
+/// `REPT(text, times)`; `#VALUE!` when the result would be too long.
+fn rept(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let (text, times) = (args[0].to_text()?, count(&args[1])?);
+        if text.encode_utf16().count().saturating_mul(times) > MAX_TEXT {
+            return Err(ExcelError::Value);
+        }
+        Ok(CellValue::Text(text.repeat(times)))
+    })
+}
+
+/// `EXACT(text1, text2)` compares matching case.
+fn exact(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| Ok(CellValue::Boolean(args[0].to_text()? == args[1].to_text()?)))
+}
+
+/// `CHAR(number)` is the character with a Windows-1252 code from 1 to 255.
+fn char(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let code = args[0].to_number()?.trunc();
+        if !(1.0..=255.0).contains(&code) {
+            return Err(ExcelError::Value);
+        }
+        Ok(CellValue::Text(from_windows_1252(code as u8).to_string()))
+    })
+}
+
+/// `CODE(text)` is the Windows-1252 code of the first character, 63 (`?`)
+/// for characters the code page lacks.
+fn code(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let first = args[0].to_text()?.chars().next().ok_or(ExcelError::Value)?;
+        Ok(CellValue::Number(to_windows_1252(first).unwrap_or(b'?') as f64))
+    })
+}
+
+/// `UNICHAR(number)` is the character with a Unicode code point. Code
+/// points reserved for UTF-16 surrogates are `#N/A`.
+fn unichar(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let code = args[0].to_number()?.trunc();
+        if (0xD800 as f64..=0xDFFF as f64).contains(&code) {
+            return Err(ExcelError::NA);
+        }
+        match code {
+            1.0..=1_114_111.0 => char::from_u32(code as u32)
+                .map(|c| CellValue::Text(c.to_string()))
+                .ok_or(ExcelError::Value),
+            _ => Err(ExcelError::Value),
+        }
+    })
+}
+
+/// `UNICODE(text)` is the code point of the first character, a surrogate
+/// pair counting as one.
+fn unicode(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| {
+        let first = args[0].to_text()?.chars().next().ok_or(ExcelError::Value)?;
+        Ok(CellValue::Number(first as u32 as f64))
+    })
+}
+
+/// `CLEAN(text)` removes the non-printing ASCII control characters 0 to 31.
+fn clean(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    lift(args, |args| Ok(CellValue::Text(args[0].to_text()?.chars().filter(|c| *c >= ' ').collect())))
+}
+
+/// The values of a `Grid` argument in row-major order, blanks included.
+fn grid_values<'g>(arg: &'g Arg) -> Result<impl Iterator<Item = CellValue> + 'g, ExcelError> {
+    let Arg::Grid(grid) = arg else {
+        return Err(ExcelError::Value);
+    };
+    let (rows, cols) = grid.used();
+    Ok((0..rows).flat_map(move |row| (0..cols).map(move |col| grid.get(row, col))))
+}
+
+/// `CONCAT(text1, ...)` joins its arguments and the cells of ranges.
+fn concat(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let mut joined = String::new();
+    for arg in &args {
+        for value in grid_values(arg)? {
+            joined.push_str(&value.to_text()?);
+        }
+    }
+    limited_text(joined)
+}
+
+/// `TEXTJOIN(delimiter, ignore_empty, text1, ...)` joins its arguments and
+/// the cells of ranges with a delimiter, or with each element of an array
+/// of delimiters in turn.
+fn textjoin(_: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let [Arg::Value(delimiter), Arg::Value(ignore_empty), texts @ ..] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+
+    let delimiters = delimiter
+        .clone()
+        .into_rows()
+        .into_iter()
+        .flatten()
+        .map(|delimiter| delimiter.to_text())
+        .collect::<Result<Vec<_>, _>>()?;
+    let ignore_empty = ignore_empty.to_bool()?;
+
+    let mut joined = String::new();
+    let mut count = 0;
+    for arg in texts {
+        for value in grid_values(arg)? {
+            let text = value.to_text()?;
+            if ignore_empty && text.is_empty() {
+                continue;
+            }
+            if count > 0 {
+                joined.push_str(&delimiters[(count - 1) % delimiters.len()]);
+            }
+            joined.push_str(&text);
+            count += 1;
+        }
+    }
+    limited_text(joined)
+}
+
+/// The delimiters of `TEXTBEFORE`, `TEXTAFTER` and `TEXTSPLIT`: a text, or
+/// each element of an array of texts.
+fn delimiters(value: CellValue) -> Result<Vec<Vec<u16>>, ExcelError> {
+    value.into_rows().into_iter().flatten().map(|delimiter| utf16(&delimiter)).collect()
+}
+
+/// Whether a `match_mode` argument ignores case: 0, the default, matches
+/// case and 1 ignores it.
+fn ignore_case(match_mode: Option<CellValue>) -> Result<bool, ExcelError> {
+    let Some(match_mode) = match_mode else {
+        return Ok(false);
+    };
+    match match_mode.to_number()?.trunc() as i64 {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ExcelError::Value),
+    }
+}
+
+/// UTF-16 text in lower case, with as many code units.
+fn lowered(units: &[u16]) -> Vec<u16> {
+    char::decode_utf16(units.iter().copied())
+        .map(|c| to_lower(c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+        .collect::<String>()
+        .encode_utf16()
+        .collect()
+}
+
+/// The start and end, in UTF-16 code units, of each occurrence of any of
+/// the delimiters, found from the left without overlapping. Where several
+/// occur at one place the longest is taken. Empty delimiters occur nowhere.
+fn occurrences(text: &[u16], delimiters: &[Vec<u16>], ignore_case: bool) -> Vec<(usize, usize)> {
+    let (text, delimiters) = if ignore_case {
+        (lowered(text), delimiters.iter().map(|delimiter| lowered(delimiter)).collect())
+    } else {
+        (text.to_vec(), delimiters.to_vec())
+    };
+    let mut found = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let rest = &text[start..];
+        let longest = delimiters
+            .iter()
+            .filter(|delimiter| !delimiter.is_empty() && rest.starts_with(delimiter))
+            .map(Vec::len)
+            .max();
+        match longest {
+            Some(len) => {
+                found.push((start, start + len));
+                start += len;
+            }
+            None => start += 1,
+        }
+    }
+    found
+}
+
+/// The parts of a text between occurrences of the delimiters.
+fn split<'t>(text: &'t [u16], delimiters: &[Vec<u16>], ignore_case: bool) -> Vec<&'t [u16]> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (from, to) in occurrences(text, delimiters, ignore_case) {
+        parts.push(&text[start..from]);
+        start = to;
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// `TEXTBEFORE(text, delimiter, [instance], [match_mode], [match_end],
+/// [if_not_found])` is the text before an occurrence of the delimiter, or
+/// of any of an array of delimiters.
+fn textbefore(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    text_around(evaluator, args, true)
+}
+
+/// `TEXTAFTER(text, delimiter, [instance], [match_mode], [match_end],
+/// [if_not_found])` is the text after an occurrence of the delimiter.
+fn textafter(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    text_around(evaluator, args, false)
+}
+
+/// The text before or after the `instance`th occurrence of a delimiter,
+/// the first by default and counted from the end when negative. With
+/// `match_end` the end of the text, or its start counting from the end, is
+/// one more occurrence. An empty delimiter occurs only at the start, or at
+/// the end counting from the end. When there is no such occurrence the
+/// result is `if_not_found`, `#N/A` by default. Each element of an array of
+/// texts is cut in turn.
+fn text_around(evaluator: &Evaluator, args: Vec<Arg>, before: bool) -> Result<CellValue, ExcelError> {
+    let [Arg::Value(text), Arg::Value(delimiter), options @ ..] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+    let delimiters = delimiters(delimiter.clone())?;
+    let instance = optional(evaluator, options.first())?.map_or(Ok(1.0), |instance| instance.to_number())?.trunc() as i64;
+    let ignore_case = ignore_case(optional(evaluator, options.get(1))?)?;
+    let match_end = optional(evaluator, options.get(2))?.map_or(Ok(false), |match_end| match_end.to_bool())?;
+    if instance == 0 {
+        return Err(ExcelError::Value);
+    }
+
+    lift(vec![Arg::Value(text.clone())], |values| {
+        let text = utf16(&values[0])?;
+        if instance.unsigned_abs() as usize > text.len() {
+            return Err(ExcelError::Value);
+        }
+
+        let found = if delimiters.iter().any(Vec::is_empty) {
+            vec![if instance > 0 { (0, 0) } else { (text.len(), text.len()) }]
+        } else {
+            occurrences(&text, &delimiters, ignore_case)
+        };
+        let count = found.len() as i64;
+        let index = if instance > 0 { instance - 1 } else { count + instance };
+        let (start, end) = match index {
+            0.. if index < count => found[index as usize],
+            _ if match_end && index == count => (text.len(), text.len()),
+            -1 if match_end => (0, 0),
+            _ => return optional(evaluator, options.get(3))?.ok_or(ExcelError::NA),
+        };
+        let part = if before { &text[..start] } else { &text[end..] };
+        Ok(from_utf16(part))
+    })
+}
+
+/// `TEXTSPLIT(text, col_delimiter, [row_delimiter], [ignore_empty],
+/// [match_mode], [pad_with])` splits a text into rows at the row delimiters
+/// and each row into columns at the column delimiters, giving an array of
+/// texts that spills. Either kind of delimiter may be left out, but not
+/// both. With `ignore_empty` empty parts are dropped rather than kept as
+/// empty texts. Rows shorter than the longest are padded with `pad_with`,
+/// `#N/A` by default.
+fn textsplit(evaluator: &Evaluator, args: Vec<Arg>) -> Result<CellValue, ExcelError> {
+    let [Arg::Value(text), options @ ..] = args.as_slice() else {
+        return Err(ExcelError::Value);
+    };
+    if matches!(text, CellValue::Array(_)) {
+        return Err(ExcelError::Value);
+    }
+    let text = utf16(text)?;
+    let col_delimiters = optional(evaluator, options.first())?.map(delimiters).transpose()?.unwrap_or_default();
+    let row_delimiters = optional(evaluator, options.get(1))?.map(delimiters).transpose()?.unwrap_or_default();
+    let ignore_empty = optional(evaluator, options.get(2))?.map_or(Ok(false), |ignore_empty| ignore_empty.to_bool())?;
+    let ignore_case = ignore_case(optional(evaluator, options.get(3))?)?;
+    let pad = match optional(evaluator, options.get(4)) {
+        Ok(pad) => pad.map_or(CellValue::Error(ExcelError::NA), |pad| top_left(&pad).clone()),
+        Err(error) => CellValue::Error(error),
+    };
+    if col_delimiters.is_empty() && row_delimiters.is_empty()
+        || col_delimiters.iter().chain(&row_delimiters).any(Vec::is_empty)
+    {
+        return Err(ExcelError::Value);
+    }
+
+    let keep = |part: &&[u16]| !ignore_empty || !part.is_empty();
+    let rows: Vec<Vec<&[u16]>> = split(&text, &row_delimiters, ignore_case)
+        .into_iter()
+        .filter(keep)
+        .map(|row| split(row, &col_delimiters, ignore_case).into_iter().filter(keep).collect::<Vec<_>>())
+        .filter(|row| !row.is_empty())
+        .collect();
+    let width = rows.iter().map(Vec::len).max().ok_or(ExcelError::Calc)?;
+    Ok(CellValue::Array(
+        rows.into_iter()
+            .map(|row| {
+                let mut cells: Vec<CellValue> = row.into_iter().map(from_utf16).collect();
+                cells.resize(width, pad.clone());
+                cells
+            })
+            .collect(),
+    ))
+}
+
+/// Windows-1252 differs from Latin-1 in 0x80 to 0x9F. The five codes it
+/// leaves undefined map to the C1 control characters, as Windows does.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+fn from_windows_1252(code: u8) -> char {
+    match code {
+        0x80..=0x9F => WINDOWS_1252_HIGH[(code - 0x80) as usize],
+        _ => code as char,
+    }
+}
+
+fn to_windows_1252(c: char) -> Option<u8> {
+    match WINDOWS_1252_HIGH.iter().position(|&high| high == c) {
+        Some(index) => Some(0x80 + index as u8),
+        None => u8::try_from(c as u32).ok().filter(|code| !(0x80..=0x9F).contains(code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_1252() {
+        assert_eq!(from_windows_1252(65), 'A');
+        assert_eq!(from_windows_1252(0x80), '€');
+        assert_eq!(from_windows_1252(0xE9), 'é');
+        assert_eq!(to_windows_1252('€'), Some(0x80));
+        assert_eq!(to_windows_1252('é'), Some(0xE9));
+        assert_eq!(to_windows_1252('\u{80}'), None);
+        assert_eq!(to_windows_1252('😀'), None);
+    }
+}
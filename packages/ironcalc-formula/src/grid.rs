@@ -0,0 +1,117 @@
+//! Random access to the cells of a range
+//!
+//! A [`Grid`] is what lookup functions search. For a reference it reads the
+//! cells where they are stored rather than copying the range into an array
+//! first, so a binary search through a sorted column of 100,000 rows only
+//! reads the cells it probes. Any other value is searched as an array.
+
+use crate::evaluator::top_left;
+use crate::reference::{CellRef, RangeRef};
+use crate::value::CellValue;
+use crate::Worksheet;
+use std::ops::Range;
+
+pub enum Grid<'a> {
+    /// The cells of a range on a sheet
+    Cells { worksheet: &'a Worksheet, range: RangeRef },
+    /// The rows of an array value; a single value is a 1x1 array
+    Values(Vec<Vec<CellValue>>),
+}
+
+impl<'a> Grid<'a> {
+    pub fn cells(worksheet: &'a Worksheet, range: RangeRef) -> Self {
+        Grid::Cells { worksheet, range }
+    }
+
+    pub fn values(value: CellValue) -> Self {
+        Grid::Values(value.into_rows())
+    }
+
+    /// Height and width of the whole range or array.
+    pub fn dimensions(&self) -> (usize, usize) {
+        match self {
+            Grid::Cells { range, .. } => extent(*range),
+            Grid::Values(rows) => (rows.len(), rows.first().map_or(0, Vec::len)),
+        }
+    }
+
+    /// Height and width of the values the grid stands for. Whole columns
+    /// and whole rows are cut at the last populated row or column of their
+    /// sheet, as ranges used as values are.
+    pub fn used(&self) -> (usize, usize) {
+        match self {
+            Grid::Cells { worksheet, range } => extent(worksheet.value_range(*range)),
+            Grid::Values(_) => self.dimensions(),
+        }
+    }
+
+    /// Height and width of the part that can hold values. Past the last
+    /// populated row or column of the sheet every cell is blank, so
+    /// searches stop there.
+    pub fn searched(&self) -> (usize, usize) {
+        match self {
+            Grid::Cells { worksheet, range } => extent(worksheet.used_part(*range)),
+            Grid::Values(_) => self.dimensions(),
+        }
+    }
+
+    /// The value at `row`, `col`, counted from 0; blank outside the grid.
+    pub fn get(&self, row: usize, col: usize) -> CellValue {
+        match self {
+            Grid::Cells { worksheet, range } => worksheet
+                .get_cell(range.start.row + row as i32, range.start.col + col as i32)
+                .map(|cell| top_left(&cell.value).clone())
+                .unwrap_or_default(),
+            Grid::Values(rows) => rows.get(row).and_then(|cells| cells.get(col)).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// The positions and values of the cells holding something in the
+    /// first `len` cells of a column, or of a row when not `vertical`. A
+    /// search of a long, sparse range reads only the cells that are there.
+    pub fn populated(&self, vertical: bool, offset: usize, len: usize) -> Box<dyn DoubleEndedIterator<Item = (usize, CellValue)> + '_> {
+        match self {
+            Grid::Cells { worksheet, range } => {
+                let (first_row, first_col) = (range.start.row, range.start.col);
+                let (row, col) = (first_row + offset as i32, first_col + offset as i32);
+                let last = len as i32 - 1;
+                let line = if vertical {
+                    RangeRef::new(cell(first_row, col), cell(first_row + last, col))
+                } else {
+                    RangeRef::new(cell(row, first_col), cell(row, first_col + last))
+                };
+                Box::new(worksheet.cells_in_range(line).map(move |(&(row, col), cell)| {
+                    let position = if vertical { row - first_row } else { col - first_col };
+                    (position as usize, top_left(&cell.value).clone())
+                }))
+            }
+            Grid::Values(_) => Box::new((0..len).map(move |position| {
+                let value = if vertical { self.get(position, offset) } else { self.get(offset, position) };
+                (position, value)
+            })),
+        }
+    }
+
+    /// The values of some rows and columns, as an array or, for a single
+    /// cell, as its value.
+    pub fn slice(&self, rows: Range<usize>, cols: Range<usize>) -> CellValue {
+        if rows.len() == 1 && cols.len() == 1 {
+            return self.get(rows.start, cols.start);
+        }
+        CellValue::Array(rows.map(|row| cols.clone().map(|col| self.get(row, col)).collect()).collect())
+    }
+}
+
+fn cell(row: i32, col: i32) -> CellRef {
+    CellRef {
+        row,
+        col,
+        row_absolute: false,
+        col_absolute: false,
+    }
+}
+
+/// Height and width of a range.
+fn extent(range: RangeRef) -> (usize, usize) {
+    ((range.end.row - range.start.row + 1) as usize, (range.end.col - range.start.col + 1) as usize)
+}
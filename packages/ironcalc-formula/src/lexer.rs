@@ -0,0 +1,607 @@
+//! Formula tokenizer
+//!
+//! Splits the text of a formula (without the leading `=`) into tokens that the
+//! parser consumes. Every token records the byte span it was read from so that
+//! syntax errors can point at the offending position.
+
+use crate::error::ExcelError;
+use crate::reference::{quote_sheet_name, CellRef};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    Error(ExcelError),
+    /// A1-style cell reference such as `B2` or `$C$10`
+    Reference(String),
+    /// Reference to the spill range of the cell it names, e.g. `B2#`
+    SpillReference(String),
+    /// Sheet qualifier of a reference, e.g. `Sheet2!` or `'Q1 Sales'!`
+    SheetPrefix(String),
+    /// Sheet span of a 3D reference, e.g. `Jan:Dec!`
+    SheetRange(String, String),
+    /// Identifier immediately followed by `(`
+    Function(String),
+    Identifier(String),
+    /// Table name, if any, and the bracketed specifier of a structured
+    /// reference, e.g. `Sales` and `[@Amount]`
+    StructuredRef {
+        table: Option<String>,
+        spec: String,
+    },
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    Ampersand,
+    Percent,
+    /// Implicit intersection, as in `@A1:A10`
+    At,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    LeftParen,
+    RightParen,
+    Comma,
+    Colon,
+    /// Delimiters of an array constant, e.g. `{1,2;3,4}`
+    LeftBrace,
+    RightBrace,
+    /// Separates the rows of an array constant
+    Semicolon,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Number(n) => write!(f, "{}", n),
+            TokenKind::Text(s) => write!(f, "\"{}\"", s.replace('"', "\"\"")),
+            TokenKind::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            TokenKind::Error(e) => write!(f, "{}", e),
+            TokenKind::Reference(r) => write!(f, "{}", r),
+            TokenKind::SpillReference(r) => write!(f, "{}#", r),
+            TokenKind::SheetPrefix(sheet) => write!(f, "{}!", quote_sheet_name(sheet)),
+            TokenKind::SheetRange(first, last) => {
+                write!(f, "{}:{}!", quote_sheet_name(first), quote_sheet_name(last))
+            }
+            TokenKind::Function(name) => write!(f, "{}(", name),
+            TokenKind::Identifier(name) => write!(f, "{}", name),
+            TokenKind::StructuredRef { table, spec } => write!(f, "{}{}", table.as_deref().unwrap_or_default(), spec),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::Caret => write!(f, "^"),
+            TokenKind::Ampersand => write!(f, "&"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::At => write!(f, "@"),
+            TokenKind::Equal => write!(f, "="),
+            TokenKind::NotEqual => write!(f, "<>"),
+            TokenKind::Less => write!(f, "<"),
+            TokenKind::LessEqual => write!(f, "<="),
+            TokenKind::Greater => write!(f, ">"),
+            TokenKind::GreaterEqual => write!(f, ">="),
+            TokenKind::LeftParen => write!(f, "("),
+            TokenKind::RightParen => write!(f, ")"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::LeftBrace => write!(f, "{{"),
+            TokenKind::RightBrace => write!(f, "}}"),
+            TokenKind::Semicolon => write!(f, ";"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    /// Tokenize the whole input, failing on the first unrecognised character.
+    pub fn tokenize(mut self) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break,
+            };
+
+            let kind = match c {
+                '0'..='9' | '.' => self.read_number()?,
+                '"' => self.read_string()?,
+                '#' => self.read_error()?,
+                '\'' => self.read_quoted_sheet()?,
+                '[' => self.read_structured_ref(None)?,
+                c if is_identifier_start(c) => self.read_identifier()?,
+                _ => self.read_operator()?,
+            };
+
+            tokens.push(Token { kind, start, end: self.pos });
+        }
+
+        Ok(tokens)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    fn read_number(&mut self) -> Result<TokenKind, String> {
+        let start = self.pos;
+
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.bump();
+        }
+
+        if self.peek() == Some('.') {
+            self.bump();
+            while matches!(self.peek(), Some('0'..='9')) {
+                self.bump();
+            }
+        }
+
+        // Exponent, only consumed when followed by digits (optionally signed)
+        if matches!(self.peek(), Some('e') | Some('E')) {
+            let has_exponent = match self.peek_nth(1) {
+                Some('0'..='9') => true,
+                Some('+') | Some('-') => matches!(self.peek_nth(2), Some('0'..='9')),
+                _ => false,
+            };
+
+            if has_exponent {
+                self.bump();
+                if matches!(self.peek(), Some('+') | Some('-')) {
+                    self.bump();
+                }
+                while matches!(self.peek(), Some('0'..='9')) {
+                    self.bump();
+                }
+            }
+        }
+
+        let text = &self.input[start..self.pos];
+        // Like Excel, refuse numbers too large to hold rather than read them
+        // as infinity
+        text.parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .map(TokenKind::Number)
+            .ok_or_else(|| format!("Invalid number '{}' at position {}", text, start))
+    }
+
+    fn read_string(&mut self) -> Result<TokenKind, String> {
+        let start = self.pos;
+        self.bump(); // opening quote
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => {
+                    // A doubled quote is an escaped literal quote
+                    if self.peek() == Some('"') {
+                        self.bump();
+                        value.push('"');
+                    } else {
+                        return Ok(TokenKind::Text(value));
+                    }
+                }
+                Some(c) => value.push(c),
+                None => return Err(format!("Unterminated string starting at position {}", start)),
+            }
+        }
+    }
+
+    fn read_error(&mut self) -> Result<TokenKind, String> {
+        let rest = &self.input[self.pos..];
+
+        for error in ExcelError::ALL {
+            let literal = error.code();
+            if rest.len() >= literal.len()
+                && rest.is_char_boundary(literal.len())
+                && rest[..literal.len()].eq_ignore_ascii_case(literal)
+            {
+                self.pos += literal.len();
+                return Ok(TokenKind::Error(error));
+            }
+        }
+
+        Err(format!("Unknown error literal at position {}", self.pos))
+    }
+
+    /// A quoted sheet name such as `'Q1 Sales'!`, where `''` stands for a
+    /// literal apostrophe. A colon inside the quotes separates the two ends
+    /// of a 3D reference, since sheet names cannot contain one.
+    fn read_quoted_sheet(&mut self) -> Result<TokenKind, String> {
+        let start = self.pos;
+        self.bump(); // opening quote
+
+        let mut name = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => {
+                    if self.peek() == Some('\'') {
+                        self.bump();
+                        name.push('\'');
+                    } else {
+                        break;
+                    }
+                }
+                Some(c) => name.push(c),
+                None => return Err(format!("Unterminated sheet name starting at position {}", start)),
+            }
+        }
+
+        if self.bump() != Some('!') || name.is_empty() {
+            return Err(format!("Invalid sheet reference at position {}", start));
+        }
+        match name.split_once(':') {
+            Some((first, last)) if !first.is_empty() && !last.is_empty() => {
+                Ok(TokenKind::SheetRange(first.to_string(), last.to_string()))
+            }
+            Some(_) => Err(format!("Invalid sheet reference at position {}", start)),
+            None => Ok(TokenKind::SheetPrefix(name)),
+        }
+    }
+
+    fn read_identifier(&mut self) -> Result<TokenKind, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_identifier_char(c) {
+                break;
+            }
+            self.bump();
+        }
+        let text = &self.input[start..self.pos];
+
+        if self.peek() == Some('!') {
+            self.bump();
+            return Ok(TokenKind::SheetPrefix(text.to_string()));
+        }
+
+        if self.peek() == Some('[') {
+            return self.read_structured_ref(Some(text.to_string()));
+        }
+
+        // `First:Last!` introduces a 3D reference
+        if self.peek() == Some(':') {
+            let rest = &self.input[self.pos + 1..];
+            let last_len = rest
+                .char_indices()
+                .find(|(_, c)| !is_identifier_char(*c))
+                .map_or(rest.len(), |(i, _)| i);
+
+            if last_len > 0 && rest[last_len..].starts_with('!') {
+                let last = rest[..last_len].to_string();
+                self.pos += 1 + last_len + 1;
+                return Ok(TokenKind::SheetRange(text.to_string(), last));
+            }
+        }
+
+        if self.peek() == Some('(') {
+            return Ok(TokenKind::Function(text.to_uppercase()));
+        }
+
+        if text.eq_ignore_ascii_case("TRUE") {
+            return Ok(TokenKind::Boolean(true));
+        }
+        if text.eq_ignore_ascii_case("FALSE") {
+            return Ok(TokenKind::Boolean(false));
+        }
+
+        if CellRef::parse(text).is_some() {
+            if self.peek() == Some('#') {
+                self.bump();
+                return Ok(TokenKind::SpillReference(text.to_uppercase()));
+            }
+            return Ok(TokenKind::Reference(text.to_uppercase()));
+        }
+
+        Ok(TokenKind::Identifier(text.to_string()))
+    }
+
+    /// The bracketed specifier of a structured reference, nested brackets
+    /// included, as in `[[#Headers],[Region]]`. An apostrophe escapes the
+    /// character after it.
+    fn read_structured_ref(&mut self, table: Option<String>) -> Result<TokenKind, String> {
+        let start = self.pos;
+        let mut depth = 0;
+
+        loop {
+            match self.bump() {
+                Some('[') => depth += 1,
+                Some(']') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Some('\'') => {
+                    self.bump();
+                }
+                Some(_) => {}
+                None => return Err(format!("Unterminated structured reference starting at position {}", start)),
+            }
+        }
+
+        Ok(TokenKind::StructuredRef {
+            table,
+            spec: self.input[start..self.pos].to_string(),
+        })
+    }
+
+    fn read_operator(&mut self) -> Result<TokenKind, String> {
+        let start = self.pos;
+        let c = self.bump().unwrap_or_default();
+
+        let kind = match c {
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '^' => TokenKind::Caret,
+            '&' => TokenKind::Ampersand,
+            '%' => TokenKind::Percent,
+            '@' => TokenKind::At,
+            '=' => TokenKind::Equal,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            '{' => TokenKind::LeftBrace,
+            '}' => TokenKind::RightBrace,
+            ';' => TokenKind::Semicolon,
+            '<' => match self.peek() {
+                Some('=') => {
+                    self.bump();
+                    TokenKind::LessEqual
+                }
+                Some('>') => {
+                    self.bump();
+                    TokenKind::NotEqual
+                }
+                _ => TokenKind::Less,
+            },
+            '>' => {
+                if self.peek() == Some('=') {
+                    self.bump();
+                    TokenKind::GreaterEqual
+                } else {
+                    TokenKind::Greater
+                }
+            }
+            other => return Err(format!("Unexpected character '{}' at position {}", other, start)),
+        };
+
+        Ok(kind)
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '\\' || c == '$'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '\\' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(input: &str) -> Vec<TokenKind> {
+        Lexer::new(input)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(kinds("42"), vec![TokenKind::Number(42.0)]);
+        assert_eq!(kinds(".5"), vec![TokenKind::Number(0.5)]);
+        assert_eq!(kinds("1.5E3"), vec![TokenKind::Number(1500.0)]);
+        assert_eq!(kinds("2e-2"), vec![TokenKind::Number(0.02)]);
+        assert_eq!(kinds("1E308"), vec![TokenKind::Number(1e308)]);
+        assert!(Lexer::new("1E400").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_strings_with_escaped_quotes() {
+        assert_eq!(
+            kinds("\"say \"\"hi\"\"\""),
+            vec![TokenKind::Text("say \"hi\"".to_string())]
+        );
+        assert!(Lexer::new("\"open").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_booleans_errors_and_references() {
+        assert_eq!(
+            kinds("true,#DIV/0!,$b$2,#n/a"),
+            vec![
+                TokenKind::Boolean(true),
+                TokenKind::Comma,
+                TokenKind::Error(ExcelError::Div0),
+                TokenKind::Comma,
+                TokenKind::Reference("$B$2".to_string()),
+                TokenKind::Comma,
+                TokenKind::Error(ExcelError::NA),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_functions_and_operators() {
+        assert_eq!(
+            kinds("sum(A1, 2) <> 3 >= 4"),
+            vec![
+                TokenKind::Function("SUM".to_string()),
+                TokenKind::LeftParen,
+                TokenKind::Reference("A1".to_string()),
+                TokenKind::Comma,
+                TokenKind::Number(2.0),
+                TokenKind::RightParen,
+                TokenKind::NotEqual,
+                TokenKind::Number(3.0),
+                TokenKind::GreaterEqual,
+                TokenKind::Number(4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sheet_prefixes() {
+        assert_eq!(
+            kinds("Sheet2!A1+'Q1 Sales'!B2:B20+'O''Brien''s'!C3"),
+            vec![
+                TokenKind::SheetPrefix("Sheet2".to_string()),
+                TokenKind::Reference("A1".to_string()),
+                TokenKind::Plus,
+                TokenKind::SheetPrefix("Q1 Sales".to_string()),
+                TokenKind::Reference("B2".to_string()),
+                TokenKind::Colon,
+                TokenKind::Reference("B20".to_string()),
+                TokenKind::Plus,
+                TokenKind::SheetPrefix("O'Brien's".to_string()),
+                TokenKind::Reference("C3".to_string()),
+            ]
+        );
+        assert!(Lexer::new("'Q1 Sales'A1").tokenize().is_err());
+        assert!(Lexer::new("'Q1 Sales").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_sheet_ranges() {
+        assert_eq!(
+            kinds("SUM(Jan:Dec!B5,'Q1 2024:Q4 2024'!A1:A2,A:B)"),
+            vec![
+                TokenKind::Function("SUM".to_string()),
+                TokenKind::LeftParen,
+                TokenKind::SheetRange("Jan".to_string(), "Dec".to_string()),
+                TokenKind::Reference("B5".to_string()),
+                TokenKind::Comma,
+                TokenKind::SheetRange("Q1 2024".to_string(), "Q4 2024".to_string()),
+                TokenKind::Reference("A1".to_string()),
+                TokenKind::Colon,
+                TokenKind::Reference("A2".to_string()),
+                TokenKind::Comma,
+                TokenKind::Identifier("A".to_string()),
+                TokenKind::Colon,
+                TokenKind::Identifier("B".to_string()),
+                TokenKind::RightParen,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structured_references() {
+        let structured = |table: Option<&str>, spec: &str| TokenKind::StructuredRef {
+            table: table.map(str::to_string),
+            spec: spec.to_string(),
+        };
+
+        assert_eq!(
+            kinds("SUM(Sales[Amount])*[@[Price']]]"),
+            vec![
+                TokenKind::Function("SUM".to_string()),
+                TokenKind::LeftParen,
+                structured(Some("Sales"), "[Amount]"),
+                TokenKind::RightParen,
+                TokenKind::Star,
+                structured(None, "[@[Price']]]"),
+            ]
+        );
+        assert_eq!(
+            kinds("Sales[[#Headers],[Region]]"),
+            vec![structured(Some("Sales"), "[[#Headers],[Region]]")]
+        );
+        assert!(Lexer::new("Sales[[#Totals]").tokenize().is_err());
+    }
+
+    #[test]
+    fn test_spill_references_and_implicit_intersection() {
+        assert_eq!(
+            kinds("SUM(b2#)+@Sheet2!A1:A3"),
+            vec![
+                TokenKind::Function("SUM".to_string()),
+                TokenKind::LeftParen,
+                TokenKind::SpillReference("B2".to_string()),
+                TokenKind::RightParen,
+                TokenKind::Plus,
+                TokenKind::At,
+                TokenKind::SheetPrefix("Sheet2".to_string()),
+                TokenKind::Reference("A1".to_string()),
+                TokenKind::Colon,
+                TokenKind::Reference("A3".to_string()),
+            ]
+        );
+        assert_eq!(kinds("A1 #N/A"), vec![TokenKind::Reference("A1".to_string()), TokenKind::Error(ExcelError::NA)]);
+    }
+
+    #[test]
+    fn test_array_constants() {
+        assert_eq!(
+            kinds("{1,\"a\";-2,TRUE}"),
+            vec![
+                TokenKind::LeftBrace,
+                TokenKind::Number(1.0),
+                TokenKind::Comma,
+                TokenKind::Text("a".to_string()),
+                TokenKind::Semicolon,
+                TokenKind::Minus,
+                TokenKind::Number(2.0),
+                TokenKind::Comma,
+                TokenKind::Boolean(true),
+                TokenKind::RightBrace,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identifiers_outside_grid() {
+        assert_eq!(kinds("XFE1"), vec![TokenKind::Identifier("XFE1".to_string())]);
+        assert_eq!(kinds("TaxRate"), vec![TokenKind::Identifier("TaxRate".to_string())]);
+    }
+}
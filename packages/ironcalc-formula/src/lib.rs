@@ -1,19 +1,77 @@
 use wasm_bindgen::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::f64;
+use std::rc::Rc;
+
+mod cache;
+mod date;
+mod dependencies;
+mod error;
+mod evaluator;
+mod formula;
+mod functions;
+mod grid;
+mod lexer;
+mod names;
+mod parser;
+mod reference;
+mod scope;
+mod spill;
+mod tables;
+mod value;
+
+#[cfg(test)]
+mod benches;
+#[cfg(test)]
+mod tests;
+
+use cache::LruCache;
+use date::DateSystem;
+use dependencies::{CellId, DependencyGraph, Precedent, Step};
+use error::ExcelError;
+use evaluator::Evaluator;
+use formula::Formula;
+use functions::{Category, Function, FunctionRegistry};
+use names::{DefinedName, NameInfo, Names};
+use parser::Expr;
+use reference::{CellRef, RangeRef};
+use spill::Spill;
+use tables::{Table, TableInfo, Tables};
+use value::CellValue;
 
 // Import the console.log! macro from web-sys
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
 }
 
+// Native builds (unit tests) have no console to log to
+#[cfg(not(target_arch = "wasm32"))]
+fn log(_s: &str) {}
+
 macro_rules! console_log {
     ($($t:tt)*) => (log(&format_args!($($t)*).to_string()))
 }
 
+/// Current time in milliseconds, from the JS clock when running as WASM
+fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs_f64() * 1000.0)
+            .unwrap_or(0.0)
+    }
+}
+
 // Performance metrics tracking
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct PerformanceMetrics {
@@ -36,50 +94,158 @@ impl Default for PerformanceMetrics {
     }
 }
 
-// Formula result structure
+// Workbook setting for Excel's iterative calculation, which lets circular
+// references converge instead of failing with #CIRC!
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IterationSettings {
+    enabled: bool,
+    max_iterations: u32,
+    max_change: f64,
+}
+
+impl Default for IterationSettings {
+    fn default() -> Self {
+        // Excel's defaults
+        Self {
+            enabled: false,
+            max_iterations: 100,
+            max_change: 0.001,
+        }
+    }
+}
+
+// When formulas are recalculated after a change, as in Excel's Formulas >
+// Calculation Options
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CalculationMode {
+    // Recalculate dependents on every change
+    Automatic,
+    // Like automatic, except for what-if data tables, which the engine does
+    // not have; kept so that workbook settings round-trip
+    AutomaticExceptTables,
+    // Only mark dependents dirty; recalculate on request
+    Manual,
+}
+
+impl CalculationMode {
+    fn parse(mode: &str) -> Option<CalculationMode> {
+        match mode {
+            "automatic" => Some(CalculationMode::Automatic),
+            "automatic-except-tables" => Some(CalculationMode::AutomaticExceptTables),
+            "manual" => Some(CalculationMode::Manual),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            CalculationMode::Automatic => "automatic",
+            CalculationMode::AutomaticExceptTables => "automatic-except-tables",
+            CalculationMode::Manual => "manual",
+        }
+    }
+}
+
+// Outcome of iterating the circular references met by a recalculation
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct IterationStatus {
+    iterations: u32,
+    converged: bool,
+    // Largest change of a cell in the last iteration
+    max_change: f64,
+}
+
+// Formula result structure. Excel errors such as `#DIV/0!` are results like
+// any other: `value` holds the error and `error` repeats its code. Failures of
+// the engine itself are returned as `Err` instead of a result.
+#[derive(Serialize, Clone, Debug)]
 pub struct FormulaResult {
-    value: String,
-    error: Option<String>,
+    value: CellValue,
+    error: Option<ExcelError>,
+    // The chain of references, ending where it started, when the formula
+    // closes a circular reference
+    circular_reference: Option<Vec<String>>,
+    // How iteration went, when the formula is part of a circular reference
+    // and iterative calculation is enabled
+    iteration: Option<IterationStatus>,
     execution_time_ms: u32,
     cell_address: String,
     formula_type: String,
+    // The cells an array result spilled into, anchor included, e.g. `B2:B10`
+    spill_range: Option<String>,
 }
 
 // Cell and sheet management
 #[derive(Clone, Debug)]
 struct Cell {
-    value: String,
-    formula: Option<String>,
+    value: CellValue,
+    // Shared with other cells holding the same formula text
+    formula: Option<Rc<Formula>>,
+    #[allow(dead_code)]
     last_calculated: f64,
-    dependencies: Vec<String>,
+    // Precedents of the formula, mirrored as reverse edges in the engine's
+    // dependency graph
+    dependencies: Vec<Precedent>,
+    // Set in manual calculation mode when a precedent changed since the
+    // formula was last calculated
+    dirty: bool,
+    // The formula cell whose array result this cell shows part of
+    spilled_from: Option<(i32, i32)>,
 }
 
 struct Worksheet {
     name: String,
-    cells: HashMap<(i32, i32), Cell>,
+    // Ordered by (row, col) so that ranges can be scanned without probing
+    // every coordinate they cover
+    cells: BTreeMap<(i32, i32), Cell>,
     max_row: i32,
     max_col: i32,
+    // Spill ranges of the formulas with array results, blocked or not, by
+    // the formula's cell
+    spills: HashMap<(i32, i32), Spill>,
+    // Ranges of the legacy array formulas, by the cell holding the formula
+    array_formulas: HashMap<(i32, i32), RangeRef>,
 }
 
 impl Worksheet {
     fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
-            cells: HashMap::new(),
+            cells: BTreeMap::new(),
             max_row: 0,
             max_col: 0,
+            spills: HashMap::new(),
+            array_formulas: HashMap::new(),
         }
     }
     
-    fn set_cell(&mut self, row: i32, col: i32, value: String, formula: Option<String>) {
+    fn set_cell(&mut self, row: i32, col: i32, value: CellValue, formula: Option<Rc<Formula>>, dependencies: Vec<Precedent>) {
         let cell = Cell {
-            value: value.clone(),
+            value,
             formula,
-            last_calculated: js_sys::Date::now(),
+            last_calculated: now_ms(),
+            dependencies,
+            dirty: false,
+            spilled_from: None,
+        };
+        self.insert_cell(row, col, cell);
+    }
+
+    /// Show an element of the array spilled from the formula at `anchor`.
+    fn set_spilled_value(&mut self, row: i32, col: i32, value: CellValue, anchor: (i32, i32)) {
+        let cell = Cell {
+            value,
+            formula: None,
+            last_calculated: now_ms(),
             dependencies: Vec::new(),
+            dirty: false,
+            spilled_from: Some(anchor),
         };
-        
+        self.insert_cell(row, col, cell);
+    }
+
+    fn insert_cell(&mut self, row: i32, col: i32, cell: Cell) {
         self.cells.insert((row, col), cell);
         
         // Update bounds
@@ -90,20 +256,107 @@ impl Worksheet {
             self.max_col = col;
         }
     }
+
+    /// Forget the spill of the formula at `row`, `col` and clear the cells
+    /// it spilled into, returning their positions.
+    fn clear_spill(&mut self, row: i32, col: i32) -> Vec<(i32, i32)> {
+        let Some(spill) = self.spills.remove(&(row, col)).filter(|spill| !spill.blocked) else {
+            return Vec::new();
+        };
+
+        let spilled: Vec<(i32, i32)> = self
+            .cells_in_range(spill.range)
+            .filter(|(_, cell)| cell.spilled_from == Some((row, col)))
+            .map(|(position, _)| *position)
+            .collect();
+        for position in &spilled {
+            self.cells.remove(position);
+        }
+        spilled
+    }
+
+    /// The legacy array formula whose range covers `row`, `col`, as the
+    /// cell holding the formula and the range.
+    fn array_formula_covering(&self, row: i32, col: i32) -> Option<((i32, i32), RangeRef)> {
+        self.array_formulas
+            .iter()
+            .find(|(_, range)| range.contains(row, col))
+            .map(|(anchor, range)| (*anchor, *range))
+    }
+
+    /// Whether a cell of `range` other than the formula's own at `anchor`
+    /// holds something an array cannot spill over: a value, a formula or
+    /// part of another spill.
+    fn blocks_spill(&self, range: RangeRef, anchor: (i32, i32)) -> bool {
+        self.cells_in_range(range).any(|(position, cell)| {
+            *position != anchor && (cell.formula.is_some() || cell.value != CellValue::Empty || cell.spilled_from.is_some())
+        })
+    }
     
     fn get_cell(&self, row: i32, col: i32) -> Option<&Cell> {
         self.cells.get(&(row, col))
     }
+
+    /// The part of a range that can hold values: rows and columns past the
+    /// last populated ones are cut off, keeping at least the first.
+    fn used_part(&self, range: RangeRef) -> RangeRef {
+        let end = CellRef {
+            row: range.end.row.min(self.max_row.max(range.start.row)),
+            col: range.end.col.min(self.max_col.max(range.start.col)),
+            ..range.end
+        };
+        RangeRef::new(range.start, end)
+    }
+
+    /// The cells a range stands for when its values are used. Whole columns
+    /// and whole rows are cut at the last populated row or column rather
+    /// than covering a million cells; any other range keeps its size, blank
+    /// cells included.
+    fn value_range(&self, range: RangeRef) -> RangeRef {
+        let used = self.used_part(range);
+        let end = CellRef {
+            row: if range.is_whole_columns() { used.end.row } else { range.end.row },
+            col: if range.is_whole_rows() { used.end.col } else { range.end.col },
+            ..range.end
+        };
+        RangeRef::new(range.start, end)
+    }
+
+    /// Iterate over the populated cells of a range in row-major order.
+    fn cells_in_range(&self, range: RangeRef) -> impl DoubleEndedIterator<Item = (&(i32, i32), &Cell)> {
+        let (first_col, last_col) = (range.start.col, range.end.col);
+        self.cells
+            .range((range.start.row, first_col)..=(range.end.row, last_col))
+            .filter(move |((_, col), _)| (first_col..=last_col).contains(col))
+    }
 }
 
 // Main IronCalc engine implementation
 #[wasm_bindgen]
 pub struct IronCalcEngine {
-    worksheets: HashMap<String, Worksheet>,
+    // Kept in tab order, which 3D references like `Jan:Dec!B5` depend on
+    worksheets: Vec<Worksheet>,
+    #[allow(dead_code)]
     active_sheet: String,
     performance_metrics: PerformanceMetrics,
-    formula_cache: HashMap<String, FormulaResult>,
-    max_cache_size: usize,
+    dependency_graph: DependencyGraph,
+    functions: FunctionRegistry,
+    names: Names,
+    tables: Tables,
+    // Cells whose formula calls a volatile function; every recalculation
+    // includes them
+    volatile_cells: HashSet<CellId>,
+    calculation_mode: CalculationMode,
+    iteration_settings: IterationSettings,
+    last_iteration: Option<IterationStatus>,
+    date_system: DateSystem,
+    // Results of formulas entered through `evaluateFormula`, keyed by cell
+    // along with the formula text. An entry is dropped whenever its cell is
+    // written or recalculated, which a change to any precedent causes.
+    formula_cache: LruCache<CellId, (Rc<Formula>, FormulaResult)>,
+    // Recently compiled formulas by text, so that entering a formula that
+    // was entered before skips parsing
+    compiled_formulas: LruCache<String, Rc<Formula>>,
 }
 
 #[wasm_bindgen]
@@ -114,20 +367,28 @@ impl IronCalcEngine {
         console_error_panic_hook::set_once();
         console_log!("Initializing IronCalc WASM engine");
         
-        let mut worksheets = HashMap::new();
         let default_sheet = "Sheet1";
-        worksheets.insert(default_sheet.to_string(), Worksheet::new(default_sheet));
         
         Ok(IronCalcEngine {
-            worksheets,
+            worksheets: vec![Worksheet::new(default_sheet)],
             active_sheet: default_sheet.to_string(),
             performance_metrics: PerformanceMetrics::default(),
-            formula_cache: HashMap::new(),
-            max_cache_size: 10000,
+            dependency_graph: DependencyGraph::new(),
+            functions: FunctionRegistry::new(),
+            names: Names::default(),
+            tables: Tables::default(),
+            volatile_cells: HashSet::new(),
+            calculation_mode: CalculationMode::Automatic,
+            iteration_settings: IterationSettings::default(),
+            last_iteration: None,
+            date_system: DateSystem::default(),
+            formula_cache: LruCache::new(10000),
+            compiled_formulas: LruCache::new(10000),
         })
     }
 
-    /// Evaluate a formula in a specific cell
+    /// Evaluate a formula in a specific cell, store it there and recalculate
+    /// the formulas that depend on that cell
     #[wasm_bindgen(js_name = evaluateFormula)]
     pub fn evaluate_formula(
         &mut self, 
@@ -136,71 +397,17 @@ impl IronCalcEngine {
         row: u32, 
         col: u32
     ) -> Result<JsValue, JsValue> {
-        let start_time = js_sys::Date::now();
-        self.performance_metrics.total_evaluations += 1;
-        
-        // Input validation
-        if formula.is_empty() {
-            return Err(JsValue::from_str("Formula cannot be empty"));
-        }
-        
-        if formula.len() > 8192 {
-            return Err(JsValue::from_str("Formula too long (max 8192 characters)"));
-        }
-
-        // Validate cell reference
-        if let Err(e) = self.validate_cell_reference(sheet_name, row as i32, col as i32) {
-            return Err(JsValue::from_str(&e));
-        }
-
-        // Check cache first
-        let cache_key = format!("{}:{}:{}:{}", sheet_name, row, col, formula);
-        if let Some(cached_result) = self.formula_cache.get(&cache_key) {
-            self.performance_metrics.cache_hit_rate += 1.0;
-            return Ok(serde_wasm_bindgen::to_value(cached_result)?);
-        }
-
-        // Evaluate the formula
-        let result = match self.evaluate_formula_internal(formula, sheet_name, row as i32, col as i32) {
-            Ok(value) => {
-                let execution_time = (js_sys::Date::now() - start_time) as u32;
-                self.update_performance_metrics(execution_time, true);
-                
-                FormulaResult {
-                    value,
-                    error: None,
-                    execution_time_ms: execution_time,
-                    cell_address: self.cell_address(col as i32, row as i32),
-                    formula_type: self.classify_formula(formula),
-                }
-            }
-            Err(error) => {
-                let execution_time = (js_sys::Date::now() - start_time) as u32;
-                self.update_performance_metrics(execution_time, false);
-                
-                FormulaResult {
-                    value: String::new(),
-                    error: Some(error),
-                    execution_time_ms: execution_time,
-                    cell_address: self.cell_address(col as i32, row as i32),
-                    formula_type: "error".to_string(),
-                }
-            }
-        };
-
-        // Cache the result (with size limit)
-        if self.formula_cache.len() < self.max_cache_size {
-            self.formula_cache.insert(cache_key, result.clone());
-        }
-
-        // Store the formula and result in the worksheet
-        self.set_cell_value(sheet_name, row as i32, col as i32, &result.value, Some(formula.to_string()))?;
+        let result = self.evaluate(formula, sheet_name, row, col)
+            .map_err(|e| JsValue::from_str(&e))?;
 
         serde_wasm_bindgen::to_value(&result)
             .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
     }
 
-    /// Set a cell value directly
+    /// Set a cell value directly and recalculate the formulas that depend on
+    /// it. The input is typed the way Excel types what is entered into a
+    /// cell: numbers, booleans and error codes keep their type, and a leading
+    /// apostrophe forces text. A row or column outside the sheet is an error.
     #[wasm_bindgen(js_name = setCellValue)]
     pub fn set_cell_value_js(
         &mut self,
@@ -209,10 +416,11 @@ impl IronCalcEngine {
         col: u32,
         value: &str
     ) -> Result<(), JsValue> {
-        self.set_cell_value(sheet_name, row as i32, col as i32, value, None)
+        self.set_cell_value(sheet_name, row as i32, col as i32, CellValue::from_input(value))
+            .map_err(|e| JsValue::from_str(&e))
     }
 
-    /// Get a cell value
+    /// Get a cell value as displayed text
     #[wasm_bindgen(js_name = getCellValue)]
     pub fn get_cell_value(
         &self,
@@ -220,28 +428,226 @@ impl IronCalcEngine {
         row: u32,
         col: u32
     ) -> Result<String, JsValue> {
-        let worksheet = self.worksheets.get(sheet_name)
+        let worksheet = self.find_worksheet(sheet_name)
             .ok_or_else(|| JsValue::from_str(&format!("Sheet '{}' not found", sheet_name)))?;
         
         if let Some(cell) = worksheet.get_cell(row as i32, col as i32) {
-            Ok(cell.value.clone())
+            Ok(cell.value.to_display_string())
         } else {
             Ok(String::new())
         }
     }
 
-    /// Create a new worksheet
+    /// Create a new worksheet after the existing ones
     #[wasm_bindgen(js_name = createSheet)]
     pub fn create_sheet(&mut self, name: &str) -> Result<(), JsValue> {
-        if self.worksheets.contains_key(name) {
-            return Err(JsValue::from_str(&format!("Sheet '{}' already exists", name)));
-        }
-        
-        self.worksheets.insert(name.to_string(), Worksheet::new(name));
-        console_log!("Created new worksheet: {}", name);
+        self.insert_worksheet(name, self.worksheets.len())
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Create a new worksheet at a 0-based position in the tab order
+    #[wasm_bindgen(js_name = insertSheet)]
+    pub fn insert_sheet(&mut self, name: &str, index: usize) -> Result<(), JsValue> {
+        self.insert_worksheet(name, index)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Get the worksheet names in tab order
+    #[wasm_bindgen(js_name = getSheetNames)]
+    pub fn get_sheet_names(&self) -> Vec<String> {
+        self.worksheets.iter().map(|ws| ws.name.clone()).collect()
+    }
+
+    /// Set the calculation mode: `automatic`, `automatic-except-tables` or
+    /// `manual`. Switching back from manual recalculates the dirty cells.
+    #[wasm_bindgen(js_name = setCalculationMode)]
+    pub fn set_calculation_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        let mode = CalculationMode::parse(mode)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown calculation mode: {}", mode)))?;
+        self.change_calculation_mode(mode);
         Ok(())
     }
 
+    /// Get the calculation mode
+    #[wasm_bindgen(js_name = getCalculationMode)]
+    pub fn get_calculation_mode(&self) -> String {
+        self.calculation_mode.as_str().to_string()
+    }
+
+    /// Recalculate the dirty formulas of every sheet (Excel's F9)
+    #[wasm_bindgen(js_name = recalculate)]
+    pub fn recalculate_js(&mut self) {
+        let dirty = self.dirty_cells(None);
+        self.recalculate_cells(&dirty);
+    }
+
+    /// Recalculate the dirty formulas of one sheet (Excel's Shift+F9).
+    /// Formulas on other sheets that depend on them stay dirty.
+    #[wasm_bindgen(js_name = recalculateSheet)]
+    pub fn recalculate_sheet(&mut self, name: &str) -> Result<(), JsValue> {
+        self.recalculate_worksheet(name)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Recalculate every formula whether dirty or not (Excel's Ctrl+Alt+F9)
+    #[wasm_bindgen(js_name = recalculateAll)]
+    pub fn recalculate_all(&mut self) {
+        let formulas: Vec<CellId> = self
+            .worksheets
+            .iter()
+            .flat_map(|ws| {
+                ws.cells
+                    .iter()
+                    .filter(|(_, cell)| cell.formula.is_some())
+                    .map(|((row, col), _)| CellId::new(&ws.name, *row, *col))
+            })
+            .collect();
+
+        self.recalculate_cells(&formulas);
+    }
+
+    /// Enable or disable iterative calculation of circular references
+    #[wasm_bindgen(js_name = setIterativeCalculation)]
+    pub fn set_iterative_calculation(&mut self, enabled: bool, max_iterations: u32, max_change: f64) -> Result<(), JsValue> {
+        self.configure_iteration(IterationSettings { enabled, max_iterations, max_change })
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Get the iterative calculation settings
+    #[wasm_bindgen(js_name = getIterativeCalculation)]
+    pub fn get_iterative_calculation(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.iteration_settings)
+            .map_err(|e| JsValue::from_str(&format!("Settings serialization error: {}", e)))
+    }
+
+    /// Get the outcome of the most recent recalculation that iterated
+    #[wasm_bindgen(js_name = getIterationStatus)]
+    pub fn get_iteration_status(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.last_iteration)
+            .map_err(|e| JsValue::from_str(&format!("Status serialization error: {}", e)))
+    }
+
+    /// Set the date system: `1900`, the default, counting days from
+    /// 1900-01-00 as Excel for Windows does, or `1904`, counting from
+    /// 1904-01-01 as older Mac workbooks do. Changing it recalculates every
+    /// formula.
+    #[wasm_bindgen(js_name = setDateSystem)]
+    pub fn set_date_system(&mut self, system: &str) -> Result<(), JsValue> {
+        let system = DateSystem::parse(system)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown date system: {}", system)))?;
+        self.change_date_system(system);
+        Ok(())
+    }
+
+    /// Get the date system
+    #[wasm_bindgen(js_name = getDateSystem)]
+    pub fn get_date_system(&self) -> String {
+        self.date_system.as_str().to_string()
+    }
+
+    /// Register a JavaScript function that formulas can call by `name`,
+    /// taking `min_args` to `max_args` arguments (any number when
+    /// `max_args` is undefined). Arguments arrive as numbers, strings,
+    /// booleans, `null` for blank cells and arrays of rows for ranges. An
+    /// exception thrown by the function makes the formula `#VALUE!`.
+    #[wasm_bindgen(js_name = registerCustomFunction)]
+    pub fn register_custom_function(
+        &mut self,
+        name: &str,
+        min_args: usize,
+        max_args: Option<usize>,
+        callback: js_sys::Function
+    ) -> Result<(), JsValue> {
+        let function = functions::custom::js_function(name, (min_args, max_args), callback);
+        self.register_function(function)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Define a name for a cell, range, constant or formula, e.g.
+    /// `defineName("TaxRate", "=Sheet1!$B$1")`. The name belongs to the
+    /// workbook, or to `sheet` when given. Formulas using the name are
+    /// recalculated.
+    #[wasm_bindgen(js_name = defineName)]
+    pub fn define_name_js(&mut self, name: &str, refers_to: &str, sheet: Option<String>) -> Result<(), JsValue> {
+        self.define_name(name, refers_to, sheet.as_deref())
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Change what an existing name refers to
+    #[wasm_bindgen(js_name = updateName)]
+    pub fn update_name_js(&mut self, name: &str, refers_to: &str, sheet: Option<String>) -> Result<(), JsValue> {
+        self.update_name(name, refers_to, sheet.as_deref())
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Delete a name; formulas still using it evaluate to `#NAME?`
+    #[wasm_bindgen(js_name = deleteName)]
+    pub fn delete_name_js(&mut self, name: &str, sheet: Option<String>) -> Result<(), JsValue> {
+        self.delete_name(name, sheet.as_deref())
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Rename a name, rewriting the formulas that use it
+    #[wasm_bindgen(js_name = renameName)]
+    pub fn rename_name_js(&mut self, name: &str, new_name: &str, sheet: Option<String>) -> Result<(), JsValue> {
+        self.rename_name(name, new_name, sheet.as_deref())
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// List the defined names with what they refer to and their sheet,
+    /// which is null for workbook names
+    #[wasm_bindgen(js_name = getNames)]
+    pub fn get_names(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.list_names())
+            .map_err(|e| JsValue::from_str(&format!("Names serialization error: {}", e)))
+    }
+
+    /// Create a table named `name` over a range of a sheet, e.g.
+    /// `createTable("Sales", "Sheet1", "A1:C10", false)`. The first row of
+    /// the range holds the column names and, with `totals_row`, the last
+    /// row holds totals. Blank column names become `Column1`, `Column2`...
+    #[wasm_bindgen(js_name = createTable)]
+    pub fn create_table_js(&mut self, name: &str, sheet_name: &str, range: &str, totals_row: bool) -> Result<(), JsValue> {
+        self.create_table(name, sheet_name, range, totals_row)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Delete a table, leaving its cells in place; structured references
+    /// to it evaluate to `#REF!`
+    #[wasm_bindgen(js_name = deleteTable)]
+    pub fn delete_table_js(&mut self, name: &str) -> Result<(), JsValue> {
+        self.delete_table(name)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// List the tables with their sheet, range, column names and whether
+    /// they have a totals row
+    #[wasm_bindgen(js_name = getTables)]
+    pub fn get_tables(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.list_tables())
+            .map_err(|e| JsValue::from_str(&format!("Tables serialization error: {}", e)))
+    }
+
+    /// Enter a legacy array formula over a range, as Ctrl+Shift+Enter does
+    /// in older Excel, e.g. `setArrayFormula("=A1:A3*B1:B3", "Sheet1",
+    /// "C1:C3")`. The result is fitted to the range instead of spilling,
+    /// and the cells of the range can only be changed together.
+    #[wasm_bindgen(js_name = setArrayFormula)]
+    pub fn set_array_formula_js(&mut self, formula: &str, sheet_name: &str, range: &str) -> Result<JsValue, JsValue> {
+        let result = self.set_array_formula(formula, sheet_name, range)
+            .map_err(|e| JsValue::from_str(&e))?;
+
+        serde_wasm_bindgen::to_value(&result)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))
+    }
+
+    /// Delete the legacy array formula covering a cell, clearing its range
+    #[wasm_bindgen(js_name = deleteArrayFormula)]
+    pub fn delete_array_formula_js(&mut self, sheet_name: &str, row: u32, col: u32) -> Result<(), JsValue> {
+        self.delete_array_formula(sheet_name, row as i32, col as i32)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
     /// Get performance metrics
     #[wasm_bindgen(js_name = getPerformanceMetrics)]
     pub fn get_performance_metrics(&self) -> Result<JsValue, JsValue> {
@@ -263,19 +669,26 @@ impl IronCalcEngine {
         console_log!("Formula cache cleared");
     }
 
+    /// Set how many formula results are cached; the least recently used
+    /// results are evicted beyond that, and 0 disables the cache
+    #[wasm_bindgen(js_name = setCacheSize)]
+    pub fn set_cache_size(&mut self, size: usize) {
+        self.formula_cache.set_capacity(size);
+    }
+
     /// Get memory usage estimate
     #[wasm_bindgen(js_name = getMemoryUsage)]
     pub fn get_memory_usage(&self) -> usize {
         let mut usage = std::mem::size_of::<Self>();
         
         // Estimate worksheet memory usage
-        for worksheet in self.worksheets.values() {
+        for worksheet in &self.worksheets {
             usage += std::mem::size_of_val(worksheet);
             usage += worksheet.cells.len() * std::mem::size_of::<Cell>();
         }
         
         // Estimate cache memory usage
-        usage += self.formula_cache.len() * (std::mem::size_of::<String>() + std::mem::size_of::<FormulaResult>());
+        usage += self.formula_cache.len() * std::mem::size_of::<(CellId, Rc<Formula>, FormulaResult)>();
         
         usage
     }
@@ -283,12 +696,118 @@ impl IronCalcEngine {
 
 // Private implementation methods
 impl IronCalcEngine {
+    fn evaluate(&mut self, formula: &str, sheet_name: &str, row: u32, col: u32) -> Result<FormulaResult, String> {
+        let start_time = now_ms();
+        self.performance_metrics.total_evaluations += 1;
+        
+        // Input validation
+        if formula.is_empty() {
+            return Err("Formula cannot be empty".to_string());
+        }
+        
+        if formula.len() > 8192 {
+            return Err("Formula too long (max 8192 characters)".to_string());
+        }
+
+        // Validate cell reference
+        self.validate_cell_reference(sheet_name, row as i32, col as i32)?;
+        let sheet = self.find_worksheet(sheet_name)
+            .map(|ws| ws.name.clone())
+            .ok_or_else(|| format!("Sheet '{}' not found", sheet_name))?;
+        let id = CellId::new(&sheet, row as i32, col as i32);
+        self.ensure_outside_array_formulas(&id)?;
+
+        // A formula entered just below a table extends it, before it is
+        // evaluated, so that it can refer to its own row
+        let table_users = self.expand_tables(&id);
+
+        // A cached result is current as long as the cell still holds the
+        // same formula, since rewriting the cell or recalculating it after a
+        // precedent changed evicts the entry
+        if let Some((cached_formula, cached_result)) = self.formula_cache.get(&id) {
+            if cached_formula.text() == formula {
+                let cached_result = cached_result.clone();
+                self.performance_metrics.cache_hit_rate += 1.0;
+                self.recalculate_or_mark_dirty(table_users);
+                return Ok(cached_result);
+            }
+        }
+
+        // Evaluate the formula
+        let evaluation = self.compile(formula).and_then(|compiled| {
+            let (value, precedents) = self.evaluate_compiled(&compiled, &id)?;
+            Ok((compiled, value, precedents))
+        });
+        let execution_time = (now_ms() - start_time) as u32;
+
+        let (compiled, value, precedents) = match evaluation {
+            Ok(evaluation) => evaluation,
+            Err(error) => {
+                self.update_performance_metrics(execution_time, false);
+                return Err(error);
+            }
+        };
+
+        // Store the formula and result in the worksheet, then bring the cells
+        // that depend on it up to date. If that closes a circle of references
+        // the cell itself is marked as circular.
+        let spilled = self.store_cell(&id, value, Some(Rc::clone(&compiled)), precedents)?;
+        let cycles = self.recalculate_dependents(&id, spilled);
+        self.recalculate_or_mark_dirty(table_users);
+
+        let result = self.formula_result(&id, &compiled, &cycles, execution_time);
+        self.update_performance_metrics(execution_time, result.error.is_none());
+
+        // Entering an iterated or volatile formula again gives a new result,
+        // so only results that follow from the precedents alone are cached
+        if result.iteration.is_none() && !compiled.is_volatile() {
+            self.formula_cache.insert(id, (compiled, result.clone()));
+        }
+
+        Ok(result)
+    }
+
+    /// The result of the formula just stored in `id`, given the circular
+    /// references its recalculation found.
+    fn formula_result(&self, id: &CellId, compiled: &Formula, cycles: &[Vec<CellId>], execution_time: u32) -> FormulaResult {
+        let cycle = cycles.iter().find(|cycle| cycle.contains(id));
+        let (circular_reference, iteration) = match cycle {
+            Some(_) if self.iteration_settings.enabled => (None, self.last_iteration.clone()),
+            Some(cycle) => {
+                let path = self.dependency_graph
+                    .cycle_path(cycle, id, &self.get_sheet_names())
+                    .iter()
+                    .map(|cell| self.cell_display_address(cell, &id.sheet))
+                    .collect();
+                (Some(path), None)
+            }
+            None => (None, None),
+        };
+        let value = self.cell_value(id);
+
+        let error = match value {
+            CellValue::Error(error) => Some(error),
+            _ => None,
+        };
+
+        FormulaResult {
+            value,
+            error,
+            circular_reference,
+            iteration,
+            execution_time_ms: execution_time,
+            cell_address: self.cell_address(id.col, id.row),
+            formula_type: if error.is_some() { "error".to_string() } else { self.classify_formula(compiled) },
+            spill_range: self.spill_range(id).map(|range| range.to_string()),
+        }
+    }
+
     fn validate_cell_reference(&self, sheet: &str, row: i32, col: i32) -> Result<(), String> {
-        if row < 1 || row > 1048576 {
+        if !(1..=1048576).contains(&row) {
             return Err(format!("Invalid row: {} (must be 1-1048576)", row));
         }
         
-        if col < 1 || col > 16384 {
+        if !(1..=16384).contains(&col) {
             return Err(format!("Invalid column: {} (must be 1-16384)", col));
         }
         
@@ -303,303 +822,998 @@ impl IronCalcEngine {
         Ok(())
     }
 
-    fn set_cell_value(&mut self, sheet_name: &str, row: i32, col: i32, value: &str, formula: Option<String>) -> Result<(), JsValue> {
-        let worksheet = self.worksheets.get_mut(sheet_name)
-            .ok_or_else(|| JsValue::from_str(&format!("Sheet '{}' not found", sheet_name)))?;
-        
-        worksheet.set_cell(row, col, value.to_string(), formula);
-        Ok(())
+    fn worksheet(&self, name: &str) -> Option<&Worksheet> {
+        self.worksheets.iter().find(|ws| ws.name == name)
     }
 
-    fn evaluate_formula_internal(&self, formula: &str, _sheet: &str, _row: i32, _col: i32) -> Result<String, String> {
-        // Remove the leading = if present
-        let formula = if formula.starts_with('=') {
-            &formula[1..]
-        } else {
-            formula
-        };
+    fn worksheet_mut(&mut self, name: &str) -> Option<&mut Worksheet> {
+        self.worksheets.iter_mut().find(|ws| ws.name == name)
+    }
 
-        // Simple formula evaluation (simulating IronCalc functionality)
-        // In a real implementation, this would use IronCalc's evaluation engine
-        match self.parse_and_evaluate(formula) {
-            Ok(result) => Ok(result),
-            Err(e) => Err(e),
-        }
+    /// Position of a worksheet in the tab order, ignoring case as Excel does
+    /// in formulas
+    fn find_worksheet_index(&self, name: &str) -> Option<usize> {
+        self.worksheets.iter().position(|ws| ws.name == name).or_else(|| {
+            let lowercase = name.to_lowercase();
+            self.worksheets.iter().position(|ws| ws.name.to_lowercase() == lowercase)
+        })
     }
 
-    fn parse_and_evaluate(&self, formula: &str) -> Result<String, String> {
-        let formula = formula.trim();
-        
-        // Handle Excel functions
-        if formula.starts_with("SUM(") {
-            return self.evaluate_sum_function(formula);
-        } else if formula.starts_with("AVERAGE(") {
-            return self.evaluate_average_function(formula);
-        } else if formula.starts_with("IF(") {
-            return self.evaluate_if_function(formula);
-        } else if formula.starts_with("MAX(") {
-            return self.evaluate_max_function(formula);
-        } else if formula.starts_with("MIN(") {
-            return self.evaluate_min_function(formula);
-        } else if formula.starts_with("COUNT(") {
-            return self.evaluate_count_function(formula);
-        }
-        
-        // Handle simple arithmetic expressions
-        if let Ok(result) = self.evaluate_arithmetic(formula) {
-            return Ok(result.to_string());
-        }
-        
-        // Handle string/number literals
-        if let Ok(num) = formula.parse::<f64>() {
-            return Ok(num.to_string());
+    fn find_worksheet(&self, name: &str) -> Option<&Worksheet> {
+        self.find_worksheet_index(name).map(|index| &self.worksheets[index])
+    }
+
+    fn insert_worksheet(&mut self, name: &str, index: usize) -> Result<(), String> {
+        if self.find_worksheet(name).is_some() {
+            return Err(format!("Sheet '{}' already exists", name));
         }
         
-        if formula.starts_with('"') && formula.ends_with('"') {
-            return Ok(formula[1..formula.len()-1].to_string());
+        if index > self.worksheets.len() {
+            return Err(format!("Invalid sheet position: {} (max {})", index, self.worksheets.len()));
         }
         
-        Err(format!("Unsupported formula: {}", formula))
+        self.worksheets.insert(index, Worksheet::new(name));
+
+        // Formulas that referred to the sheet before it existed now resolve
+        let mentioning: Vec<CellId> = self
+            .worksheets
+            .iter()
+            .flat_map(|ws| {
+                ws.cells
+                    .iter()
+                    .filter(|(_, cell)| cell.dependencies.iter().any(|precedent| precedent.mentions_sheet(name)))
+                    .map(|((row, col), _)| CellId::new(&ws.name, *row, *col))
+            })
+            .collect();
+        self.recalculate_or_mark_dirty(mentioning);
+
+        console_log!("Created new worksheet: {}", name);
+        Ok(())
     }
 
-    fn evaluate_sum_function(&self, formula: &str) -> Result<String, String> {
-        let args = self.extract_function_args(formula, "SUM")?;
-        let mut total = 0.0;
-        
-        for arg in args {
-            if let Ok(num) = arg.trim().parse::<f64>() {
-                total += num;
-            }
+    /// Store a constant in a cell, replacing any formula, and recalculate
+    /// its dependents.
+    fn set_cell_value(&mut self, sheet_name: &str, row: i32, col: i32, value: CellValue) -> Result<(), String> {
+        self.validate_cell_reference(sheet_name, row, col)?;
+        let sheet = self.find_worksheet(sheet_name)
+            .map(|ws| ws.name.clone())
+            .ok_or_else(|| format!("Sheet '{}' not found", sheet_name))?;
+        let id = CellId::new(&sheet, row, col);
+        self.ensure_outside_array_formulas(&id)?;
+        let table_users = if value == CellValue::Empty { Vec::new() } else { self.expand_tables(&id) };
+        let spilled = self.store_cell(&id, value, None, Vec::new())?;
+        self.recalculate_dependents(&id, spilled);
+        self.recalculate_or_mark_dirty(table_users);
+        Ok(())
+    }
+
+    /// The cells of a legacy array formula only change together, as in
+    /// Excel.
+    fn ensure_outside_array_formulas(&self, id: &CellId) -> Result<(), String> {
+        match self.worksheet(&id.sheet).and_then(|ws| ws.array_formula_covering(id.row, id.col)) {
+            Some((_, range)) => Err(format!("Cannot change part of the array formula in {}", range)),
+            None => Ok(()),
         }
-        
-        Ok(total.to_string())
     }
 
-    fn evaluate_average_function(&self, formula: &str) -> Result<String, String> {
-        let args = self.extract_function_args(formula, "AVERAGE")?;
-        let mut total = 0.0;
-        let mut count = 0;
-        
-        for arg in args {
-            if let Ok(num) = arg.trim().parse::<f64>() {
-                total += num;
-                count += 1;
+    /// Grow the tables that a value written to `id` appends a row to, and
+    /// return the formulas using them, whose precedents grow too.
+    fn expand_tables(&mut self, id: &CellId) -> Vec<CellId> {
+        self.tables
+            .expand(&id.sheet, id.row, id.col)
+            .iter()
+            .flat_map(|name| self.table_users(name))
+            .collect()
+    }
+
+    /// Write a cell and replace its edges in the dependency graph. A
+    /// formula with an array result spills it into the cells below and to
+    /// the right, or shows `#SPILL!` when they are not free; a legacy array
+    /// formula fills its range whatever it holds. Returns the cells that
+    /// started or stopped showing part of the cell's array.
+    fn store_cell(&mut self, id: &CellId, value: CellValue, formula: Option<Rc<Formula>>, dependencies: Vec<Precedent>) -> Result<Vec<CellId>, String> {
+        let volatile = formula.as_ref().is_some_and(|formula| formula.is_volatile());
+        let fixed = formula
+            .as_ref()
+            .and(self.worksheet(&id.sheet))
+            .and_then(|ws| ws.array_formulas.get(&(id.row, id.col)).copied());
+        let (value, spill) = match (&formula, &value, fixed) {
+            (Some(_), _, Some(range)) => (spill::fit(&value, &range), Some(range).filter(|range| range.start != range.end)),
+            (Some(_), CellValue::Array(rows), None) => match spill::extent(id.row, id.col, rows) {
+                Ok(range) => (value, range),
+                Err(error) => (CellValue::Error(error), None),
+            },
+            _ => (value, None),
+        };
+        // Arrays do not spill inside tables
+        let in_table = fixed.is_none() && spill.is_some_and(|range| self.tables.overlaps(&id.sheet, &range));
+        let worksheet = self.worksheet_mut(&id.sheet)
+            .ok_or_else(|| format!("Sheet '{}' not found", id.sheet))?;
+
+        let anchor = (id.row, id.col);
+        let cleared = worksheet.clear_spill(id.row, id.col);
+        let previous = worksheet.get_cell(id.row, id.col).map(|cell| cell.dependencies.clone()).unwrap_or_default();
+        let mut spilled = Vec::new();
+
+        match spill {
+            Some(range) if fixed.is_none() && (in_table || worksheet.blocks_spill(range, anchor)) => {
+                worksheet.set_cell(id.row, id.col, CellValue::Error(ExcelError::Spill), formula, dependencies.clone());
+                worksheet.spills.insert(anchor, Spill { range, blocked: true });
+            }
+            Some(range) => {
+                let CellValue::Array(rows) = &value else {
+                    unreachable!("only arrays spill");
+                };
+                for (row, values) in (range.start.row..).zip(rows) {
+                    for (col, element) in (range.start.col..).zip(values) {
+                        if (row, col) != anchor {
+                            worksheet.set_spilled_value(row, col, element.clone(), anchor);
+                            spilled.push((row, col));
+                        }
+                    }
+                }
+                worksheet.set_cell(id.row, id.col, value, formula, dependencies.clone());
+                worksheet.spills.insert(anchor, Spill { range, blocked: false });
             }
+            None => worksheet.set_cell(id.row, id.col, value, formula, dependencies.clone()),
         }
-        
-        if count == 0 {
-            return Err("#DIV/0!".to_string());
+
+        // Cells spilled into before and after only changed value, which
+        // their readers learn through the dependency on this cell
+        let changed: Vec<CellId> = cleared
+            .iter()
+            .filter(|position| !spilled.contains(position))
+            .chain(spilled.iter().filter(|position| !cleared.contains(position)))
+            .map(|(row, col)| CellId::new(&id.sheet, *row, *col))
+            .collect();
+        let spill = self.spill_range(id);
+
+        self.dependency_graph.remove(id, &previous);
+        self.dependency_graph.add(id, &dependencies);
+        self.dependency_graph.set_spill(id, spill);
+        self.formula_cache.remove(id);
+        if volatile {
+            self.volatile_cells.insert(id.clone());
+        } else {
+            self.volatile_cells.remove(id);
         }
-        
-        Ok((total / count as f64).to_string())
+        Ok(changed)
     }
 
-    fn evaluate_if_function(&self, formula: &str) -> Result<String, String> {
-        let args = self.extract_function_args(formula, "IF")?;
-        
-        if args.len() < 2 {
-            return Err("#VALUE!".to_string());
+    /// The cells the formula in `id` spills into, when it spilled.
+    fn spill_range(&self, id: &CellId) -> Option<RangeRef> {
+        self.worksheet(&id.sheet)?
+            .spills
+            .get(&(id.row, id.col))
+            .filter(|spill| !spill.blocked)
+            .map(|spill| spill.range)
+    }
+
+    /// Formula cells whose spill range, blocked or not, covers one of
+    /// `cells` without the cell showing part of their own array. Writing
+    /// or clearing such a cell may block or unblock the spill.
+    fn spill_anchors_covering(&self, cells: &[CellId]) -> Vec<CellId> {
+        let mut anchors: Vec<CellId> = cells
+            .iter()
+            .filter_map(|id| Some((id, self.worksheet(&id.sheet)?)))
+            .flat_map(|(id, worksheet)| {
+                let shown_from = worksheet.get_cell(id.row, id.col).and_then(|cell| cell.spilled_from);
+                worksheet
+                    .spills
+                    .iter()
+                    .filter(move |(anchor, spill)| {
+                        **anchor != (id.row, id.col) && Some(**anchor) != shown_from && spill.range.contains(id.row, id.col)
+                    })
+                    .map(|((row, col), _)| CellId::new(&worksheet.name, *row, *col))
+            })
+            .collect();
+        anchors.sort();
+        anchors.dedup();
+        anchors
+    }
+
+    /// Recalculate every formula that depends on `id`, directly or not, and
+    /// the volatile ones. `id` itself is assumed up to date unless it turns
+    /// out to be part of a circular reference. `spilled` are the cells that
+    /// writing `id` spilled into or cleared, whose readers and the spills
+    /// they block are recalculated too. Returns the circular references
+    /// found. In manual calculation mode the dependents are only marked
+    /// dirty.
+    fn recalculate_dependents(&mut self, id: &CellId, spilled: Vec<CellId>) -> Vec<Vec<CellId>> {
+        let mut written = spilled;
+        written.push(id.clone());
+        let anchors = self.spill_anchors_covering(&written);
+
+        if self.calculation_mode == CalculationMode::Manual {
+            let sheets = self.get_sheet_names();
+            let mut dependents: Vec<CellId> = written
+                .iter()
+                .flat_map(|cell| self.dependency_graph.dependents(cell, &sheets))
+                .collect();
+            dependents.extend(anchors);
+            self.mark_dirty(dependents);
+            return Vec::new();
         }
-        
-        let condition = args[0].trim();
-        let true_value = args.get(1).map(|s| s.trim()).unwrap_or("");
-        let false_value = args.get(2).map(|s| s.trim()).unwrap_or("FALSE");
-        
-        // Simple condition evaluation (just check if condition contains ">" and evaluate)
-        let result = if condition.contains(">") {
-            let parts: Vec<&str> = condition.split('>').collect();
-            if parts.len() == 2 {
-                if let (Ok(left), Ok(right)) = (parts[0].trim().parse::<f64>(), parts[1].trim().parse::<f64>()) {
-                    left > right
-                } else {
-                    false
+
+        let mut cells = written;
+        cells.extend(anchors);
+        cells.extend(self.volatile_cells.iter().filter(|cell| *cell != id).cloned());
+        self.recalculate(&cells, Some(id), None)
+    }
+
+    /// Recalculate the formulas in `cells` and everything depending on them,
+    /// each after its precedents.
+    fn recalculate_cells(&mut self, cells: &[CellId]) -> Vec<Vec<CellId>> {
+        self.recalculate(cells, None, None)
+    }
+
+    /// Like `recalculate_cells`, but only touching cells on `sheet`.
+    fn recalculate_within(&mut self, cells: &[CellId], sheet: Option<&str>) -> Vec<Vec<CellId>> {
+        self.recalculate(cells, None, sheet)
+    }
+
+    fn recalculate(&mut self, cells: &[CellId], up_to_date: Option<&CellId>, only_sheet: Option<&str>) -> Vec<Vec<CellId>> {
+        let mut cycles = Vec::new();
+        let mut iteration: Option<IterationStatus> = None;
+
+        let sheets = self.get_sheet_names();
+        let steps = self.dependency_graph.recalculation_order(cells, &sheets);
+        let steps = steps.into_iter().filter(|step| match (step, only_sheet) {
+            (_, None) => true,
+            (Step::Cell(id), Some(sheet)) => id.sheet == sheet,
+            (Step::Cycle(cells), Some(sheet)) => cells.iter().all(|id| id.sheet == sheet),
+        });
+        let (mut spilled, mut spilling) = (Vec::new(), Vec::new());
+
+        for step in steps {
+            match step {
+                Step::Cell(id) if Some(&id) == up_to_date => {}
+                Step::Cell(id) => {
+                    let changed = self.recalculate_cell(&id);
+                    if !changed.is_empty() {
+                        spilled.extend(changed);
+                        spilling.push(id);
+                    }
                 }
-            } else {
-                false
-            }
-        } else if condition.contains("<") {
-            let parts: Vec<&str> = condition.split('<').collect();
-            if parts.len() == 2 {
-                if let (Ok(left), Ok(right)) = (parts[0].trim().parse::<f64>(), parts[1].trim().parse::<f64>()) {
-                    left < right
-                } else {
-                    false
+                Step::Cycle(cells) if self.iteration_settings.enabled => {
+                    // Excel counts the first calculation of a formula just
+                    // entered as the first iteration, so it starts blank
+                    if let Some(id) = up_to_date.filter(|id| cells.contains(id)) {
+                        self.set_computed_value(id, CellValue::Empty);
+                    }
+                    let status = self.iterate_cycle(&cells);
+                    iteration = Some(match iteration {
+                        None => status,
+                        Some(so_far) => IterationStatus {
+                            iterations: so_far.iterations.max(status.iterations),
+                            converged: so_far.converged && status.converged,
+                            max_change: so_far.max_change.max(status.max_change),
+                        },
+                    });
+                    cycles.push(cells);
+                }
+                // Cells in a circle have no consistent value; formulas that
+                // read them see the error
+                Step::Cycle(cells) => {
+                    for id in &cells {
+                        self.set_computed_value(id, CellValue::Error(ExcelError::Circular));
+                    }
+                    cycles.push(cells);
                 }
-            } else {
-                false
             }
-        } else {
-            // Try to parse as boolean or number
-            condition.parse::<f64>().map(|n| n != 0.0).unwrap_or(false)
+        }
+
+        if iteration.is_some() {
+            self.last_iteration = iteration;
+        }
+
+        // A spill that grew or shrank moved values into or out of cells
+        // whose readers were not known to depend on it when the order was
+        // worked out, and may block or unblock other spills
+        if !spilled.is_empty() {
+            let mut followers: Vec<CellId> = spilled
+                .iter()
+                .flat_map(|cell| self.dependency_graph.dependents(cell, &sheets))
+                .chain(self.spill_anchors_covering(&spilled).into_iter().filter(|anchor| !spilling.contains(anchor)))
+                .collect();
+            followers.sort();
+            followers.dedup();
+            cycles.extend(self.recalculate(&followers, None, only_sheet));
+        }
+        cycles
+    }
+
+    /// Recalculate the cells of a circular reference repeatedly, starting
+    /// from their current values, until no cell changes by more than the
+    /// configured maximum change or the iteration limit is reached.
+    fn iterate_cycle(&mut self, cells: &[CellId]) -> IterationStatus {
+        for id in cells {
+            if self.cell_value(id) == CellValue::Error(ExcelError::Circular) {
+                self.set_computed_value(id, CellValue::Empty);
+            }
+        }
+
+        let mut status = IterationStatus {
+            iterations: 0,
+            converged: false,
+            max_change: 0.0,
         };
-        
-        Ok(if result { 
-            // Remove quotes if present
-            if true_value.starts_with('"') && true_value.ends_with('"') {
-                true_value[1..true_value.len()-1].to_string()
-            } else {
-                true_value.to_string()
+
+        while status.iterations < self.iteration_settings.max_iterations {
+            status.iterations += 1;
+            status.max_change = 0.0;
+            let mut settled = true;
+
+            for id in cells {
+                let previous = self.cell_value(id);
+                let _ = self.recalculate_cell(id);
+                match (previous, self.cell_value(id)) {
+                    (CellValue::Number(before), CellValue::Number(after)) => {
+                        status.max_change = status.max_change.max((after - before).abs());
+                    }
+                    (before, after) => settled &= before == after,
+                }
             }
-        } else { 
-            if false_value.starts_with('"') && false_value.ends_with('"') {
-                false_value[1..false_value.len()-1].to_string()
-            } else {
-                false_value.to_string()
+
+            if settled && status.max_change <= self.iteration_settings.max_change {
+                status.converged = true;
+                break;
             }
-        })
+        }
+
+        status
     }
 
-    fn evaluate_max_function(&self, formula: &str) -> Result<String, String> {
-        let args = self.extract_function_args(formula, "MAX")?;
-        let mut max_val = f64::NEG_INFINITY;
-        let mut found_number = false;
-        
-        for arg in args {
-            if let Ok(num) = arg.trim().parse::<f64>() {
-                if num > max_val {
-                    max_val = num;
-                }
-                found_number = true;
+    fn cell_value(&self, id: &CellId) -> CellValue {
+        self.worksheet(&id.sheet)
+            .and_then(|ws| ws.get_cell(id.row, id.col))
+            .map(|cell| cell.value.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replace the value of a formula cell without touching its formula,
+    /// clearing whatever it spilled.
+    fn set_computed_value(&mut self, id: &CellId, value: CellValue) {
+        if let Some(worksheet) = self.worksheet_mut(&id.sheet) {
+            worksheet.clear_spill(id.row, id.col);
+            if let Some(cell) = worksheet.cells.get_mut(&(id.row, id.col)) {
+                cell.value = value;
+                cell.dirty = false;
             }
         }
-        
-        if !found_number {
-            return Err("#VALUE!".to_string());
+        self.dependency_graph.set_spill(id, None);
+        self.formula_cache.remove(id);
+    }
+
+    /// Flag `cells` and every formula depending on them as dirty. Cells
+    /// already dirty are not followed again, since their dependents were
+    /// flagged along with them, so a bulk edit looks up the dependents of
+    /// each formula it reaches once.
+    fn mark_dirty(&mut self, cells: Vec<CellId>) {
+        let sheets = self.get_sheet_names();
+        let mut pending = cells;
+
+        while let Some(id) = pending.pop() {
+            let Some(cell) = self.worksheet_mut(&id.sheet).and_then(|ws| ws.cells.get_mut(&(id.row, id.col))) else {
+                continue;
+            };
+            if cell.dirty || cell.formula.is_none() {
+                continue;
+            }
+
+            cell.dirty = true;
+            self.formula_cache.remove(&id);
+            pending.extend(self.dependency_graph.dependents(&id, &sheets));
         }
-        
-        Ok(max_val.to_string())
     }
 
-    fn evaluate_min_function(&self, formula: &str) -> Result<String, String> {
-        let args = self.extract_function_args(formula, "MIN")?;
-        let mut min_val = f64::INFINITY;
-        let mut found_number = false;
-        
-        for arg in args {
-            if let Ok(num) = arg.trim().parse::<f64>() {
-                if num < min_val {
-                    min_val = num;
-                }
-                found_number = true;
+    /// Formula cells due for recalculation, on one sheet or all of them:
+    /// the dirty ones and the volatile ones, which are always due.
+    fn dirty_cells(&self, sheet: Option<&str>) -> Vec<CellId> {
+        let mut cells: Vec<CellId> = self
+            .worksheets
+            .iter()
+            .filter(|ws| sheet.is_none_or(|name| ws.name == name))
+            .flat_map(|ws| {
+                ws.cells
+                    .iter()
+                    .filter(|(_, cell)| cell.dirty)
+                    .map(|((row, col), _)| CellId::new(&ws.name, *row, *col))
+            })
+            .collect();
+
+        cells.extend(
+            self.volatile_cells
+                .iter()
+                .filter(|id| sheet.is_none_or(|name| id.sheet == name))
+                .cloned(),
+        );
+        cells
+    }
+
+    fn recalculate_worksheet(&mut self, name: &str) -> Result<(), String> {
+        let sheet = self.find_worksheet(name)
+            .map(|ws| ws.name.clone())
+            .ok_or_else(|| format!("Sheet '{}' not found", name))?;
+
+        let dirty = self.dirty_cells(Some(&sheet));
+        self.recalculate_within(&dirty, Some(&sheet));
+        Ok(())
+    }
+
+    fn change_calculation_mode(&mut self, mode: CalculationMode) {
+        let was_manual = self.calculation_mode == CalculationMode::Manual;
+        self.calculation_mode = mode;
+
+        // Leaving manual mode brings the workbook up to date, as Excel does
+        if was_manual && mode != CalculationMode::Manual {
+            let dirty = self.dirty_cells(None);
+            self.recalculate_cells(&dirty);
+        }
+    }
+
+    fn change_date_system(&mut self, system: DateSystem) {
+        if self.date_system != system {
+            self.date_system = system;
+            self.recalculate_all();
+        }
+    }
+
+    /// Add a custom function to the registry, or replace a custom function
+    /// of the same name, and recalculate the formulas calling it.
+    fn register_function(&mut self, function: Function) -> Result<(), String> {
+        let name = function.name.to_uppercase();
+        if !names::is_valid_name(&name) {
+            return Err(format!("Invalid function name: {}", function.name));
+        }
+
+        if self.functions.get(&name).is_some_and(|existing| existing.category != Category::Custom) {
+            return Err(format!("Cannot replace built-in function {}", name));
+        }
+
+        if function.max_args.is_some_and(|max| max < function.min_args) {
+            return Err(format!("Invalid arguments for {}: the maximum is below the minimum", name));
+        }
+
+        self.functions.register(function);
+
+        // Formulas calling the function so far evaluated to #NAME?, or used
+        // the function being replaced
+        let callers: Vec<CellId> = self
+            .worksheets
+            .iter()
+            .flat_map(|ws| {
+                ws.cells
+                    .iter()
+                    .filter(|(_, cell)| {
+                        cell.formula
+                            .as_ref()
+                            .is_some_and(|formula| functions::calls_any(formula.expr(), &|called| called == name))
+                    })
+                    .map(|((row, col), _)| CellId::new(&ws.name, *row, *col))
+            })
+            .collect();
+        self.recalculate_or_mark_dirty(callers);
+
+        console_log!("Registered custom function: {}", name);
+        Ok(())
+    }
+
+    /// The sheet a name is scoped to, spelled as the worksheet is.
+    fn name_scope(&self, sheet: Option<&str>) -> Result<Option<String>, String> {
+        sheet
+            .map(|name| {
+                self.find_worksheet(name)
+                    .map(|ws| ws.name.clone())
+                    .ok_or_else(|| format!("Sheet '{}' not found", name))
+            })
+            .transpose()
+    }
+
+    fn define_name(&mut self, name: &str, refers_to: &str, sheet: Option<&str>) -> Result<(), String> {
+        let sheet = self.name_scope(sheet)?;
+        if !names::is_valid_name(name) {
+            return Err(format!("Invalid name: {}", name));
+        }
+        if self.names.get(name, sheet.as_deref()).is_some() || self.tables.get(name).is_some() {
+            return Err(format!("Name '{}' already exists", name));
+        }
+
+        self.set_name(name.to_string(), refers_to, sheet)
+    }
+
+    fn update_name(&mut self, name: &str, refers_to: &str, sheet: Option<&str>) -> Result<(), String> {
+        let sheet = self.name_scope(sheet)?;
+        let existing = self.names.get(name, sheet.as_deref())
+            .ok_or_else(|| format!("Name '{}' not found", name))?;
+
+        self.set_name(existing.name.clone(), refers_to, sheet)
+    }
+
+    fn set_name(&mut self, name: String, refers_to: &str, sheet: Option<String>) -> Result<(), String> {
+        let formula = self.compile(refers_to)?;
+        let users = self.name_users(&name);
+        self.names.insert(DefinedName { name, sheet, formula });
+        self.recalculate_or_mark_dirty(users);
+        Ok(())
+    }
+
+    fn delete_name(&mut self, name: &str, sheet: Option<&str>) -> Result<(), String> {
+        let sheet = self.name_scope(sheet)?;
+        let users = self.name_users(name);
+        self.names.remove(name, sheet.as_deref())
+            .ok_or_else(|| format!("Name '{}' not found", name))?;
+
+        self.recalculate_or_mark_dirty(users);
+        Ok(())
+    }
+
+    /// Rename a name and rewrite the formulas that see it, in cells and in
+    /// other names, to use the new name.
+    fn rename_name(&mut self, name: &str, new_name: &str, sheet: Option<&str>) -> Result<(), String> {
+        let sheet = self.name_scope(sheet)?;
+        if !names::is_valid_name(new_name) {
+            return Err(format!("Invalid name: {}", new_name));
+        }
+        if !new_name.eq_ignore_ascii_case(name)
+            && (self.names.get(new_name, sheet.as_deref()).is_some() || self.tables.get(new_name).is_some())
+        {
+            return Err(format!("Name '{}' already exists", new_name));
+        }
+        let mut renamed = self.names.remove(name, sheet.as_deref())
+            .ok_or_else(|| format!("Name '{}' not found", name))?;
+
+        // A workbook name is not seen from sheets with a name of their own
+        // spelled the same
+        let sees_name = |names: &Names, formula_sheet: Option<&str>| match (&sheet, formula_sheet) {
+            (Some(scope), Some(formula_sheet)) => scope == formula_sheet,
+            (Some(_), None) => false,
+            (None, Some(formula_sheet)) => names.get(name, Some(formula_sheet)).is_none(),
+            (None, None) => true,
+        };
+
+        let rewritten: Vec<(CellId, String)> = self
+            .worksheets
+            .iter()
+            .filter(|ws| sees_name(&self.names, Some(&ws.name)))
+            .flat_map(|ws| {
+                ws.cells.iter().filter_map(|((row, col), cell)| {
+                    let text = names::rename_in_formula(cell.formula.as_ref()?.text(), name, new_name)?;
+                    Some((CellId::new(&ws.name, *row, *col), text))
+                })
+            })
+            .collect();
+        for (id, text) in rewritten {
+            let formula = self.compile(&text)?;
+            if let Some(cell) = self.worksheet_mut(&id.sheet).and_then(|ws| ws.cells.get_mut(&(id.row, id.col))) {
+                cell.formula = Some(formula);
             }
+            self.formula_cache.remove(&id);
         }
-        
-        if !found_number {
-            return Err("#VALUE!".to_string());
+
+        let rewritten: Vec<(String, Option<String>, String)> = self
+            .names
+            .iter()
+            .filter(|defined| sees_name(&self.names, defined.sheet.as_deref()))
+            .filter_map(|defined| {
+                let text = names::rename_in_formula(defined.formula.text(), name, new_name)?;
+                Some((defined.name.clone(), defined.sheet.clone(), text))
+            })
+            .collect();
+        for (other, other_sheet, text) in rewritten {
+            let formula = self.compile(&text)?;
+            if let Some(defined) = self.names.iter_mut().find(|defined| {
+                defined.name == other && defined.sheet == other_sheet
+            }) {
+                defined.formula = formula;
+            }
         }
-        
-        Ok(min_val.to_string())
+        if let Some(text) = names::rename_in_formula(renamed.formula.text(), name, new_name) {
+            renamed.formula = self.compile(&text)?;
+        }
+
+        // Formulas that were waiting for a name spelled like the new one
+        // now find it
+        renamed.name = new_name.to_string();
+        let users = self.name_users(new_name);
+        self.names.insert(renamed);
+        self.recalculate_or_mark_dirty(users);
+        Ok(())
     }
 
-    fn evaluate_count_function(&self, formula: &str) -> Result<String, String> {
-        let args = self.extract_function_args(formula, "COUNT")?;
-        let mut count = 0;
-        
-        for arg in args {
-            if arg.trim().parse::<f64>().is_ok() {
-                count += 1;
+    /// The defined names, workbook names first, each group sorted by name.
+    fn list_names(&self) -> Vec<NameInfo> {
+        let mut names: Vec<NameInfo> = self
+            .names
+            .iter()
+            .map(|defined| NameInfo {
+                name: defined.name.clone(),
+                refers_to: defined.formula.text().to_string(),
+                sheet: defined.sheet.clone(),
+            })
+            .collect();
+        names.sort_by_key(|info| (info.sheet.clone(), info.name.to_uppercase()));
+        names
+    }
+
+    /// Formula cells whose value depends on the name `name`, directly or
+    /// through other names, whichever scope they see it in.
+    fn name_users(&self, name: &str) -> Vec<CellId> {
+        let mut affected = vec![name.to_string()];
+        let uses_affected = |expr: &Expr, affected: &[String]| {
+            names::mentioned(expr).iter().any(|used| affected.iter().any(|name| scope::same_name(used, name)))
+        };
+
+        loop {
+            let using: Vec<String> = self
+                .names
+                .iter()
+                .filter(|defined| !affected.iter().any(|name| scope::same_name(&defined.name, name)))
+                .filter(|defined| uses_affected(defined.formula.expr(), &affected))
+                .map(|defined| defined.name.clone())
+                .collect();
+            if using.is_empty() {
+                break;
             }
+            affected.extend(using);
         }
-        
-        Ok(count.to_string())
+
+        self.worksheets
+            .iter()
+            .flat_map(|ws| {
+                ws.cells
+                    .iter()
+                    .filter(|(_, cell)| cell.formula.as_ref().is_some_and(|formula| uses_affected(formula.expr(), &affected)))
+                    .map(|((row, col), _)| CellId::new(&ws.name, *row, *col))
+            })
+            .collect()
     }
 
-    fn extract_function_args(&self, formula: &str, function_name: &str) -> Result<Vec<String>, String> {
-        let start_pattern = format!("{}(", function_name);
-        
-        if !formula.starts_with(&start_pattern) {
-            return Err(format!("Invalid {} function format", function_name));
+    fn create_table(&mut self, name: &str, sheet_name: &str, range: &str, totals_row: bool) -> Result<(), String> {
+        let sheet = self.find_worksheet(sheet_name)
+            .map(|ws| ws.name.clone())
+            .ok_or_else(|| format!("Sheet '{}' not found", sheet_name))?;
+        if !names::is_valid_name(name) {
+            return Err(format!("Invalid table name: {}", name));
         }
-        
-        if !formula.ends_with(')') {
-            return Err("Missing closing parenthesis".to_string());
+        if self.tables.get(name).is_some() || self.names.iter().any(|defined| scope::same_name(&defined.name, name)) {
+            return Err(format!("Name '{}' already exists", name));
         }
-        
-        let args_str = &formula[start_pattern.len()..formula.len()-1];
-        
-        // Simple argument parsing (doesn't handle nested functions properly)
-        let args: Vec<String> = args_str.split(',').map(|s| s.to_string()).collect();
-        
-        Ok(args)
+
+        let range = parse_address_range(range)
+            .ok_or_else(|| format!("Invalid table range: {}", range))?;
+        // A header row, a data row and the totals row if there is one
+        let min_rows = if totals_row { 3 } else { 2 };
+        if range.end.row - range.start.row + 1 < min_rows {
+            return Err(format!("Table range {} is too small", range));
+        }
+        if self.tables.overlaps(&sheet, &range) {
+            return Err(format!("Table range {} overlaps another table", range));
+        }
+        if self.worksheet(&sheet).is_some_and(|ws| ws.array_formulas.values().any(|array| array.overlaps(&range))) {
+            return Err(format!("Table range {} overlaps an array formula", range));
+        }
+
+        self.tables.insert(Table {
+            name: name.to_string(),
+            sheet: sheet.clone(),
+            range,
+            totals_row,
+        });
+
+        // Every column needs a name for structured references to use
+        let header = range.start.row;
+        let unnamed: Vec<i32> = (range.start.col..=range.end.col)
+            .filter(|col| {
+                self.worksheet(&sheet)
+                    .and_then(|ws| ws.get_cell(header, *col))
+                    .is_none_or(|cell| cell.value.to_display_string().is_empty())
+            })
+            .collect();
+        for col in unnamed {
+            let name = CellValue::Text(format!("Column{}", col - range.start.col + 1));
+            self.set_cell_value(&sheet, header, col, name)?;
+        }
+
+        // Formulas using the table so far evaluated to #REF!
+        let users = self.table_users(name);
+        self.recalculate_or_mark_dirty(users);
+        Ok(())
     }
 
-    fn evaluate_arithmetic(&self, formula: &str) -> Result<f64, String> {
-        // Very simple arithmetic evaluation (addition, subtraction, multiplication, division)
-        // In a real implementation, this would use a proper expression parser
-        
-        if formula.contains('+') {
-            let parts: Vec<&str> = formula.split('+').collect();
-            if parts.len() == 2 {
-                let left = parts[0].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                let right = parts[1].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                return Ok(left + right);
+    fn delete_table(&mut self, name: &str) -> Result<(), String> {
+        let users = self.table_users(name);
+        self.tables.remove(name)
+            .ok_or_else(|| format!("Table '{}' not found", name))?;
+
+        self.recalculate_or_mark_dirty(users);
+        Ok(())
+    }
+
+    /// The tables, sorted by name.
+    fn list_tables(&self) -> Vec<TableInfo> {
+        let mut tables: Vec<TableInfo> = self
+            .tables
+            .iter()
+            .map(|table| TableInfo {
+                name: table.name.clone(),
+                sheet: table.sheet.clone(),
+                range: table.range.to_string(),
+                columns: self.worksheet(&table.sheet).map(|ws| table.column_names(ws)).unwrap_or_default(),
+                totals_row: table.totals_row,
+            })
+            .collect();
+        tables.sort_by_key(|info| info.name.to_uppercase());
+        tables
+    }
+
+    /// Formula cells that may use the table `name`: those naming it in a
+    /// structured reference, directly or through defined names, and those
+    /// with unqualified structured references, which refer to the table
+    /// they are in.
+    fn table_users(&self, name: &str) -> Vec<CellId> {
+        let uses_table = |expr: &Expr| {
+            tables::structured_refs(expr)
+                .iter()
+                .any(|reference| reference.table.as_deref().is_none_or(|table| scope::same_name(table, name)))
+        };
+
+        let mut users: Vec<CellId> = self
+            .worksheets
+            .iter()
+            .flat_map(|ws| {
+                ws.cells
+                    .iter()
+                    .filter(|(_, cell)| cell.formula.as_ref().is_some_and(|formula| uses_table(formula.expr())))
+                    .map(|((row, col), _)| CellId::new(&ws.name, *row, *col))
+            })
+            .collect();
+        for defined in self.names.iter().filter(|defined| uses_table(defined.formula.expr())) {
+            users.extend(self.name_users(&defined.name));
+        }
+
+        users.sort();
+        users.dedup();
+        users
+    }
+
+    /// Enter a legacy array formula over `range`, replacing what the range
+    /// held, array formulas entirely inside it included.
+    fn set_array_formula(&mut self, formula: &str, sheet_name: &str, range: &str) -> Result<FormulaResult, String> {
+        let start_time = now_ms();
+        let sheet = self.find_worksheet(sheet_name)
+            .map(|ws| ws.name.clone())
+            .ok_or_else(|| format!("Sheet '{}' not found", sheet_name))?;
+        let range = parse_address_range(range)
+            .ok_or_else(|| format!("Invalid array formula range: {}", range))?;
+        if self.tables.overlaps(&sheet, &range) {
+            return Err(format!("Array formula range {} overlaps a table", range));
+        }
+
+        let compiled = self.compile(formula)?;
+
+        let worksheet = self.worksheet(&sheet).ok_or_else(|| format!("Sheet '{}' not found", sheet))?;
+        let mut replaced = Vec::new();
+        for (anchor, other) in worksheet.array_formulas.iter().filter(|(_, other)| other.overlaps(&range)) {
+            if !(range.contains(other.start.row, other.start.col) && range.contains(other.end.row, other.end.col)) {
+                return Err(format!("Cannot change part of the array formula in {}", other));
             }
+            replaced.push(*anchor);
         }
-        
-        if formula.contains('-') && !formula.starts_with('-') {
-            let parts: Vec<&str> = formula.split('-').collect();
-            if parts.len() == 2 {
-                let left = parts[0].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                let right = parts[1].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                return Ok(left - right);
+
+        // Values, formulas and their spills make way; arrays spilled from
+        // outside the range become blocked by it
+        let occupied: Vec<CellId> = worksheet
+            .cells_in_range(range)
+            .filter(|(_, cell)| cell.spilled_from.is_none())
+            .map(|((row, col), _)| CellId::new(&sheet, *row, *col))
+            .collect();
+        if let Some(worksheet) = self.worksheet_mut(&sheet) {
+            for anchor in &replaced {
+                worksheet.array_formulas.remove(anchor);
             }
         }
-        
-        if formula.contains('*') {
-            let parts: Vec<&str> = formula.split('*').collect();
-            if parts.len() == 2 {
-                let left = parts[0].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                let right = parts[1].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                return Ok(left * right);
+        let mut written = Vec::new();
+        for id in occupied {
+            written.extend(self.store_cell(&id, CellValue::Empty, None, Vec::new())?);
+            written.push(id);
+        }
+
+        let id = CellId::new(&sheet, range.start.row, range.start.col);
+        if let Some(worksheet) = self.worksheet_mut(&sheet) {
+            worksheet.array_formulas.insert((id.row, id.col), range);
+        }
+        let (value, precedents) = self.evaluate_compiled(&compiled, &id)?;
+        written.extend(self.store_cell(&id, value, Some(Rc::clone(&compiled)), precedents)?);
+        let cycles = self.recalculate_dependents(&id, written);
+
+        Ok(self.formula_result(&id, &compiled, &cycles, (now_ms() - start_time) as u32))
+    }
+
+    fn delete_array_formula(&mut self, sheet_name: &str, row: i32, col: i32) -> Result<(), String> {
+        let worksheet = self.find_worksheet(sheet_name)
+            .ok_or_else(|| format!("Sheet '{}' not found", sheet_name))?;
+        let ((anchor_row, anchor_col), _) = worksheet.array_formula_covering(row, col)
+            .ok_or_else(|| format!("No array formula at {}", self.cell_address(col, row)))?;
+
+        let id = CellId::new(&worksheet.name, anchor_row, anchor_col);
+        if let Some(worksheet) = self.worksheet_mut(&id.sheet) {
+            worksheet.array_formulas.remove(&(anchor_row, anchor_col));
+        }
+        let cleared = self.store_cell(&id, CellValue::Empty, None, Vec::new())?;
+        self.recalculate_dependents(&id, cleared);
+        Ok(())
+    }
+
+    /// Bring `cells` and their dependents up to date, or only flag them in
+    /// manual calculation mode.
+    fn recalculate_or_mark_dirty(&mut self, cells: Vec<CellId>) {
+        if self.calculation_mode == CalculationMode::Manual {
+            self.mark_dirty(cells);
+        } else {
+            self.recalculate_cells(&cells);
+        }
+    }
+
+    fn configure_iteration(&mut self, settings: IterationSettings) -> Result<(), String> {
+        if !(1..=32767).contains(&settings.max_iterations) {
+            return Err(format!("Invalid maximum iterations: {} (must be 1-32767)", settings.max_iterations));
+        }
+
+        if !(settings.max_change >= 0.0 && settings.max_change.is_finite()) {
+            return Err(format!("Invalid maximum change: {} (must be zero or positive)", settings.max_change));
+        }
+
+        self.iteration_settings = settings;
+        Ok(())
+    }
+
+    /// Recalculate the formula in `id`, returning the cells its spill grew
+    /// into or shrank from.
+    fn recalculate_cell(&mut self, id: &CellId) -> Vec<CellId> {
+        let Some(formula) = self
+            .worksheet(&id.sheet)
+            .and_then(|ws| ws.get_cell(id.row, id.col))
+            .and_then(|cell| cell.formula.clone())
+        else {
+            return Vec::new();
+        };
+
+        // Only fails if the sheet has gone
+        self.evaluate_compiled(&formula, id)
+            .and_then(|(value, precedents)| self.store_cell(id, value, Some(formula), precedents))
+            .unwrap_or_default()
+    }
+
+    /// The compiled form of a formula, parsing it only if it is not among
+    /// the recently compiled ones.
+    fn compile(&mut self, formula: &str) -> Result<Rc<Formula>, String> {
+        let key = formula.to_string();
+        if let Some(compiled) = self.compiled_formulas.get(&key) {
+            return Ok(Rc::clone(compiled));
+        }
+
+        let compiled = Rc::new(Formula::compile(formula, &self.functions)?);
+        self.compiled_formulas.insert(key, Rc::clone(&compiled));
+        Ok(compiled)
+    }
+
+    /// Evaluate a compiled formula to its value and collect its precedents.
+    /// Excel errors are returned as `CellValue::Error`; `Err` is reserved for
+    /// engine-level failures such as a sheet that does not exist.
+    fn evaluate_compiled(&self, formula: &Formula, id: &CellId) -> Result<(CellValue, Vec<Precedent>), String> {
+        let worksheet = self.worksheet(&id.sheet)
+            .ok_or_else(|| format!("Sheet '{}' not found", id.sheet))?;
+
+        let expr = formula.expr();
+        let value = match formula.evaluate(&Evaluator::new(self, worksheet).at(id.row, id.col)) {
+            // A formula that only reads a blank cell evaluates to 0
+            Ok(CellValue::Empty) => CellValue::Number(0.0),
+            // A lambda is only a value inside a formula, never a result
+            Ok(CellValue::Lambda(_)) => CellValue::Error(ExcelError::Calc),
+            // Likewise for the elements of an array, which spill into cells
+            Ok(CellValue::Array(rows)) => CellValue::Array(
+                rows.into_iter()
+                    .map(|row| {
+                        row.into_iter()
+                            .map(|element| match element {
+                                CellValue::Empty => CellValue::Number(0.0),
+                                CellValue::Lambda(_) => CellValue::Error(ExcelError::Calc),
+                                element => element,
+                            })
+                            .collect()
+                    })
+                    .collect(),
+            ),
+            Ok(value) => value,
+            Err(error) => CellValue::Error(error),
+        };
+
+        let resolve_sheet = |name: Option<&str>| match name {
+            None => worksheet.name.clone(),
+            Some(name) => self.find_worksheet(name).map_or_else(|| name.to_string(), |ws| ws.name.clone()),
+        };
+        let mut precedents = dependencies::precedents(expr, &resolve_sheet);
+        let mut structured_refs = tables::structured_refs(expr);
+
+        // The formula also reads whatever the names it uses read
+        if !self.names.is_empty() {
+            let mut pending = names::mentioned(expr);
+            let mut seen: Vec<&DefinedName> = Vec::new();
+            while let Some(name) = pending.pop() {
+                let Some(defined) = self.names.resolve(name, &worksheet.name) else {
+                    continue;
+                };
+                if seen.iter().any(|other| std::ptr::eq(*other, defined)) {
+                    continue;
+                }
+                seen.push(defined);
+
+                for precedent in dependencies::precedents(defined.formula.expr(), &resolve_sheet) {
+                    if !precedents.contains(&precedent) {
+                        precedents.push(precedent);
+                    }
+                }
+                structured_refs.extend(tables::structured_refs(defined.formula.expr()));
+                pending.extend(names::mentioned(defined.formula.expr()));
             }
         }
-        
-        if formula.contains('/') {
-            let parts: Vec<&str> = formula.split('/').collect();
-            if parts.len() == 2 {
-                let left = parts[0].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                let right = parts[1].trim().parse::<f64>().map_err(|_| "Invalid number")?;
-                if right == 0.0 {
-                    return Err("#DIV/0!".to_string());
+
+        // Structured references read the cells their table covers now, and
+        // the header row their columns are looked up in
+        for reference in structured_refs {
+            let Some(table) = self.tables.find(reference, &worksheet.name, Some((id.row, id.col))) else {
+                continue;
+            };
+            let Some(table_sheet) = self.worksheet(&table.sheet) else {
+                continue;
+            };
+
+            let header_row = RangeRef::new(table.range.start, CellRef { row: table.range.start.row, ..table.range.end });
+            let mut read = vec![Precedent::Range { sheet: table.sheet.clone(), range: header_row }];
+            let this_row = Some(id.row).filter(|_| table.sheet == worksheet.name);
+            if let Ok(range) = table.resolve(table_sheet, reference, this_row) {
+                read.push(if range.start == range.end {
+                    Precedent::Cell(CellId::new(&table.sheet, range.start.row, range.start.col))
+                } else {
+                    Precedent::Range { sheet: table.sheet.clone(), range }
+                });
+            }
+            for precedent in read {
+                if !precedents.contains(&precedent) {
+                    precedents.push(precedent);
                 }
-                return Ok(left / right);
             }
         }
-        
-        Err("Unsupported arithmetic expression".to_string())
+        Ok((value, precedents))
     }
 
     fn cell_address(&self, col: i32, row: i32) -> String {
-        let col_letters = self.col_to_letters(col);
+        let col_letters = reference::column_to_letters(col);
         format!("{}{}", col_letters, row)
     }
 
-    fn col_to_letters(&self, col: i32) -> String {
-        let mut result = String::new();
-        let mut c = col;
-        
-        while c > 0 {
-            c -= 1;
-            result = char::from(b'A' + (c % 26) as u8).to_string() + &result;
-            c /= 26;
+    /// Address of a cell as written in a formula on `from_sheet`.
+    fn cell_display_address(&self, cell: &CellId, from_sheet: &str) -> String {
+        if cell.sheet == from_sheet {
+            self.cell_address(cell.col, cell.row)
+        } else {
+            cell.to_string()
         }
-        
-        result
     }
 
-    fn classify_formula(&self, formula: &str) -> String {
-        let formula = formula.trim();
-        
-        if formula.starts_with("SUM(") {
-            "aggregate".to_string()
-        } else if formula.starts_with("AVERAGE(") || formula.starts_with("MAX(") || formula.starts_with("MIN(") || formula.starts_with("COUNT(") {
-            "aggregate".to_string()
-        } else if formula.starts_with("IF(") {
-            "logical".to_string()
-        } else if formula.contains('+') || formula.contains('-') || formula.contains('*') || formula.contains('/') {
-            "arithmetic".to_string()
-        } else {
-            "literal".to_string()
+    /// Describe a formula by its outermost expression: the category of the
+    /// function it calls, `arithmetic` for an operator or `literal` for a
+    /// constant.
+    fn classify_formula(&self, formula: &Formula) -> String {
+        match formula.expr() {
+            Expr::Function { name, .. } => self
+                .functions
+                .get(name)
+                .map_or("literal", |function| function.category.as_str())
+                .to_string(),
+            Expr::Unary { .. } | Expr::Binary { .. } => "arithmetic".to_string(),
+            _ => "literal".to_string(),
         }
     }
 
@@ -623,6 +1837,18 @@ impl IronCalcEngine {
     }
 }
 
+/// A range typed as an address without a sheet, e.g. `A1:C10`, made
+/// relative. Whole rows and columns are not accepted.
+fn parse_address_range(text: &str) -> Option<RangeRef> {
+    let range = match parser::parse(text) {
+        Ok(Expr::Reference { sheet: None, cell }) => RangeRef::new(cell, cell),
+        Ok(Expr::Range { sheet: None, range }) if !range.is_whole_columns() && !range.is_whole_rows() => range,
+        _ => return None,
+    };
+    let relative = |cell: CellRef| CellRef { row_absolute: false, col_absolute: false, ..cell };
+    Some(RangeRef::new(relative(range.start), relative(range.end)))
+}
+
 /// Initialize the IronCalc plugin WASM module
 #[wasm_bindgen]
 pub fn init_ironcalc_plugin() {
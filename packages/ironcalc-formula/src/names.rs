@@ -0,0 +1,177 @@
+//! Defined names
+//!
+//! A defined name stands for a formula that other formulas can use in its
+//! place: a cell (`=Sheet1!$B$1`), a range, a constant (`=0.2`) or any other
+//! formula, including a `LAMBDA` that formulas then call like a function.
+//! A name belongs to the workbook or to a single sheet; on its own sheet a
+//! sheet-scoped name hides a workbook name spelled the same. Names are
+//! case-insensitive.
+//!
+//! A name's formula is evaluated where the name is used, so unqualified
+//! references in a workbook-scoped name refer to the sheet of the formula
+//! using it.
+
+use crate::formula::Formula;
+use crate::lexer::{Lexer, TokenKind};
+use crate::parser::Expr;
+use crate::reference::CellRef;
+use crate::scope::same_name;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub struct DefinedName {
+    pub name: String,
+    /// The sheet the name is scoped to, or `None` for the workbook
+    pub sheet: Option<String>,
+    pub formula: Rc<Formula>,
+}
+
+/// A defined name as listed by `getNames`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct NameInfo {
+    pub name: String,
+    pub refers_to: String,
+    pub sheet: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Names {
+    // Keyed by the upper-cased name and the sheet of sheet-scoped names
+    names: HashMap<(String, Option<String>), DefinedName>,
+}
+
+impl Names {
+    /// The name defined in exactly this scope.
+    pub fn get(&self, name: &str, sheet: Option<&str>) -> Option<&DefinedName> {
+        self.names.get(&key(name, sheet))
+    }
+
+    /// The name a formula on `sheet` sees: the sheet's own name if there is
+    /// one, the workbook's otherwise.
+    pub fn resolve(&self, name: &str, sheet: &str) -> Option<&DefinedName> {
+        let name = name.to_uppercase();
+        self.names
+            .get(&(name.clone(), Some(sheet.to_string())))
+            .or_else(|| self.names.get(&(name, None)))
+    }
+
+    pub fn insert(&mut self, name: DefinedName) {
+        self.names.insert(key(&name.name, name.sheet.as_deref()), name);
+    }
+
+    pub fn remove(&mut self, name: &str, sheet: Option<&str>) -> Option<DefinedName> {
+        self.names.remove(&key(name, sheet))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DefinedName> {
+        self.names.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut DefinedName> {
+        self.names.values_mut()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+fn key(name: &str, sheet: Option<&str>) -> (String, Option<String>) {
+    (name.to_uppercase(), sheet.map(str::to_string))
+}
+
+/// Whether `name` can be defined, following Excel's rules: a letter,
+/// underscore or backslash, then letters, digits, underscores and periods,
+/// and nothing that reads as a cell reference or a boolean. Function names
+/// follow the same rules.
+pub fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '\\')
+        && name.chars().skip(1).all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        && name.len() <= 255
+        && CellRef::parse(name).is_none()
+        // R and C stand for the current row and column in R1C1 notation
+        && !["R", "C", "TRUE", "FALSE"].iter().any(|reserved| name.eq_ignore_ascii_case(reserved))
+}
+
+/// Every name an expression mentions, as a value or as a function it
+/// calls, which may be defined names.
+pub fn mentioned(expr: &Expr) -> Vec<&str> {
+    fn collect<'e>(expr: &'e Expr, found: &mut Vec<&'e str>) {
+        match expr {
+            Expr::Name(name) => found.push(name),
+            Expr::Unary { operand, .. } => collect(operand, found),
+            Expr::Binary { left, right, .. } => {
+                collect(left, found);
+                collect(right, found);
+            }
+            Expr::Function { name, args } => {
+                found.push(name);
+                args.iter().for_each(|arg| collect(arg, found));
+            }
+            Expr::Call { callee, args } => {
+                collect(callee, found);
+                args.iter().for_each(|arg| collect(arg, found));
+            }
+            _ => {}
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(expr, &mut found);
+    found
+}
+
+/// The text of a formula with every use of the name `from` renamed to `to`,
+/// or `None` when the formula does not use it. Everything else is kept as
+/// written.
+pub fn rename_in_formula(text: &str, from: &str, to: &str) -> Option<String> {
+    let offset = if text.starts_with('=') { 1 } else { 0 };
+    let tokens = Lexer::new(&text[offset..]).tokenize().ok()?;
+
+    let mut renamed = String::with_capacity(text.len());
+    let mut copied = 0;
+    for token in tokens {
+        if let TokenKind::Identifier(name) | TokenKind::Function(name) = &token.kind {
+            if same_name(name, from) {
+                renamed.push_str(&text[copied..offset + token.start]);
+                renamed.push_str(to);
+                copied = offset + token.end;
+            }
+        }
+    }
+
+    if copied == 0 {
+        return None;
+    }
+    renamed.push_str(&text[copied..]);
+    Some(renamed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_names() {
+        assert!(is_valid_name("TaxRate"));
+        assert!(is_valid_name("_total.2024"));
+        assert!(is_valid_name("\\path"));
+        assert!(!is_valid_name("A1"));
+        assert!(!is_valid_name("xfd1048576"));
+        assert!(!is_valid_name("r"));
+        assert!(!is_valid_name("True"));
+        assert!(!is_valid_name("1st"));
+        assert!(!is_valid_name("Tax Rate"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn test_rename_in_formula() {
+        assert_eq!(
+            rename_in_formula("=Rate * SUM(rate, \"Rate\") + RATE(2)", "Rate", "Vat").as_deref(),
+            Some("=Vat * SUM(Vat, \"Rate\") + Vat(2)")
+        );
+        assert_eq!(rename_in_formula("=Rates+Sheet1!A1", "Rate", "Vat"), None);
+    }
+}
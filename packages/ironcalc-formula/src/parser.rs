@@ -0,0 +1,685 @@
+//! Recursive-descent formula parser
+//!
+//! Turns the token stream produced by the lexer into a typed [`Expr`] tree.
+//! Binary operators are handled by precedence climbing: each operator has a
+//! left and right binding power, and a sub-expression keeps absorbing
+//! operators for as long as they bind at least as tightly as its minimum.
+//!
+//! Operator precedence follows Excel, from tightest to loosest:
+//!
+//! | Operator                   | Description                 |
+//! |----------------------------|-----------------------------|
+//! | `@` (prefix)               | implicit intersection       |
+//! | `-` `+` (prefix)           | negation, unary plus        |
+//! | `%` (postfix)              | percent                     |
+//! | `^`                        | exponentiation              |
+//! | `*` `/`                    | multiplication and division |
+//! | `+` `-`                    | addition and subtraction    |
+//! | `&`                        | text concatenation          |
+//! | `=` `<>` `<` `>` `<=` `>=` | comparison                  |
+//!
+//! All binary operators are left-associative, including `^` (`2^3^2` is 64).
+//! Because negation binds tighter than `^`, `-2^2` evaluates to 4.
+
+use crate::error::ExcelError;
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::reference::{CellRef, RangeRef, StructuredRef};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    Error(ExcelError),
+    /// Cell reference, optionally qualified with a sheet name
+    Reference {
+        sheet: Option<String>,
+        cell: CellRef,
+    },
+    Range {
+        sheet: Option<String>,
+        range: RangeRef,
+    },
+    /// The spill range of the formula in a cell, e.g. `B2#`
+    SpillRef {
+        sheet: Option<String>,
+        cell: CellRef,
+    },
+    /// 3D reference covering the same range on every sheet from
+    /// `first_sheet` to `last_sheet` in tab order
+    Range3D {
+        first_sheet: String,
+        last_sheet: String,
+        range: RangeRef,
+    },
+    /// Array constant, e.g. `{1,2,3;4,5,6}`, as rows of literals
+    Array(Vec<Vec<Expr>>),
+    /// Bare identifier that is not a cell reference, e.g. a defined name
+    Name(String),
+    /// Reference to part of a table, e.g. `Sales[Amount]`
+    StructuredRef(StructuredRef),
+    /// Omitted function argument, as in `IF(A1,,0)`
+    Missing,
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// Call of the function value an expression evaluates to, as in
+    /// `LAMBDA(x,x+1)(2)`
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Plus,
+    Negate,
+    Percent,
+    /// `@`, which reduces a range or array to a single value
+    ImplicitIntersection,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Concat,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl BinaryOp {
+    fn from_token(kind: &TokenKind) -> Option<Self> {
+        let op = match kind {
+            TokenKind::Plus => BinaryOp::Add,
+            TokenKind::Minus => BinaryOp::Subtract,
+            TokenKind::Star => BinaryOp::Multiply,
+            TokenKind::Slash => BinaryOp::Divide,
+            TokenKind::Caret => BinaryOp::Power,
+            TokenKind::Ampersand => BinaryOp::Concat,
+            TokenKind::Equal => BinaryOp::Equal,
+            TokenKind::NotEqual => BinaryOp::NotEqual,
+            TokenKind::Less => BinaryOp::Less,
+            TokenKind::LessEqual => BinaryOp::LessEqual,
+            TokenKind::Greater => BinaryOp::Greater,
+            TokenKind::GreaterEqual => BinaryOp::GreaterEqual,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Left and right binding powers; all operators are left-associative.
+    fn binding_power(self) -> (u8, u8) {
+        match self {
+            BinaryOp::Equal
+            | BinaryOp::NotEqual
+            | BinaryOp::Less
+            | BinaryOp::LessEqual
+            | BinaryOp::Greater
+            | BinaryOp::GreaterEqual => (1, 2),
+            BinaryOp::Concat => (3, 4),
+            BinaryOp::Add | BinaryOp::Subtract => (5, 6),
+            BinaryOp::Multiply | BinaryOp::Divide => (7, 8),
+            BinaryOp::Power => (9, 10),
+        }
+    }
+}
+
+/// Parse a formula body (without the leading `=`) into an expression tree.
+pub fn parse(formula: &str) -> Result<Expr, String> {
+    let tokens = Lexer::new(formula).tokenize()?;
+    if tokens.is_empty() {
+        return Err("Formula is empty".to_string());
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.parse_expression(0)?;
+
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(format!("Unexpected '{}' at position {}", token.kind, token.start));
+    }
+
+    Ok(expr)
+}
+
+/// One side of a `start:end` range: a cell, a whole column or a whole row.
+#[derive(Clone, Copy)]
+enum RangeBound {
+    Cell(CellRef),
+    Column((i32, bool)),
+    Row((i32, bool)),
+}
+
+impl RangeBound {
+    fn from_token(kind: &TokenKind) -> Option<RangeBound> {
+        match kind {
+            TokenKind::Reference(r) => CellRef::parse(r).map(RangeBound::Cell),
+            TokenKind::Identifier(text) => RangeRef::parse_column(text)
+                .map(RangeBound::Column)
+                .or_else(|| RangeRef::parse_row(text).map(RangeBound::Row)),
+            TokenKind::Number(n) if n.fract() == 0.0 => {
+                RangeRef::parse_row(&(*n as i64).to_string()).map(RangeBound::Row)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "Unexpected end of formula".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: TokenKind) -> Result<(), String> {
+        let token = self.next()?;
+        if token.kind == expected {
+            Ok(())
+        } else {
+            Err(format!(
+                "Expected '{}' but found '{}' at position {}",
+                expected, token.kind, token.start
+            ))
+        }
+    }
+
+    fn parse_expression(&mut self, min_bp: u8) -> Result<Expr, String> {
+        let mut left = self.parse_operand()?;
+
+        while let Some(op) = self.peek().and_then(BinaryOp::from_token) {
+            let (left_bp, right_bp) = op.binding_power();
+            if left_bp < min_bp {
+                break;
+            }
+
+            self.pos += 1;
+            let right = self.parse_expression(right_bp)?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    /// A primary expression with its prefix and postfix operators. Unary
+    /// operators bind tighter than every binary operator, and prefix signs
+    /// tighter than a postfix `%`.
+    fn parse_operand(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_prefix()?;
+
+        while self.peek() == Some(&TokenKind::Percent) {
+            self.pos += 1;
+            expr = Expr::Unary {
+                op: UnaryOp::Percent,
+                operand: Box::new(expr),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expr, String> {
+        let op = match self.peek() {
+            Some(TokenKind::Minus) => UnaryOp::Negate,
+            Some(TokenKind::Plus) => UnaryOp::Plus,
+            Some(TokenKind::At) => UnaryOp::ImplicitIntersection,
+            _ => return self.parse_primary(),
+        };
+
+        self.pos += 1;
+        let operand = self.parse_prefix()?;
+        Ok(Expr::Unary {
+            op,
+            operand: Box::new(operand),
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = self.next()?;
+
+        if self.peek() == Some(&TokenKind::Colon) {
+            if let Some(start) = RangeBound::from_token(&token.kind) {
+                return self.parse_range(None, start, token.start);
+            }
+        }
+
+        match token.kind {
+            TokenKind::Number(n) => Ok(Expr::Number(n)),
+            TokenKind::Text(s) => Ok(Expr::Text(s)),
+            TokenKind::Boolean(b) => Ok(Expr::Boolean(b)),
+            TokenKind::Error(e) => Ok(Expr::Error(e)),
+            TokenKind::Reference(r) => CellRef::parse(&r)
+                .map(|cell| Expr::Reference { sheet: None, cell })
+                .ok_or_else(|| format!("Invalid reference '{}' at position {}", r, token.start)),
+            TokenKind::SpillReference(r) => CellRef::parse(&r)
+                .map(|cell| Expr::SpillRef { sheet: None, cell })
+                .ok_or_else(|| format!("Invalid reference '{}' at position {}", r, token.start)),
+            TokenKind::SheetPrefix(sheet) => self.parse_sheet_reference(sheet, token.start),
+            TokenKind::SheetRange(first_sheet, last_sheet) => {
+                let range = match self.parse_sheet_reference(first_sheet.clone(), token.start)? {
+                    Expr::Reference { cell, .. } => RangeRef::new(cell, cell),
+                    Expr::Range { range, .. } => range,
+                    _ => unreachable!("sheet references are cells or ranges"),
+                };
+                Ok(Expr::Range3D {
+                    first_sheet,
+                    last_sheet,
+                    range,
+                })
+            }
+            TokenKind::Identifier(name) => Ok(Expr::Name(name)),
+            TokenKind::StructuredRef { table, spec } => StructuredRef::parse(table, &spec)
+                .map(Expr::StructuredRef)
+                .ok_or_else(|| format!("Invalid structured reference '{}' at position {}", spec, token.start)),
+            TokenKind::Function(name) => {
+                let args = self.parse_arguments()?;
+                let mut expr = Expr::Function { name, args };
+                // A call that returns a lambda can itself be called
+                while self.peek() == Some(&TokenKind::LeftParen) {
+                    let args = self.parse_arguments()?;
+                    expr = Expr::Call {
+                        callee: Box::new(expr),
+                        args,
+                    };
+                }
+                Ok(expr)
+            }
+            TokenKind::LeftParen => {
+                let expr = self.parse_expression(0)?;
+                self.expect(TokenKind::RightParen)?;
+                Ok(expr)
+            }
+            TokenKind::LeftBrace => self.parse_array(token.start),
+            other => Err(format!("Unexpected '{}' at position {}", other, token.start)),
+        }
+    }
+
+    /// Parse the cell or range following a `Sheet!` prefix.
+    fn parse_sheet_reference(&mut self, sheet: String, position: usize) -> Result<Expr, String> {
+        let token = self.next()?;
+        if let TokenKind::SpillReference(r) = &token.kind {
+            if let Some(cell) = CellRef::parse(r) {
+                return Ok(Expr::SpillRef { sheet: Some(sheet), cell });
+            }
+        }
+        let bound = RangeBound::from_token(&token.kind);
+
+        match bound {
+            Some(start) if self.peek() == Some(&TokenKind::Colon) => {
+                self.parse_range(Some(sheet), start, position)
+            }
+            Some(RangeBound::Cell(cell)) => Ok(Expr::Reference {
+                sheet: Some(sheet),
+                cell,
+            }),
+            _ => Err(format!("Invalid reference after '{}!' at position {}", sheet, position)),
+        }
+    }
+
+    /// Parse the `:end` part of a range whose first bound has been read.
+    fn parse_range(&mut self, sheet: Option<String>, start: RangeBound, position: usize) -> Result<Expr, String> {
+        self.expect(TokenKind::Colon)?;
+        let token = self.next()?;
+
+        let range = match (start, RangeBound::from_token(&token.kind)) {
+            (RangeBound::Cell(first), Some(RangeBound::Cell(last))) => RangeRef::new(first, last),
+            (RangeBound::Column(first), Some(RangeBound::Column(last))) => RangeRef::columns(first, last),
+            (RangeBound::Row(first), Some(RangeBound::Row(last))) => RangeRef::rows(first, last),
+            _ => return Err(format!("Invalid range at position {}", position)),
+        };
+
+        Ok(Expr::Range { sheet, range })
+    }
+
+    /// Parse the rest of an array constant after its `{`. Elements are
+    /// literals, numbers optionally signed, with `,` between the values of
+    /// a row and `;` between rows, which must all be as long.
+    fn parse_array(&mut self, position: usize) -> Result<Expr, String> {
+        let (mut rows, mut row) = (Vec::new(), Vec::new());
+
+        loop {
+            let token = self.next()?;
+            let element = match token.kind {
+                TokenKind::Minus | TokenKind::Plus => {
+                    let sign = if token.kind == TokenKind::Minus { -1.0 } else { 1.0 };
+                    match self.next()?.kind {
+                        TokenKind::Number(n) => Expr::Number(sign * n),
+                        _ => return Err(format!("Invalid array constant at position {}", position)),
+                    }
+                }
+                TokenKind::Number(n) => Expr::Number(n),
+                TokenKind::Text(s) => Expr::Text(s),
+                TokenKind::Boolean(b) => Expr::Boolean(b),
+                TokenKind::Error(e) => Expr::Error(e),
+                _ => return Err(format!("Invalid array constant at position {}", position)),
+            };
+            row.push(element);
+
+            match self.next()?.kind {
+                TokenKind::Comma => {}
+                TokenKind::Semicolon => rows.push(std::mem::take(&mut row)),
+                TokenKind::RightBrace => {
+                    rows.push(row);
+                    break;
+                }
+                _ => return Err(format!("Invalid array constant at position {}", position)),
+            }
+        }
+
+        if rows.iter().any(|other| other.len() != rows[0].len()) {
+            return Err(format!("Array constant rows differ in length at position {}", position));
+        }
+        Ok(Expr::Array(rows))
+    }
+
+    /// Parse a parenthesised, comma-separated argument list. Empty slots
+    /// between commas become [`Expr::Missing`].
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, String> {
+        self.expect(TokenKind::LeftParen)?;
+
+        let mut args = Vec::new();
+        if self.peek() == Some(&TokenKind::RightParen) {
+            self.pos += 1;
+            return Ok(args);
+        }
+
+        loop {
+            match self.peek() {
+                Some(TokenKind::Comma) | Some(TokenKind::RightParen) => args.push(Expr::Missing),
+                _ => args.push(self.parse_expression(0)?),
+            }
+
+            let token = self.next()?;
+            match token.kind {
+                TokenKind::Comma => continue,
+                TokenKind::RightParen => break,
+                other => {
+                    return Err(format!(
+                        "Expected ',' or ')' but found '{}' at position {}",
+                        other, token.start
+                    ))
+                }
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(n: f64) -> Box<Expr> {
+        Box::new(Expr::Number(n))
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            parse("1+2*3").unwrap(),
+            Expr::Binary {
+                op: BinaryOp::Add,
+                left: num(1.0),
+                right: Box::new(Expr::Binary {
+                    op: BinaryOp::Multiply,
+                    left: num(2.0),
+                    right: num(3.0),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        assert_eq!(
+            parse("8-4-2").unwrap(),
+            Expr::Binary {
+                op: BinaryOp::Subtract,
+                left: Box::new(Expr::Binary {
+                    op: BinaryOp::Subtract,
+                    left: num(8.0),
+                    right: num(4.0),
+                }),
+                right: num(2.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_negation_binds_tighter_than_power() {
+        assert_eq!(
+            parse("-2^2").unwrap(),
+            Expr::Binary {
+                op: BinaryOp::Power,
+                left: Box::new(Expr::Unary {
+                    op: UnaryOp::Negate,
+                    operand: num(2.0),
+                }),
+                right: num(2.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_percent_binds_tighter_than_power() {
+        assert_eq!(
+            parse("2^50%").unwrap(),
+            Expr::Binary {
+                op: BinaryOp::Power,
+                left: num(2.0),
+                right: Box::new(Expr::Unary {
+                    op: UnaryOp::Percent,
+                    operand: num(50.0),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn test_concat_is_looser_than_arithmetic() {
+        assert_eq!(
+            parse("1+2&3").unwrap(),
+            Expr::Binary {
+                op: BinaryOp::Concat,
+                left: Box::new(Expr::Binary {
+                    op: BinaryOp::Add,
+                    left: num(1.0),
+                    right: num(2.0),
+                }),
+                right: num(3.0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nested_functions_and_missing_arguments() {
+        assert_eq!(
+            parse("IF(SUM(1,2)>2,,MAX(3))").unwrap(),
+            Expr::Function {
+                name: "IF".to_string(),
+                args: vec![
+                    Expr::Binary {
+                        op: BinaryOp::Greater,
+                        left: Box::new(Expr::Function {
+                            name: "SUM".to_string(),
+                            args: vec![Expr::Number(1.0), Expr::Number(2.0)],
+                        }),
+                        right: num(2.0),
+                    },
+                    Expr::Missing,
+                    Expr::Function {
+                        name: "MAX".to_string(),
+                        args: vec![Expr::Number(3.0)],
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let range = |text: &str| match parse(text).unwrap() {
+            Expr::Range { range, .. } => range.to_string(),
+            other => panic!("expected a range, got {:?}", other),
+        };
+
+        assert_eq!(range("B10:A1"), "A1:B10");
+        assert_eq!(range("$A:C"), "$A:C");
+        assert_eq!(range("3:$1"), "$1:3");
+        assert!(parse("A1:B").is_err());
+        assert!(parse("A:1").is_err());
+    }
+
+    #[test]
+    fn test_sheet_qualified_references() {
+        assert_eq!(
+            parse("'Q1 Sales'!B2:B20").unwrap(),
+            Expr::Range {
+                sheet: Some("Q1 Sales".to_string()),
+                range: RangeRef::new(CellRef::parse("B2").unwrap(), CellRef::parse("B20").unwrap()),
+            }
+        );
+        assert_eq!(
+            parse("Sheet2!$A$1").unwrap(),
+            Expr::Reference {
+                sheet: Some("Sheet2".to_string()),
+                cell: CellRef::parse("$A$1").unwrap(),
+            }
+        );
+        assert_eq!(
+            parse("Jan:Dec!B5").unwrap(),
+            Expr::Range3D {
+                first_sheet: "Jan".to_string(),
+                last_sheet: "Dec".to_string(),
+                range: RangeRef::new(CellRef::parse("B5").unwrap(), CellRef::parse("B5").unwrap()),
+            }
+        );
+        assert!(parse("Sheet2!").is_err());
+        assert!(parse("Sheet2!Total").is_err());
+    }
+
+    #[test]
+    fn test_structured_references() {
+        assert_eq!(
+            parse("SUM(Sales[Amount])").unwrap(),
+            Expr::Function {
+                name: "SUM".to_string(),
+                args: vec![Expr::StructuredRef(StructuredRef {
+                    table: Some("Sales".to_string()),
+                    rows: crate::reference::TableRows::Data,
+                    columns: Some(("Amount".to_string(), "Amount".to_string())),
+                })],
+            }
+        );
+        assert!(parse("[@Amount]*2").is_ok());
+        assert!(parse("Sales[#Everything]").is_err());
+    }
+
+    #[test]
+    fn test_spill_references_and_implicit_intersection() {
+        assert_eq!(
+            parse("@Sheet2!B2#").unwrap(),
+            Expr::Unary {
+                op: UnaryOp::ImplicitIntersection,
+                operand: Box::new(Expr::SpillRef {
+                    sheet: Some("Sheet2".to_string()),
+                    cell: CellRef::parse("B2").unwrap(),
+                }),
+            }
+        );
+        assert_eq!(
+            parse("-@A1:A3").unwrap(),
+            Expr::Unary {
+                op: UnaryOp::Negate,
+                operand: Box::new(Expr::Unary {
+                    op: UnaryOp::ImplicitIntersection,
+                    operand: Box::new(Expr::Range {
+                        sheet: None,
+                        range: RangeRef::new(CellRef::parse("A1").unwrap(), CellRef::parse("A3").unwrap()),
+                    }),
+                }),
+            }
+        );
+        assert!(parse("A1#:A3").is_err());
+        assert!(parse("@").is_err());
+    }
+
+    #[test]
+    fn test_array_constants() {
+        assert_eq!(
+            parse("{1,-2.5;\"a\",#N/A}").unwrap(),
+            Expr::Array(vec![
+                vec![Expr::Number(1.0), Expr::Number(-2.5)],
+                vec![Expr::Text("a".to_string()), Expr::Error(ExcelError::NA)],
+            ])
+        );
+        assert!(parse("SUM({1;2;3})").is_ok());
+        assert!(parse("{1,2;3}").is_err());
+        assert!(parse("{A1,2}").is_err());
+        assert!(parse("{1+2}").is_err());
+        assert!(parse("{}").is_err());
+        assert!(parse("{1,2").is_err());
+    }
+
+    #[test]
+    fn test_calls_of_function_values() {
+        let lambda = Expr::Function {
+            name: "LAMBDA".to_string(),
+            args: vec![Expr::Name("x".to_string()), Expr::Name("x".to_string())],
+        };
+        assert_eq!(
+            parse("LAMBDA(x,x)(1)(2,)").unwrap(),
+            Expr::Call {
+                callee: Box::new(Expr::Call {
+                    callee: Box::new(lambda),
+                    args: vec![Expr::Number(1.0)],
+                }),
+                args: vec![Expr::Number(2.0), Expr::Missing],
+            }
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert!(parse("(1+2").is_err());
+        assert!(parse("1+").is_err());
+        assert!(parse("SUM(1 2)").is_err());
+        assert!(parse("1)").is_err());
+    }
+}
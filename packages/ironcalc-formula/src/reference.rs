@@ -0,0 +1,484 @@
+//! A1-style cell and range references
+//!
+//! Rows and columns are 1-based, matching the coordinates used by
+//! `Worksheet` and the `#[wasm_bindgen]` API.
+
+use std::fmt;
+
+pub const MAX_ROW: i32 = 1048576;
+pub const MAX_COL: i32 = 16384;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CellRef {
+    pub row: i32,
+    pub col: i32,
+    pub row_absolute: bool,
+    pub col_absolute: bool,
+}
+
+impl CellRef {
+    /// Parse a reference such as `B2`, `$B2` or `$B$2`. Returns `None` when
+    /// the text is not a reference or falls outside Excel's grid.
+    pub fn parse(text: &str) -> Option<CellRef> {
+        let (col_absolute, body) = match text.strip_prefix('$') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+
+        let letters_len = body.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+        if letters_len == 0 || letters_len > 3 {
+            return None;
+        }
+
+        let (letters, rest) = body.split_at(letters_len);
+        let (row_absolute, digits) = match rest.strip_prefix('$') {
+            Some(digits) => (true, digits),
+            None => (false, rest),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let col = letters_to_column(letters)?;
+        let row = digits.parse::<i32>().ok()?;
+        if !(1..=MAX_ROW).contains(&row) {
+            return None;
+        }
+
+        Some(CellRef {
+            row,
+            col,
+            row_absolute,
+            col_absolute,
+        })
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}",
+            if self.col_absolute { "$" } else { "" },
+            column_to_letters(self.col),
+            if self.row_absolute { "$" } else { "" },
+            self.row
+        )
+    }
+}
+
+/// A rectangular block of cells. Whole-column (`A:C`) and whole-row (`1:3`)
+/// references are stored as ranges spanning the full grid in the other
+/// dimension. `start` is always the top-left corner and `end` the
+/// bottom-right one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RangeRef {
+    pub start: CellRef,
+    pub end: CellRef,
+}
+
+impl RangeRef {
+    /// Build a range from two corners given in any order.
+    pub fn new(first: CellRef, second: CellRef) -> RangeRef {
+        let (top, bottom) = if first.row <= second.row { (first, second) } else { (second, first) };
+        let (left, right) = if first.col <= second.col { (first, second) } else { (second, first) };
+
+        RangeRef {
+            start: CellRef {
+                row: top.row,
+                col: left.col,
+                row_absolute: top.row_absolute,
+                col_absolute: left.col_absolute,
+            },
+            end: CellRef {
+                row: bottom.row,
+                col: right.col,
+                row_absolute: bottom.row_absolute,
+                col_absolute: right.col_absolute,
+            },
+        }
+    }
+
+    /// Parse one side of a whole-column range such as `A` or `$C`.
+    pub fn parse_column(text: &str) -> Option<(i32, bool)> {
+        match text.strip_prefix('$') {
+            Some(letters) => letters_to_column(letters).map(|col| (col, true)),
+            None => letters_to_column(text).map(|col| (col, false)),
+        }
+    }
+
+    /// Parse one side of a whole-row range such as `3` or `$3`.
+    pub fn parse_row(text: &str) -> Option<(i32, bool)> {
+        let (absolute, digits) = match text.strip_prefix('$') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let row = digits.parse::<i32>().ok()?;
+        if (1..=MAX_ROW).contains(&row) {
+            Some((row, absolute))
+        } else {
+            None
+        }
+    }
+
+    /// Whole-column range `first:last`.
+    pub fn columns(first: (i32, bool), last: (i32, bool)) -> RangeRef {
+        RangeRef::new(
+            CellRef { row: 1, col: first.0, row_absolute: true, col_absolute: first.1 },
+            CellRef { row: MAX_ROW, col: last.0, row_absolute: true, col_absolute: last.1 },
+        )
+    }
+
+    /// Whole-row range `first:last`.
+    pub fn rows(first: (i32, bool), last: (i32, bool)) -> RangeRef {
+        RangeRef::new(
+            CellRef { row: first.0, col: 1, row_absolute: first.1, col_absolute: true },
+            CellRef { row: last.0, col: MAX_COL, row_absolute: last.1, col_absolute: true },
+        )
+    }
+
+    pub fn is_whole_columns(&self) -> bool {
+        self.start.row == 1 && self.end.row == MAX_ROW
+    }
+
+    pub fn is_whole_rows(&self) -> bool {
+        self.start.col == 1 && self.end.col == MAX_COL
+    }
+
+    pub fn contains(&self, row: i32, col: i32) -> bool {
+        (self.start.row..=self.end.row).contains(&row) && (self.start.col..=self.end.col).contains(&col)
+    }
+
+    /// Whether the two ranges share a cell.
+    pub fn overlaps(&self, other: &RangeRef) -> bool {
+        self.start.row <= other.end.row
+            && other.start.row <= self.end.row
+            && self.start.col <= other.end.col
+            && other.start.col <= self.end.col
+    }
+}
+
+impl fmt::Display for RangeRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dollar = |absolute: bool| if absolute { "$" } else { "" };
+
+        if self.is_whole_columns() && !self.is_whole_rows() {
+            write!(
+                f,
+                "{}{}:{}{}",
+                dollar(self.start.col_absolute),
+                column_to_letters(self.start.col),
+                dollar(self.end.col_absolute),
+                column_to_letters(self.end.col)
+            )
+        } else if self.is_whole_rows() && !self.is_whole_columns() {
+            write!(
+                f,
+                "{}{}:{}{}",
+                dollar(self.start.row_absolute),
+                self.start.row,
+                dollar(self.end.row_absolute),
+                self.end.row
+            )
+        } else {
+            write!(f, "{}:{}", self.start, self.end)
+        }
+    }
+}
+
+/// The rows of a table a structured reference covers, chosen by its special
+/// items (`#Headers`, `#Totals`, `@`, ...). Without one it is the data rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableRows {
+    Data,
+    Headers,
+    Totals,
+    All,
+    HeadersAndData,
+    DataAndTotals,
+    /// The data row of the formula using the reference, written `@`
+    ThisRow,
+}
+
+/// A structured reference to part of a table, such as `Sales[Amount]`,
+/// `Sales[@Amount]` or `Sales[[#Headers],[Region]:[Amount]]`. Columns are
+/// kept by name and looked up in the table's header row when evaluated, so
+/// that the reference follows the table as it grows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructuredRef {
+    /// `None` for the unqualified form used inside a table, as in `[@Amount]`
+    pub table: Option<String>,
+    pub rows: TableRows,
+    /// First and last column, or `None` for every column
+    pub columns: Option<(String, String)>,
+}
+
+impl StructuredRef {
+    /// Parse the bracketed specifier following a table name, e.g. `[Amount]`
+    /// or `[[#Totals],[Amount]]`. In column names an apostrophe escapes the
+    /// next character, so `[Q1 '#]` names the column `Q1 #`.
+    pub fn parse(table: Option<String>, spec: &str) -> Option<StructuredRef> {
+        let inner = spec.strip_prefix('[')?.strip_suffix(']')?;
+
+        let (rows, columns) = if let Some(rest) = inner.strip_prefix('@') {
+            let columns = match rest.trim() {
+                "" => None,
+                rest if rest.starts_with('[') => Some(column_span(rest)?),
+                rest => Some(single_column(rest)?),
+            };
+            (TableRows::ThisRow, columns)
+        } else if inner.trim_start().starts_with('[') {
+            item_list(inner)?
+        } else if inner.starts_with('#') {
+            (special_item(inner)?, None)
+        } else if inner.is_empty() {
+            (TableRows::Data, None)
+        } else {
+            (TableRows::Data, Some(single_column(inner)?))
+        };
+
+        Some(StructuredRef { table, rows, columns })
+    }
+}
+
+/// A comma-separated list of bracketed items, as in
+/// `[#Headers],[#Data],[Region]:[Amount]`: special items choosing rows and
+/// at most one column or span of columns.
+fn item_list(text: &str) -> Option<(TableRows, Option<(String, String)>)> {
+    let mut specials = Vec::new();
+    let mut columns = None;
+
+    for item in split_top_level(text, ',') {
+        let item = item.trim();
+        if item.starts_with("[#") && split_top_level(item, ':').len() == 1 {
+            specials.push(special_item(item.strip_prefix('[')?.strip_suffix(']')?)?);
+        } else if columns.is_none() {
+            columns = Some(column_span(item)?);
+        } else {
+            return None;
+        }
+    }
+
+    let has = |rows: TableRows| specials.contains(&rows);
+    let rows = match specials.len() {
+        0 => TableRows::Data,
+        1 => specials[0],
+        2 if has(TableRows::Headers) && has(TableRows::Data) => TableRows::HeadersAndData,
+        2 if has(TableRows::Data) && has(TableRows::Totals) => TableRows::DataAndTotals,
+        _ => return None,
+    };
+    Some((rows, columns))
+}
+
+fn special_item(text: &str) -> Option<TableRows> {
+    let rows = match text.to_ascii_uppercase().as_str() {
+        "#ALL" => TableRows::All,
+        "#DATA" => TableRows::Data,
+        "#HEADERS" => TableRows::Headers,
+        "#TOTALS" => TableRows::Totals,
+        "#THIS ROW" => TableRows::ThisRow,
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// `[Region]` or `[Region]:[Amount]`.
+fn column_span(text: &str) -> Option<(String, String)> {
+    let bracketed = |part: &str| {
+        let part = part.trim();
+        single_column(part.strip_prefix('[')?.strip_suffix(']')?).map(|(name, _)| name)
+    };
+
+    match split_top_level(text, ':').as_slice() {
+        [only] => bracketed(only).map(|name| (name.clone(), name)),
+        [first, last] => Some((bracketed(first)?, bracketed(last)?)),
+        _ => None,
+    }
+}
+
+/// A column name with its escapes removed, as a span of one column.
+fn single_column(text: &str) -> Option<(String, String)> {
+    let mut name = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => name.push(chars.next()?),
+            '[' | ']' => return None,
+            c => name.push(c),
+        }
+    }
+
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.clone(), name))
+}
+
+/// Split `text` at `separator` where it is outside brackets and not escaped.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut escaped, mut start) = (0, false, 0);
+
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\'' => escaped = true,
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Format a sheet name for use as a reference prefix, quoting it (and
+/// doubling embedded apostrophes) unless it is a plain identifier that
+/// cannot be mistaken for a cell reference.
+pub fn quote_sheet_name(name: &str) -> String {
+    let plain = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        && CellRef::parse(name).is_none()
+        && RangeRef::parse_column(name).is_none();
+
+    if plain {
+        name.to_string()
+    } else {
+        format!("'{}'", name.replace('\'', "''"))
+    }
+}
+
+/// Convert a 1-based column number to its letters (1 -> `A`, 27 -> `AA`).
+pub fn column_to_letters(col: i32) -> String {
+    let mut result = String::new();
+    let mut c = col;
+
+    while c > 0 {
+        c -= 1;
+        result.insert(0, char::from(b'A' + (c % 26) as u8));
+        c /= 26;
+    }
+
+    result
+}
+
+/// Convert column letters to a 1-based column number, case-insensitively.
+pub fn letters_to_column(letters: &str) -> Option<i32> {
+    if letters.is_empty() || letters.len() > 3 || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let col = letters
+        .chars()
+        .fold(0, |acc, c| acc * 26 + (c.to_ascii_uppercase() as i32 - 'A' as i32 + 1));
+
+    if (1..=MAX_COL).contains(&col) {
+        Some(col)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cell_references() {
+        assert_eq!(
+            CellRef::parse("$B2"),
+            Some(CellRef {
+                row: 2,
+                col: 2,
+                row_absolute: false,
+                col_absolute: true,
+            })
+        );
+        assert_eq!(CellRef::parse("xfd1048576").map(|r| (r.row, r.col)), Some((1048576, 16384)));
+        assert_eq!(CellRef::parse("XFE1"), None);
+        assert_eq!(CellRef::parse("A0"), None);
+        assert_eq!(CellRef::parse("A$"), None);
+    }
+
+    #[test]
+    fn test_ranges_are_normalised() {
+        let range = RangeRef::new(CellRef::parse("B10").unwrap(), CellRef::parse("A1").unwrap());
+        assert_eq!(range.to_string(), "A1:B10");
+        assert_eq!((range.start.row, range.start.col, range.end.row, range.end.col), (1, 1, 10, 2));
+        assert!(range.contains(10, 1));
+        assert!(!range.contains(11, 2));
+    }
+
+    #[test]
+    fn test_whole_column_and_row_ranges() {
+        let columns = RangeRef::columns(RangeRef::parse_column("$C").unwrap(), RangeRef::parse_column("a").unwrap());
+        assert_eq!(columns.to_string(), "A:$C");
+        assert_eq!((columns.end.row, columns.end.col), (MAX_ROW, 3));
+
+        let rows = RangeRef::rows(RangeRef::parse_row("2").unwrap(), RangeRef::parse_row("$2").unwrap());
+        assert_eq!(rows.to_string(), "2:$2");
+        assert_eq!((rows.end.row, rows.end.col), (2, MAX_COL));
+        assert_eq!(RangeRef::parse_row("0"), None);
+    }
+
+    #[test]
+    fn test_quote_sheet_name() {
+        assert_eq!(quote_sheet_name("Sheet2"), "Sheet2");
+        assert_eq!(quote_sheet_name("Q1 Sales"), "'Q1 Sales'");
+        assert_eq!(quote_sheet_name("O'Brien"), "'O''Brien'");
+        assert_eq!(quote_sheet_name("AB12"), "'AB12'");
+        assert_eq!(quote_sheet_name("2024"), "'2024'");
+    }
+
+    #[test]
+    fn test_column_letters_round_trip() {
+        for col in [1, 26, 27, 52, 702, 703, 16384] {
+            assert_eq!(letters_to_column(&column_to_letters(col)), Some(col));
+        }
+        assert_eq!(column_to_letters(28), "AB");
+        assert_eq!(CellRef::parse("$c$3").unwrap().to_string(), "$C$3");
+    }
+
+    #[test]
+    fn test_structured_references() {
+        let parse = |spec: &str| StructuredRef::parse(Some("Sales".to_string()), spec);
+        let columns = |first: &str, last: &str| Some((first.to_string(), last.to_string()));
+        let reference = |rows, columns| {
+            Some(StructuredRef {
+                table: Some("Sales".to_string()),
+                rows,
+                columns,
+            })
+        };
+
+        assert_eq!(parse("[]"), reference(TableRows::Data, None));
+        assert_eq!(parse("[Amount]"), reference(TableRows::Data, columns("Amount", "Amount")));
+        assert_eq!(parse("[@Amount]"), reference(TableRows::ThisRow, columns("Amount", "Amount")));
+        assert_eq!(parse("[@[Unit Price]]"), reference(TableRows::ThisRow, columns("Unit Price", "Unit Price")));
+        assert_eq!(parse("[#totals]"), reference(TableRows::Totals, None));
+        assert_eq!(
+            parse("[[#Headers],[Region]]"),
+            reference(TableRows::Headers, columns("Region", "Region"))
+        );
+        assert_eq!(
+            parse("[[#Data], [#Totals], [Region]:[Amount]]"),
+            reference(TableRows::DataAndTotals, columns("Region", "Amount"))
+        );
+        assert_eq!(
+            parse("[[#This Row],[Q1 '#]]"),
+            reference(TableRows::ThisRow, columns("Q1 #", "Q1 #"))
+        );
+        assert_eq!(parse("[#Everything]"), None);
+        assert_eq!(parse("[[A],[B]]"), None);
+        assert_eq!(parse("[[#Headers],[#Totals]]"), None);
+    }
+}
@@ -0,0 +1,88 @@
+//! Lexical scopes and lambda values
+//!
+//! `LET` and `LAMBDA` bind names to values for the expressions inside them.
+//! A [`Scope`] is an immutable linked list of those bindings: binding a name
+//! puts a new link in front of the enclosing scope, which is shared rather
+//! than copied. That makes it cheap for a [`Lambda`] to capture the scope it
+//! was created in and keep it alive after the `LET` that built it returned.
+
+use crate::parser::Expr;
+use crate::value::CellValue;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Default)]
+pub struct Scope(Option<Rc<Binding>>);
+
+struct Binding {
+    name: String,
+    value: CellValue,
+    parent: Scope,
+}
+
+impl Scope {
+    /// A scope binding `name` to `value` in front of this one, shadowing
+    /// any outer binding of the same name.
+    pub fn bind(&self, name: &str, value: CellValue) -> Scope {
+        Scope(Some(Rc::new(Binding {
+            name: name.to_string(),
+            value,
+            parent: self.clone(),
+        })))
+    }
+
+    /// The innermost value bound to `name`. Names are case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&CellValue> {
+        let mut scope = self;
+        while let Some(binding) = &scope.0 {
+            if same_name(&binding.name, name) {
+                return Some(&binding.value);
+            }
+            scope = &binding.parent;
+        }
+        None
+    }
+}
+
+/// Whether two names are the same, ignoring case.
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.chars().flat_map(char::to_uppercase).eq(b.chars().flat_map(char::to_uppercase))
+}
+
+/// A function value created by `LAMBDA`. It closes over the scope it was
+/// created in, so `LET(n, 2, LAMBDA(x, x*n))` keeps seeing `n`.
+pub struct Lambda {
+    pub params: Vec<String>,
+    pub body: Expr,
+    pub scope: Scope,
+}
+
+/// Lambdas are only equal to themselves; comparing bodies would say
+/// nothing about the values they capture.
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Lambda) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LAMBDA({})", self.params.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bindings_shadow_outer_scopes() {
+        let outer = Scope::default().bind("x", CellValue::Number(1.0)).bind("y", CellValue::Number(2.0));
+        let inner = outer.bind("X", CellValue::Number(3.0));
+
+        assert_eq!(inner.get("x"), Some(&CellValue::Number(3.0)));
+        assert_eq!(inner.get("Y"), Some(&CellValue::Number(2.0)));
+        assert_eq!(outer.get("x"), Some(&CellValue::Number(1.0)));
+        assert_eq!(outer.get("z"), None);
+    }
+}
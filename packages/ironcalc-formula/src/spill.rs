@@ -0,0 +1,145 @@
+//! Dynamic array spilling
+//!
+//! A formula whose result is an array spills it: the formula's cell, the
+//! anchor, shows the top-left element and the cells below and to the right
+//! show the rest. Spilled values are stored in those cells like any other
+//! value, marked with their anchor, so that formulas read them without
+//! knowing where they came from. Only the anchor holds a formula.
+//!
+//! A spill needs every cell of its range to be blank. When one is not, or
+//! the range would leave the grid or overlap a table, the anchor shows
+//! `#SPILL!` and nothing is spilled. The range is remembered either way,
+//! so that the anchor is recalculated when a cell in it is written or
+//! cleared. The whole spill range is referred to as `A1#`, and an implicit
+//! intersection `@A1:A10` picks the single cell of a range in line with
+//! the formula, as formulas did before arrays spilled.
+//!
+//! Legacy array formulas, entered over a fixed range as with Ctrl+Shift+Enter
+//! in older Excel, are stored the same way with the range given instead of
+//! taken from the result. They never block: the result is fitted to the
+//! range, and the cells of the range can only change together.
+
+use crate::error::ExcelError;
+use crate::reference::{CellRef, RangeRef, MAX_COL, MAX_ROW};
+use crate::value::CellValue;
+
+/// The range a formula's array result covers, by the formula's cell.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spill {
+    pub range: RangeRef,
+    /// Whether a cell in the way kept the array from spilling
+    pub blocked: bool,
+}
+
+/// The range an array anchored at `row`, `col` spills into, or `None` when
+/// it has a single element and stays in its cell. An empty array is
+/// `#CALC!` and one running off the grid `#SPILL!`.
+pub fn extent(row: i32, col: i32, rows: &[Vec<CellValue>]) -> Result<Option<RangeRef>, ExcelError> {
+    let (height, width) = (rows.len(), rows.first().map_or(0, Vec::len));
+    if height == 0 || width == 0 {
+        return Err(ExcelError::Calc);
+    }
+    if height == 1 && width == 1 {
+        return Ok(None);
+    }
+
+    let (last_row, last_col) = (row as i64 + height as i64 - 1, col as i64 + width as i64 - 1);
+    if last_row > MAX_ROW as i64 || last_col > MAX_COL as i64 {
+        return Err(ExcelError::Spill);
+    }
+    Ok(Some(RangeRef::new(cell(row, col), cell(last_row as i32, last_col as i32))))
+}
+
+/// The value a legacy array formula entered over `range` shows: its result
+/// stretched to the range as operators stretch arrays, `#N/A` where it
+/// falls short and cut where it is larger. A single element when the range
+/// is a single cell.
+pub fn fit(value: &CellValue, range: &RangeRef) -> CellValue {
+    let element = |row: i32, col: i32| {
+        value
+            .broadcast_at((row - range.start.row) as usize, (col - range.start.col) as usize)
+            .cloned()
+            .unwrap_or(CellValue::Error(ExcelError::NA))
+    };
+
+    if range.start == range.end {
+        return element(range.start.row, range.start.col);
+    }
+    CellValue::Array(
+        (range.start.row..=range.end.row)
+            .map(|row| (range.start.col..=range.end.col).map(|col| element(row, col)).collect())
+            .collect(),
+    )
+}
+
+/// The cell of `range` an implicit intersection at `row`, `col` picks: the
+/// range itself when it is one cell, the cell in the same row of a column
+/// or in the same column of a row, or the cell at that position of a
+/// block. `None`, which is `#VALUE!`, when the position is not in line.
+pub fn intersect(range: &RangeRef, row: i32, col: i32) -> Option<CellRef> {
+    let rows = range.start.row..=range.end.row;
+    let cols = range.start.col..=range.end.col;
+
+    let row = if range.start.row == range.end.row { range.start.row } else { rows.contains(&row).then_some(row)? };
+    let col = if range.start.col == range.end.col { range.start.col } else { cols.contains(&col).then_some(col)? };
+    Some(cell(row, col))
+}
+
+fn cell(row: i32, col: i32) -> CellRef {
+    CellRef {
+        row,
+        col,
+        row_absolute: false,
+        col_absolute: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(text: &str) -> RangeRef {
+        let (first, last) = text.split_once(':').unwrap();
+        RangeRef::new(CellRef::parse(first).unwrap(), CellRef::parse(last).unwrap())
+    }
+
+    #[test]
+    fn test_extent() {
+        let array = |height: usize, width: usize| vec![vec![CellValue::Number(1.0); width]; height];
+
+        assert_eq!(extent(2, 2, &array(3, 2)), Ok(Some(range("B2:C4"))));
+        assert_eq!(extent(2, 2, &array(1, 1)), Ok(None));
+        assert_eq!(extent(2, 2, &array(0, 0)), Err(ExcelError::Calc));
+        assert_eq!(extent(MAX_ROW, 1, &array(2, 1)), Err(ExcelError::Spill));
+    }
+
+    #[test]
+    fn test_fit() {
+        let n = CellValue::Number;
+        let row = CellValue::Array(vec![vec![n(1.0), n(2.0), n(3.0)]]);
+
+        assert_eq!(
+            fit(&row, &range("B2:C3")),
+            CellValue::Array(vec![vec![n(1.0), n(2.0)], vec![n(1.0), n(2.0)]])
+        );
+        assert_eq!(
+            fit(&row, &range("A1:A2")),
+            CellValue::Array(vec![vec![n(1.0)], vec![n(1.0)]])
+        );
+        assert_eq!(
+            fit(&CellValue::Array(vec![vec![n(1.0), n(2.0)], vec![n(3.0), n(4.0)]]), &range("A1:C1")),
+            CellValue::Array(vec![vec![n(1.0), n(2.0), CellValue::Error(ExcelError::NA)]])
+        );
+        assert_eq!(fit(&n(5.0), &range("A1:B1")), CellValue::Array(vec![vec![n(5.0), n(5.0)]]));
+        assert_eq!(fit(&row, &range("D4:D4")), n(1.0));
+    }
+
+    #[test]
+    fn test_intersect() {
+        assert_eq!(intersect(&range("B1:B10"), 4, 7), CellRef::parse("B4"));
+        assert_eq!(intersect(&range("A3:F3"), 9, 2), CellRef::parse("B3"));
+        assert_eq!(intersect(&range("B2:C3"), 3, 3), CellRef::parse("C3"));
+        assert_eq!(intersect(&range("B1:B10"), 11, 2), None);
+        assert_eq!(intersect(&range("B2:C3"), 3, 4), None);
+    }
+}
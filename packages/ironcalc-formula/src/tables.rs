@@ -0,0 +1,250 @@
+//! Tables
+//!
+//! A table (Excel's ListObject) is a named block of a worksheet with a
+//! header row, data rows and optionally a totals row. Formulas refer to it
+//! through structured references such as `Sales[Amount]`, which name
+//! columns by their header text rather than by address. Column names are
+//! read from the header cells whenever a reference is evaluated, so editing
+//! a header renames its column.
+//!
+//! Table names share the workbook namespace of defined names and are
+//! case-insensitive, as are column names.
+
+use crate::error::ExcelError;
+use crate::parser::Expr;
+use crate::reference::{CellRef, RangeRef, StructuredRef, TableRows};
+use crate::scope::same_name;
+use crate::Worksheet;
+use serde::Serialize;
+
+pub struct Table {
+    pub name: String,
+    pub sheet: String,
+    /// The whole table, from the header row to the last data row or the
+    /// totals row
+    pub range: RangeRef,
+    pub totals_row: bool,
+}
+
+/// A table as listed by `getTables`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TableInfo {
+    pub name: String,
+    pub sheet: String,
+    pub range: String,
+    pub columns: Vec<String>,
+    pub totals_row: bool,
+}
+
+impl Table {
+    /// The first and last data rows. A table always has at least one.
+    pub fn data_rows(&self) -> (i32, i32) {
+        let last = if self.totals_row { self.range.end.row - 1 } else { self.range.end.row };
+        (self.range.start.row + 1, last)
+    }
+
+    /// The header text of each column, left to right.
+    pub fn column_names(&self, worksheet: &Worksheet) -> Vec<String> {
+        (self.range.start.col..=self.range.end.col)
+            .map(|col| header(worksheet, self.range.start.row, col))
+            .collect()
+    }
+
+    /// The sheet column holding the table column `name`.
+    fn column(&self, worksheet: &Worksheet, name: &str) -> Option<i32> {
+        (self.range.start.col..=self.range.end.col)
+            .find(|col| same_name(&header(worksheet, self.range.start.row, *col), name))
+    }
+
+    /// The cells a structured reference to this table covers. `this_row` is
+    /// the row of the formula using it, for `@` references. An unknown
+    /// column or a missing totals row is `#REF!`, and `@` outside the data
+    /// rows is `#VALUE!`.
+    pub fn resolve(&self, worksheet: &Worksheet, reference: &StructuredRef, this_row: Option<i32>) -> Result<RangeRef, ExcelError> {
+        let (first_col, last_col) = match &reference.columns {
+            None => (self.range.start.col, self.range.end.col),
+            Some((first, last)) => (
+                self.column(worksheet, first).ok_or(ExcelError::Ref)?,
+                self.column(worksheet, last).ok_or(ExcelError::Ref)?,
+            ),
+        };
+
+        let (header, end) = (self.range.start.row, self.range.end.row);
+        let (first_data, last_data) = self.data_rows();
+        let (first_row, last_row) = match reference.rows {
+            TableRows::Data => (first_data, last_data),
+            TableRows::Headers => (header, header),
+            TableRows::Totals if self.totals_row => (end, end),
+            TableRows::Totals => return Err(ExcelError::Ref),
+            TableRows::All => (header, end),
+            TableRows::HeadersAndData => (header, last_data),
+            TableRows::DataAndTotals => (first_data, end),
+            TableRows::ThisRow => match this_row {
+                Some(row) if (first_data..=last_data).contains(&row) => (row, row),
+                _ => return Err(ExcelError::Value),
+            },
+        };
+
+        let corner = |row, col| CellRef {
+            row,
+            col,
+            row_absolute: false,
+            col_absolute: false,
+        };
+        Ok(RangeRef::new(corner(first_row, first_col), corner(last_row, last_col)))
+    }
+}
+
+fn header(worksheet: &Worksheet, row: i32, col: i32) -> String {
+    worksheet
+        .get_cell(row, col)
+        .map(|cell| cell.value.to_display_string())
+        .unwrap_or_default()
+}
+
+#[derive(Default)]
+pub struct Tables {
+    tables: Vec<Table>,
+}
+
+impl Tables {
+    pub fn get(&self, name: &str) -> Option<&Table> {
+        self.tables.iter().find(|table| same_name(&table.name, name))
+    }
+
+    /// The table a structured reference in a formula at `position` on
+    /// `sheet` refers to: the one it names, or for an unqualified reference
+    /// the one the formula is in.
+    pub fn find(&self, reference: &StructuredRef, sheet: &str, position: Option<(i32, i32)>) -> Option<&Table> {
+        match &reference.table {
+            Some(name) => self.get(name),
+            None => {
+                let (row, col) = position?;
+                self.tables
+                    .iter()
+                    .find(|table| table.sheet == sheet && table.range.contains(row, col))
+            }
+        }
+    }
+
+    /// Whether `range` on `sheet` overlaps a table.
+    pub fn overlaps(&self, sheet: &str, range: &RangeRef) -> bool {
+        self.tables.iter().any(|table| table.sheet == sheet && table.range.overlaps(range))
+    }
+
+    pub fn insert(&mut self, table: Table) {
+        self.tables.push(table);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Table> {
+        let index = self.tables.iter().position(|table| same_name(&table.name, name))?;
+        Some(self.tables.remove(index))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Table> {
+        self.tables.iter()
+    }
+
+    /// Grow the tables that a value written at `row`, `col` on `sheet`
+    /// extends: those without a totals row whose last row is just above it,
+    /// within their columns. Returns the names of the tables grown.
+    pub fn expand(&mut self, sheet: &str, row: i32, col: i32) -> Vec<String> {
+        let growing: Vec<usize> = self
+            .tables
+            .iter()
+            .enumerate()
+            .filter(|(_, table)| {
+                table.sheet == sheet
+                    && !table.totals_row
+                    && table.range.end.row + 1 == row
+                    && (table.range.start.col..=table.range.end.col).contains(&col)
+            })
+            // Tables never grow into each other
+            .filter(|(_, table)| {
+                let below = RangeRef::new(
+                    CellRef { row, ..table.range.start },
+                    CellRef { row, ..table.range.end },
+                );
+                !self.overlaps(sheet, &below)
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        growing
+            .into_iter()
+            .map(|index| {
+                let table = &mut self.tables[index];
+                table.range.end.row += 1;
+                table.name.clone()
+            })
+            .collect()
+    }
+}
+
+/// Every structured reference in an expression.
+pub fn structured_refs(expr: &Expr) -> Vec<&StructuredRef> {
+    fn collect<'e>(expr: &'e Expr, found: &mut Vec<&'e StructuredRef>) {
+        match expr {
+            Expr::StructuredRef(reference) => found.push(reference),
+            Expr::Unary { operand, .. } => collect(operand, found),
+            Expr::Binary { left, right, .. } => {
+                collect(left, found);
+                collect(right, found);
+            }
+            Expr::Function { args, .. } => args.iter().for_each(|arg| collect(arg, found)),
+            Expr::Call { callee, args } => {
+                collect(callee, found);
+                args.iter().for_each(|arg| collect(arg, found));
+            }
+            _ => {}
+        }
+    }
+
+    let mut found = Vec::new();
+    collect(expr, &mut found);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(totals_row: bool) -> Table {
+        Table {
+            name: "Sales".to_string(),
+            sheet: "Sheet1".to_string(),
+            range: RangeRef::new(CellRef::parse("B2").unwrap(), CellRef::parse("C5").unwrap()),
+            totals_row,
+        }
+    }
+
+    #[test]
+    fn test_tables_grow_below_their_last_row() {
+        let mut tables = Tables::default();
+        tables.insert(table(false));
+
+        assert!(tables.expand("Sheet1", 7, 2).is_empty());
+        assert!(tables.expand("Sheet1", 6, 4).is_empty());
+        assert!(tables.expand("Sheet2", 6, 2).is_empty());
+        assert_eq!(tables.expand("Sheet1", 6, 3), vec!["Sales".to_string()]);
+        assert_eq!(tables.get("SALES").unwrap().range.to_string(), "B2:C6");
+
+        let mut totalled = Tables::default();
+        totalled.insert(table(true));
+        assert!(totalled.expand("Sheet1", 6, 2).is_empty());
+        assert_eq!(totalled.get("Sales").unwrap().data_rows(), (3, 4));
+    }
+
+    #[test]
+    fn test_unqualified_references_find_the_enclosing_table() {
+        let mut tables = Tables::default();
+        tables.insert(table(false));
+        let unqualified = StructuredRef::parse(None, "[@Amount]").unwrap();
+
+        assert!(tables.find(&unqualified, "Sheet1", Some((4, 3))).is_some());
+        assert!(tables.find(&unqualified, "Sheet1", Some((4, 4))).is_none());
+        assert!(tables.find(&unqualified, "Sheet2", Some((4, 3))).is_none());
+        assert!(tables.find(&unqualified, "Sheet1", None).is_none());
+        assert!(tables.overlaps("Sheet1", &RangeRef::new(CellRef::parse("C5").unwrap(), CellRef::parse("D9").unwrap())));
+    }
+}
@@ -0,0 +1,1080 @@
+//! Native unit tests for the engine, exercising the internal API that the
+//! `#[wasm_bindgen]` exports wrap.
+
+use super::*;
+
+fn evaluate(engine: &mut IronCalcEngine, formula: &str) -> FormulaResult {
+    evaluate_on(engine, "Sheet1", formula)
+}
+
+fn evaluate_on(engine: &mut IronCalcEngine, sheet: &str, formula: &str) -> FormulaResult {
+    engine.evaluate(formula, sheet, 1, 1).unwrap()
+}
+
+#[test]
+fn test_simple_arithmetic() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    assert_eq!(evaluate(&mut engine, "=1+2+3").value, CellValue::Number(6.0));
+    assert_eq!(evaluate(&mut engine, "=2*3-4/2").value, CellValue::Number(4.0));
+}
+
+#[test]
+fn test_complex_formulas() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    let result = evaluate(&mut engine, "=IF(SUM(1,2,3)>5,MAX(10,20,30),MIN(1,2,3))");
+    assert_eq!(result.value, CellValue::Number(30.0));
+    assert_eq!(result.error, None);
+
+    let result = evaluate(&mut engine, "=((10+5)*2-3)/7");
+    assert_eq!(result.value, CellValue::Number(27.0 / 7.0));
+}
+
+#[test]
+fn test_errors_are_reported_in_result() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    let result = evaluate(&mut engine, "=1/0");
+    assert_eq!(result.value, CellValue::Error(ExcelError::Div0));
+    assert_eq!(result.error, Some(ExcelError::Div0));
+    assert_eq!(evaluate(&mut engine, "=UNKNOWN_FUNC()").error, Some(ExcelError::Name));
+}
+
+#[test]
+fn test_engine_failures_are_not_excel_errors() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    assert!(engine.evaluate("=SUM(1,", "Sheet1", 1, 1).is_err());
+    assert!(engine.evaluate("", "Sheet1", 1, 1).is_err());
+    assert!(engine.evaluate("=1", "Missing", 1, 1).is_err());
+
+    // A failed evaluation leaves the cell untouched
+    engine.set_cell_value("Sheet1", 2, 1, CellValue::Number(5.0)).unwrap();
+    assert!(engine.evaluate("=1+", "Sheet1", 2, 1).is_err());
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "5");
+}
+
+#[test]
+fn test_cell_values_outside_the_sheet_are_rejected() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    // Rows and columns past i32::MAX wrap to negative coordinates
+    for (row, col) in [(0, 1), (1048577, 1), (1, 16385), (u32::MAX as i32, 1), (1, (1u32 << 31) as i32)] {
+        assert!(engine.set_cell_value("Sheet1", row, col, CellValue::Number(1.0)).is_err(), "{} {}", row, col);
+    }
+    assert!(engine.worksheets[0].cells.is_empty());
+    engine.set_cell_value("Sheet1", 1048576, 16384, CellValue::Number(1.0)).unwrap();
+}
+
+#[test]
+fn test_string_literals() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    assert_eq!(evaluate(&mut engine, "=\"say \"\"hi\"\"\"").value, CellValue::Text("say \"hi\"".to_string()));
+    assert_eq!(evaluate(&mut engine, "=IF(5>3,\"YES\",\"NO\")").value, CellValue::Text("YES".to_string()));
+}
+
+#[test]
+fn test_cell_references() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value("Sheet1", 1, 1, CellValue::Number(6.0)).unwrap();
+    engine.set_cell_value("Sheet1", 2, 2, CellValue::Number(7.0)).unwrap();
+
+    let result = engine.evaluate("=A1*B2", "Sheet1", 3, 3).unwrap();
+    assert_eq!(result.value, CellValue::Number(42.0));
+
+    // Empty cells read as zero, and a bare reference to one evaluates to 0
+    assert_eq!(engine.evaluate("=Z100+1", "Sheet1", 4, 3).unwrap().value, CellValue::Number(1.0));
+    assert_eq!(engine.evaluate("=Z100", "Sheet1", 5, 3).unwrap().value, CellValue::Number(0.0));
+}
+
+#[test]
+fn test_reference_sees_formula_errors() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.evaluate("=1/0", "Sheet1", 1, 1).unwrap();
+    let result = engine.evaluate("=A1+1", "Sheet1", 1, 2).unwrap();
+    assert_eq!(result.value, CellValue::Error(ExcelError::Div0));
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "#DIV/0!");
+}
+
+#[test]
+fn test_typed_cell_values() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "0.1").unwrap();
+    engine.set_cell_value_js("Sheet1", 2, 1, "'123").unwrap();
+    engine.set_cell_value_js("Sheet1", 3, 1, "TRUE").unwrap();
+
+    assert_eq!(engine.evaluate("=A1+0.2", "Sheet1", 1, 2).unwrap().value, CellValue::Number(0.1 + 0.2));
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "0.3");
+    assert_eq!(engine.evaluate("=A2", "Sheet1", 2, 2).unwrap().value, CellValue::Text("123".to_string()));
+    assert_eq!(engine.evaluate("=A2*2", "Sheet1", 3, 2).unwrap().value, CellValue::Number(246.0));
+    assert_eq!(engine.evaluate("=A3", "Sheet1", 4, 2).unwrap().value, CellValue::Boolean(true));
+    assert_eq!(engine.evaluate("=A3&\"\"", "Sheet1", 5, 2).unwrap().value, CellValue::Text("TRUE".to_string()));
+    assert_eq!(engine.get_cell_value("Sheet1", 9, 9).unwrap(), "");
+}
+
+#[test]
+fn test_range_references() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=10 {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64)).unwrap();
+    }
+    engine.set_cell_value("Sheet1", 1048576, 1, CellValue::Number(45.0)).unwrap();
+
+    assert_eq!(engine.evaluate("=SUM(A1:A10)", "Sheet1", 1, 2).unwrap().value, CellValue::Number(55.0));
+    assert_eq!(engine.evaluate("=SUM(A:A)", "Sheet1", 2, 2).unwrap().value, CellValue::Number(100.0));
+    assert_eq!(engine.evaluate("=COUNT(1:1)", "Sheet1", 3, 3).unwrap().value, CellValue::Number(2.0));
+}
+
+#[test]
+fn test_edits_recalculate_dependents() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+    engine.evaluate("=A1*10", "Sheet1", 1, 2).unwrap();
+    engine.evaluate("=B1+A1", "Sheet1", 1, 3).unwrap();
+    engine.evaluate("=SUM(A1:C1)", "Sheet1", 2, 1).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "44");
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "3").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "30");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "33");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "66");
+
+    // Replacing a formula with a new one re-points its dependents' inputs
+    engine.evaluate("=A1-1", "Sheet1", 1, 2).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "5");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "10");
+
+    // Overwriting a formula with a constant drops its precedents
+    engine.set_cell_value_js("Sheet1", 1, 2, "100").unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "0").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "100");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "100");
+}
+
+#[test]
+fn test_recalculation_across_sheets() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.create_sheet("Jan").unwrap();
+    engine.create_sheet("Mar").unwrap();
+    engine.evaluate("=SUM(Jan:Mar!A1)+Feb!A1", "Sheet1", 1, 1).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "#REF!");
+
+    // Creating the missing sheet resolves the reference, and the new sheet
+    // is part of the 3D span once it sits between its endpoints
+    engine.insert_sheet("Feb", 2).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "0");
+    engine.set_cell_value_js("Feb", 1, 1, "5").unwrap();
+    engine.set_cell_value_js("Mar", 1, 1, "7").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "17");
+}
+
+#[test]
+fn test_sheet_names_ignore_case() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.evaluate("=Sheet1!B1*2", "sheet1", 1, 1).unwrap();
+    engine.set_cell_value_js("SHEET1", 1, 2, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "8");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "4");
+    assert!(engine.evaluate("=1", "Sheet2", 1, 1).is_err());
+}
+
+#[test]
+fn test_long_dependency_chain() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "0").unwrap();
+    for row in 2..=5000 {
+        engine.evaluate(&format!("=A{}+1", row - 1), "Sheet1", row, 1).unwrap();
+    }
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "1").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 5000, 1).unwrap(), "5000");
+}
+
+#[test]
+fn test_circular_references_are_reported() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.evaluate("=B1+1", "Sheet1", 1, 1).unwrap();
+    engine.evaluate("=C1*2", "Sheet1", 1, 2).unwrap();
+    engine.evaluate("=A1*10", "Sheet1", 2, 1).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "10");
+
+    let result = engine.evaluate("=A1", "Sheet1", 1, 3).unwrap();
+    assert_eq!(result.value, CellValue::Error(ExcelError::Circular));
+    assert_eq!(result.error, Some(ExcelError::Circular));
+    assert_eq!(
+        result.circular_reference,
+        Some(vec!["C1".to_string(), "A1".to_string(), "B1".to_string(), "C1".to_string()])
+    );
+    for col in 1..=3 {
+        assert_eq!(engine.get_cell_value("Sheet1", 1, col).unwrap(), "#CIRC!");
+    }
+    // Cells outside the circle that read it see the error
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "#CIRC!");
+
+    // Breaking the circle recalculates every cell that was in it
+    engine.set_cell_value_js("Sheet1", 1, 3, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "8");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "9");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "90");
+}
+
+#[test]
+fn test_self_and_cross_sheet_circular_references() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.create_sheet("Q1 Sales").unwrap();
+
+    let result = engine.evaluate("=A1+1", "Sheet1", 1, 1).unwrap();
+    assert_eq!(result.circular_reference, Some(vec!["A1".to_string(), "A1".to_string()]));
+
+    engine.evaluate("=Sheet1!B2", "Q1 Sales", 1, 1).unwrap();
+    let result = engine.evaluate("=SUM('Q1 Sales'!A1:A3)", "Sheet1", 2, 2).unwrap();
+    assert_eq!(
+        result.circular_reference,
+        Some(vec!["B2".to_string(), "'Q1 Sales'!A1".to_string(), "B2".to_string()])
+    );
+
+    let result = engine.evaluate("=1", "Sheet1", 1, 1).unwrap();
+    assert_eq!(result.circular_reference, None);
+    assert_eq!(result.value, CellValue::Number(1.0));
+}
+
+#[test]
+fn test_iterative_calculation_converges() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.configure_iteration(IterationSettings { enabled: true, max_iterations: 100, max_change: 0.000001 }).unwrap();
+
+    // Interest on the average of the opening and closing balances
+    engine.set_cell_value_js("Sheet1", 1, 1, "1000").unwrap();
+    engine.evaluate("=0.1*(A1+A3)/2", "Sheet1", 2, 1).unwrap();
+    let result = engine.evaluate("=A1+A2", "Sheet1", 3, 1).unwrap();
+
+    let status = result.iteration.unwrap();
+    assert!(status.converged);
+    assert!(status.iterations > 1 && status.max_change <= 0.000001);
+    assert_eq!(result.circular_reference, None);
+    let CellValue::Number(closing) = result.value else { panic!("expected a number") };
+    assert!((closing - (1000.0 + 100.0 / 0.95)).abs() < 0.00001);
+
+    // Editing an input iterates the circle again
+    engine.set_cell_value_js("Sheet1", 1, 1, "2000").unwrap();
+    let closing: f64 = engine.get_cell_value("Sheet1", 3, 1).unwrap().parse().unwrap();
+    assert!((closing - (2000.0 + 200.0 / 0.95)).abs() < 0.00001);
+    assert!(engine.last_iteration.as_ref().unwrap().converged);
+}
+
+#[test]
+fn test_iterative_calculation_stops_at_the_limit() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.configure_iteration(IterationSettings { enabled: true, max_iterations: 10, max_change: 0.001 }).unwrap();
+
+    let result = engine.evaluate("=A1+1", "Sheet1", 1, 1).unwrap();
+    assert_eq!(result.value, CellValue::Number(10.0));
+    assert_eq!(result.iteration, Some(IterationStatus { iterations: 10, converged: false, max_change: 1.0 }));
+
+    assert!(engine.configure_iteration(IterationSettings { enabled: true, max_iterations: 0, max_change: 0.001 }).is_err());
+    assert!(engine.configure_iteration(IterationSettings { enabled: true, max_iterations: 5, max_change: -1.0 }).is_err());
+}
+
+fn is_dirty(engine: &IronCalcEngine, sheet: &str, row: i32, col: i32) -> bool {
+    engine.worksheet(sheet).unwrap().get_cell(row, col).unwrap().dirty
+}
+
+#[test]
+fn test_manual_calculation_mode() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_calculation_mode("manual").unwrap();
+    assert_eq!(engine.get_calculation_mode(), "manual");
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "1").unwrap();
+    // A formula is calculated when entered, even in manual mode
+    assert_eq!(engine.evaluate("=A1*2", "Sheet1", 1, 2).unwrap().value, CellValue::Number(2.0));
+    engine.evaluate("=B1+1", "Sheet1", 1, 3).unwrap();
+
+    // Edits only mark the dependents dirty, transitively
+    engine.set_cell_value_js("Sheet1", 1, 1, "5").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "3");
+    assert!(is_dirty(&engine, "Sheet1", 1, 2));
+    assert!(is_dirty(&engine, "Sheet1", 1, 3));
+
+    engine.recalculate_js();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "10");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "11");
+    assert!(!is_dirty(&engine, "Sheet1", 1, 3));
+
+    // Switching back to automatic brings dirty cells up to date
+    engine.set_cell_value_js("Sheet1", 1, 1, "7").unwrap();
+    engine.set_calculation_mode("automatic-except-tables").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "15");
+    engine.set_cell_value_js("Sheet1", 1, 1, "8").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "17");
+}
+
+#[test]
+fn test_recalculate_sheet_and_all() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.create_sheet("Summary").unwrap();
+    engine.set_calculation_mode("manual").unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "1").unwrap();
+    engine.evaluate("=A1+1", "Sheet1", 2, 1).unwrap();
+    engine.evaluate("=Sheet1!A2*10", "Summary", 1, 1).unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+
+    // Shift+F9 on Sheet1 leaves the dirty cell on Summary alone
+    engine.recalculate_worksheet("Sheet1").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "3");
+    assert_eq!(engine.get_cell_value("Summary", 1, 1).unwrap(), "20");
+    assert!(is_dirty(&engine, "Summary", 1, 1));
+
+    // Sheet names ignore case, as in Excel
+    engine.recalculate_worksheet("SUMMARY").unwrap();
+    assert_eq!(engine.get_cell_value("summary", 1, 1).unwrap(), "30");
+    assert!(engine.recalculate_worksheet("Missing").is_err());
+
+    // Ctrl+Alt+F9 recalculates formulas that are not dirty too
+    engine.worksheet_mut("Sheet1").unwrap().cells.get_mut(&(2, 1)).unwrap().value = CellValue::Number(0.0);
+    engine.recalculate_all();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "3");
+}
+
+#[test]
+fn test_cached_results_follow_precedents() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "5").unwrap();
+    engine.evaluate("=A1*2", "Sheet1", 2, 1).unwrap();
+    engine.evaluate("=A2+1", "Sheet1", 3, 1).unwrap();
+    assert_eq!(engine.evaluate("=A2+1", "Sheet1", 3, 1).unwrap().value, CellValue::Number(11.0));
+    assert_eq!(engine.performance_metrics.cache_hit_rate, 1.0);
+
+    // A change two steps up the chain evicts the cached result
+    engine.set_cell_value_js("Sheet1", 1, 1, "7").unwrap();
+    assert_eq!(engine.evaluate("=A2+1", "Sheet1", 3, 1).unwrap().value, CellValue::Number(15.0));
+
+    // So does overwriting the formula's own cell
+    engine.set_cell_value_js("Sheet1", 3, 1, "1").unwrap();
+    engine.evaluate("=A2+1", "Sheet1", 3, 1).unwrap();
+    assert_eq!(engine.performance_metrics.cache_hit_rate, 1.0);
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 1).unwrap(), "15");
+}
+
+#[test]
+fn test_cache_evicts_least_recently_used() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cache_size(2);
+
+    engine.evaluate("=1", "Sheet1", 1, 1).unwrap();
+    engine.evaluate("=2", "Sheet1", 2, 1).unwrap();
+    engine.evaluate("=1", "Sheet1", 1, 1).unwrap();
+    engine.evaluate("=3", "Sheet1", 3, 1).unwrap();
+    assert_eq!(engine.formula_cache.len(), 2);
+    assert_eq!(engine.performance_metrics.cache_hit_rate, 1.0);
+
+    // A1 was used more recently than A2, so it is still cached
+    engine.evaluate("=1", "Sheet1", 1, 1).unwrap();
+    assert_eq!(engine.performance_metrics.cache_hit_rate, 2.0);
+    engine.evaluate("=2", "Sheet1", 2, 1).unwrap();
+    assert_eq!(engine.performance_metrics.cache_hit_rate, 2.0);
+
+    engine.set_cache_size(0);
+    assert_eq!(engine.formula_cache.len(), 0);
+}
+
+#[test]
+fn test_formulas_are_compiled_once() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.evaluate("=A1+1", "Sheet1", 2, 1).unwrap();
+    engine.evaluate("=A1+1", "Sheet1", 2, 2).unwrap();
+
+    let formula = |row, col| Rc::clone(engine.worksheet("Sheet1").unwrap().get_cell(row, col).unwrap().formula.as_ref().unwrap());
+    assert!(Rc::ptr_eq(&formula(2, 1), &formula(2, 2)));
+    assert_eq!(formula(2, 1).text(), "=A1+1");
+
+    // Recalculation evaluates the stored compiled formula
+    engine.set_cell_value_js("Sheet1", 1, 1, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 2).unwrap(), "5");
+}
+
+#[test]
+fn test_formula_type_comes_from_function_category() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    assert_eq!(evaluate(&mut engine, "=SUM(1,2)").formula_type, "aggregate");
+    assert_eq!(evaluate(&mut engine, "=iferror(1,2)").formula_type, "logical");
+    assert_eq!(evaluate(&mut engine, "=ISNA(1)").formula_type, "information");
+    assert_eq!(evaluate(&mut engine, "=SUM(1,2)*2").formula_type, "arithmetic");
+    assert_eq!(evaluate(&mut engine, "=\"text\"").formula_type, "literal");
+    assert_eq!(evaluate(&mut engine, "=NOPE(1)").formula_type, "error");
+}
+
+#[test]
+fn test_volatile_cells_recalculate() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    let rand = |engine: &IronCalcEngine| engine.get_cell_value("Sheet1", 1, 1).unwrap();
+
+    let first = evaluate(&mut engine, "=RAND()").value;
+    assert_ne!(evaluate(&mut engine, "=RAND()").value, first, "volatile results are not cached");
+
+    // Any edit recalculates volatile cells, even unrelated ones
+    let before = rand(&engine);
+    engine.set_cell_value_js("Sheet1", 5, 5, "1").unwrap();
+    assert_ne!(rand(&engine), before);
+
+    // In manual mode they wait for F9
+    engine.set_calculation_mode("manual").unwrap();
+    let before = rand(&engine);
+    engine.set_cell_value_js("Sheet1", 5, 5, "2").unwrap();
+    assert_eq!(rand(&engine), before);
+    engine.recalculate_js();
+    assert_ne!(rand(&engine), before);
+
+    // Overwriting the formula makes the cell ordinary again
+    engine.set_cell_value_js("Sheet1", 1, 1, "7").unwrap();
+    assert!(engine.volatile_cells.is_empty());
+}
+
+#[test]
+fn test_registering_custom_functions() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "4").unwrap();
+    assert_eq!(engine.evaluate("=DOUBLE(A1)+1", "Sheet1", 2, 1).unwrap().error, Some(ExcelError::Name));
+
+    let double = |function: fn(f64) -> f64| {
+        Function::new("Double", Category::Custom, (1, Some(1)), &[functions::ArgKind::Value], move |_, args| {
+            match args.as_slice() {
+                [functions::Arg::Value(value)] => Ok(CellValue::Number(function(value.to_number()?))),
+                _ => Err(ExcelError::Value),
+            }
+        })
+    };
+
+    // Formulas that were waiting for the function pick it up
+    engine.register_function(double(|n| n * 2.0)).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "9");
+    assert_eq!(engine.evaluate("=double(A1)", "Sheet1", 3, 1).unwrap().formula_type, "custom");
+
+    // Replacing it recalculates them again
+    engine.register_function(double(|n| n * 3.0)).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "13");
+    assert_eq!(engine.evaluate("=DOUBLE(A1,2)", "Sheet1", 4, 1).unwrap().error, Some(ExcelError::Value));
+
+    let builtin = Function::new("sum", Category::Custom, (0, None), &[], |_, _| Ok(CellValue::Empty));
+    assert!(engine.register_function(builtin).is_err());
+    let invalid = Function::new("A1", Category::Custom, (0, None), &[], |_, _| Ok(CellValue::Empty));
+    assert!(engine.register_function(invalid).is_err());
+    let invalid = Function::new("FOO BAR", Category::Custom, (0, None), &[], |_, _| Ok(CellValue::Empty));
+    assert!(engine.register_function(invalid).is_err());
+}
+
+#[test]
+fn test_let_and_lambda_formulas() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "3").unwrap();
+
+    let result = engine.evaluate("=LET(x,A1,sq,LAMBDA(n,n*n),sq(x)+1)", "Sheet1", 1, 2).unwrap();
+    assert_eq!(result.value, CellValue::Number(10.0));
+    assert_eq!(result.formula_type, "logical");
+    engine.set_cell_value_js("Sheet1", 1, 1, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "17");
+
+    // A lambda that is never called is not a cell value
+    let result = engine.evaluate("=LAMBDA(x,x+1)", "Sheet1", 2, 2).unwrap();
+    assert_eq!(result.error, Some(ExcelError::Calc));
+
+    // Blank cells of the range are passed to the lambda too
+    let result = engine.evaluate("=MAP(A1:A4,LAMBDA(x,x+1))", "Sheet1", 1, 3).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("C1:C4"));
+    assert_eq!(engine.get_cell_value("Sheet1", 4, 3).unwrap(), "1");
+}
+
+#[test]
+fn test_defined_names() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    let value = |engine: &IronCalcEngine, row, col| engine.get_cell_value("Sheet1", row, col).unwrap();
+    for (row, amount) in [(1, "10"), (2, "20"), (3, "30")] {
+        engine.set_cell_value_js("Sheet1", row, 3, amount).unwrap();
+    }
+    engine.set_cell_value_js("Sheet1", 1, 2, "0.2").unwrap();
+
+    engine.define_name("TaxRate", "=Sheet1!$B$1", None).unwrap();
+    engine.define_name("Sales", "=Sheet1!$C$1:$C$3", None).unwrap();
+    engine.define_name("Net", "=SUM(Sales)*(1-taxrate)", None).unwrap();
+    engine.define_name("Double", "=LAMBDA(x,x*2)", None).unwrap();
+
+    engine.evaluate("=SUM(Sales)*TaxRate", "Sheet1", 1, 1).unwrap();
+    engine.evaluate("=Double(Net)", "Sheet1", 2, 1).unwrap();
+    engine.evaluate("=COUNT(Sales)", "Sheet1", 3, 1).unwrap();
+    assert_eq!(value(&engine, 1, 1), "12");
+    assert_eq!(value(&engine, 2, 1), "96");
+    assert_eq!(value(&engine, 3, 1), "3");
+
+    // Names take part in dependency tracking, also through other names
+    engine.set_cell_value_js("Sheet1", 1, 2, "0.5").unwrap();
+    assert_eq!(value(&engine, 1, 1), "30");
+    assert_eq!(value(&engine, 2, 1), "60");
+
+    engine.update_name("sales", "=Sheet1!$C$1:$C$2", None).unwrap();
+    assert_eq!(value(&engine, 2, 1), "30");
+    assert_eq!(value(&engine, 3, 1), "2");
+
+    engine.delete_name("Double", None).unwrap();
+    assert_eq!(value(&engine, 2, 1), "#NAME?");
+    engine.define_name("Double", "=LAMBDA(x,x+x)", None).unwrap();
+    assert_eq!(value(&engine, 2, 1), "30");
+
+    // Named lambdas can call themselves
+    engine.define_name("Fact", "=LAMBDA(n,IF(n<2,1,n*FACT(n-1)))", None).unwrap();
+    assert_eq!(engine.evaluate("=Fact(5)", "Sheet1", 4, 1).unwrap().value, CellValue::Number(120.0));
+    assert_eq!(engine.evaluate("=Fact(1000)", "Sheet1", 4, 1).unwrap().error, Some(ExcelError::Num));
+}
+
+#[test]
+fn test_sheet_scoped_names() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.create_sheet("Sheet2").unwrap();
+
+    engine.define_name("Rate", "=0.1", None).unwrap();
+    engine.define_name("Rate", "=0.2", Some("sheet2")).unwrap();
+    engine.define_name("Here", "=B1", None).unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 2, "1").unwrap();
+    engine.set_cell_value_js("Sheet2", 1, 2, "2").unwrap();
+
+    assert_eq!(evaluate_on(&mut engine, "Sheet1", "=Rate+Here").value, CellValue::Number(1.1));
+    assert_eq!(evaluate_on(&mut engine, "Sheet2", "=Rate+Here").value, CellValue::Number(2.2));
+
+    assert_eq!(
+        engine.list_names(),
+        vec![
+            NameInfo { name: "Here".to_string(), refers_to: "=B1".to_string(), sheet: None },
+            NameInfo { name: "Rate".to_string(), refers_to: "=0.1".to_string(), sheet: None },
+            NameInfo { name: "Rate".to_string(), refers_to: "=0.2".to_string(), sheet: Some("Sheet2".to_string()) },
+        ]
+    );
+
+    assert!(engine.define_name("RATE", "=1", None).is_err());
+    assert!(engine.define_name("B2", "=1", None).is_err());
+    assert!(engine.define_name("Tax", "=1+", None).is_err());
+    assert!(engine.define_name("Tax", "=1", Some("Missing")).is_err());
+    assert!(engine.update_name("Tax", "=1", None).is_err());
+    assert!(engine.delete_name("Rate", Some("Sheet1")).is_err());
+}
+
+#[test]
+fn test_renaming_names_rewrites_formulas() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.create_sheet("Sheet2").unwrap();
+    let formula = |engine: &IronCalcEngine, sheet: &str| {
+        engine.worksheet(sheet).unwrap().get_cell(1, 1).unwrap().formula.as_ref().unwrap().text().to_string()
+    };
+
+    engine.define_name("Rate", "=0.1", None).unwrap();
+    engine.define_name("Rate", "=0.2", Some("Sheet2")).unwrap();
+    engine.define_name("Total", "=100*rate", None).unwrap();
+    evaluate_on(&mut engine, "Sheet1", "=Total + RATE");
+    evaluate_on(&mut engine, "Sheet2", "=Rate");
+    engine.evaluate("=Vat", "Sheet1", 2, 1).unwrap();
+
+    engine.rename_name("Rate", "Vat", None).unwrap();
+    assert_eq!(formula(&engine, "Sheet1"), "=Total + Vat");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "10.1");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "0.1");
+    assert_eq!(engine.names.get("Total", None).unwrap().formula.text(), "=100*Vat");
+
+    // Sheet2 sees its own Rate, which keeps its name
+    assert_eq!(formula(&engine, "Sheet2"), "=Rate");
+    assert_eq!(engine.get_cell_value("Sheet2", 1, 1).unwrap(), "0.2");
+
+    assert!(engine.rename_name("Vat", "Total", None).is_err());
+    assert!(engine.rename_name("Vat", "A1", None).is_err());
+    assert!(engine.rename_name("Rate", "Vat", None).is_err());
+}
+
+/// A `Sales` table over A1:C4 of Sheet1 with columns Region, Amount and
+/// Units, and a totals row when `totals_row` is set.
+fn engine_with_table(totals_row: bool) -> IronCalcEngine {
+    let mut engine = IronCalcEngine::new().unwrap();
+    let rows = [["Region", "Amount", "Units"], ["North", "10", "1"], ["South", "20", "2"], ["East", "30", "3"]];
+    for (row, values) in rows.iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            engine.set_cell_value_js("Sheet1", row as u32 + 1, col as u32 + 1, value).unwrap();
+        }
+    }
+    if totals_row {
+        engine.set_cell_value_js("Sheet1", 5, 1, "Total").unwrap();
+        engine.evaluate("=SUM(Sales[Amount])", "Sheet1", 5, 2).unwrap();
+    }
+
+    let range = if totals_row { "A1:C5" } else { "A1:C4" };
+    engine.create_table("Sales", "Sheet1", range, totals_row).unwrap();
+    engine
+}
+
+#[test]
+fn test_structured_references() {
+    let mut engine = engine_with_table(false);
+    let value = |engine: &IronCalcEngine, row, col| engine.get_cell_value("Sheet1", row, col).unwrap();
+
+    engine.evaluate("=SUM(Sales[Amount])", "Sheet1", 1, 5).unwrap();
+    engine.evaluate("=COUNT(Sales[])", "Sheet1", 2, 5).unwrap();
+    engine.evaluate("=Sales[[#Headers],[Units]]", "Sheet1", 3, 5).unwrap();
+    engine.evaluate("=SUM(sales[[amount]:[UNITS]])", "Sheet1", 4, 5).unwrap();
+    assert_eq!(value(&engine, 1, 5), "60");
+    assert_eq!(value(&engine, 2, 5), "6");
+    assert_eq!(value(&engine, 3, 5), "Units");
+    assert_eq!(value(&engine, 4, 5), "66");
+
+    // `@` reads the formula's own row
+    engine.evaluate("=Sales[@Amount]*Sales[@[Units]]", "Sheet1", 3, 4).unwrap();
+    engine.evaluate("=Sales[@Amount]", "Sheet1", 4, 4).unwrap();
+    assert_eq!(value(&engine, 3, 4), "40");
+    assert_eq!(value(&engine, 4, 4), "30");
+    assert_eq!(engine.evaluate("=Sales[@Amount]", "Sheet1", 8, 4).unwrap().error, Some(ExcelError::Value));
+    // Unqualified references only work inside a table
+    assert_eq!(engine.evaluate("=[@Amount]", "Sheet1", 2, 4).unwrap().error, Some(ExcelError::Ref));
+
+    // Edits to the table's cells and headers recalculate the references
+    engine.set_cell_value_js("Sheet1", 3, 2, "25").unwrap();
+    assert_eq!(value(&engine, 1, 5), "65");
+    assert_eq!(value(&engine, 3, 4), "50");
+    engine.set_cell_value_js("Sheet1", 1, 2, "Revenue").unwrap();
+    assert_eq!(value(&engine, 1, 5), "#REF!");
+
+    assert_eq!(engine.evaluate("=Sales[#Totals]", "Sheet1", 10, 1).unwrap().error, Some(ExcelError::Ref));
+    assert_eq!(engine.evaluate("=Missing[Amount]", "Sheet1", 10, 1).unwrap().error, Some(ExcelError::Ref));
+    assert!(engine.evaluate("=Sales[#Everything]", "Sheet1", 10, 1).is_err());
+}
+
+#[test]
+fn test_totals_rows() {
+    let mut engine = engine_with_table(true);
+
+    assert_eq!(engine.get_cell_value("Sheet1", 5, 2).unwrap(), "60");
+    assert_eq!(engine.evaluate("=Sales[[#Totals],[Amount]]*2", "Sheet1", 7, 1).unwrap().value, CellValue::Number(120.0));
+    assert_eq!(engine.evaluate("=COUNT(Sales[[#All],[Amount]])", "Sheet1", 8, 1).unwrap().value, CellValue::Number(4.0));
+
+    // A totals row stops the table from growing into the rows below
+    engine.set_cell_value_js("Sheet1", 6, 2, "40").unwrap();
+    assert_eq!(engine.list_tables()[0].range, "A1:C5");
+}
+
+#[test]
+fn test_tables_expand_when_rows_are_appended() {
+    let mut engine = engine_with_table(false);
+    engine.evaluate("=SUM(Sales[Amount])", "Sheet1", 1, 6).unwrap();
+
+    engine.set_cell_value_js("Sheet1", 5, 1, "West").unwrap();
+    engine.set_cell_value_js("Sheet1", 5, 2, "40").unwrap();
+    assert_eq!(engine.list_tables()[0].range, "A1:C5");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 6).unwrap(), "100");
+
+    // A formula typed into the next row joins the table and sees its row
+    engine.evaluate("=[@Amount]/2", "Sheet1", 6, 3).unwrap();
+    engine.set_cell_value_js("Sheet1", 6, 2, "50").unwrap();
+    assert_eq!(engine.list_tables()[0].range, "A1:C6");
+    assert_eq!(engine.get_cell_value("Sheet1", 6, 3).unwrap(), "25");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 6).unwrap(), "150");
+
+    // Outside the table's columns, or past the next row, nothing changes
+    engine.set_cell_value_js("Sheet1", 7, 4, "1").unwrap();
+    engine.set_cell_value_js("Sheet1", 9, 1, "1").unwrap();
+    assert_eq!(engine.list_tables()[0].range, "A1:C6");
+
+    // Entering a cached formula again below the table extends it too
+    engine.evaluate("=10", "Sheet1", 8, 2).unwrap();
+    engine.set_cell_value_js("Sheet1", 7, 1, "North").unwrap();
+    engine.evaluate("=10", "Sheet1", 8, 2).unwrap();
+    assert_eq!(engine.list_tables()[0].range, "A1:C8");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 6).unwrap(), "160");
+}
+
+#[test]
+fn test_creating_and_deleting_tables() {
+    let mut engine = engine_with_table(false);
+    engine.evaluate("=SUM(Orders[Qty])", "Sheet1", 10, 1).unwrap();
+    for (row, value) in [(11, "Qty"), (12, "5"), (13, "7")] {
+        engine.set_cell_value_js("Sheet1", row, 1, value).unwrap();
+    }
+    assert_eq!(engine.get_cell_value("Sheet1", 10, 1).unwrap(), "#REF!");
+
+    // Blank headers are named after their position
+    engine.create_table("Orders", "sheet1", "$A$11:$B$13", false).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 10, 1).unwrap(), "12");
+    assert_eq!(
+        engine.list_tables()[0],
+        TableInfo {
+            name: "Orders".to_string(),
+            sheet: "Sheet1".to_string(),
+            range: "A11:B13".to_string(),
+            columns: vec!["Qty".to_string(), "Column2".to_string()],
+            totals_row: false,
+        }
+    );
+
+    engine.delete_table("orders").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 10, 1).unwrap(), "#REF!");
+    assert!(engine.delete_table("Orders").is_err());
+
+    assert!(engine.create_table("Sales", "Sheet1", "E1:F3", false).is_err());
+    assert!(engine.create_table("Overlap", "Sheet1", "C4:D6", false).is_err());
+    assert!(engine.create_table("Small", "Sheet1", "E1:F1", false).is_err());
+    assert!(engine.create_table("Small", "Sheet1", "E1:F2", true).is_err());
+    assert!(engine.create_table("Whole", "Sheet1", "E:F", false).is_err());
+    assert!(engine.create_table("A1", "Sheet1", "E1:F3", false).is_err());
+    assert!(engine.define_name("SALES", "=1", None).is_err());
+    engine.define_name("Rate", "=1", Some("Sheet1")).unwrap();
+    assert!(engine.create_table("Rate", "Sheet1", "E1:F3", false).is_err());
+
+}
+
+#[test]
+fn test_arrays_spill_into_neighbouring_cells() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for (row, values) in [(1, ["1", "10"]), (2, ["2", "20"]), (3, ["3", ""])] {
+        engine.set_cell_value_js("Sheet1", row, 1, values[0]).unwrap();
+        engine.set_cell_value_js("Sheet1", row, 2, values[1]).unwrap();
+    }
+    engine.evaluate("=D2*100", "Sheet1", 1, 7).unwrap();
+
+    let result = engine.evaluate("=A1:B3", "Sheet1", 1, 4).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("D1:E3"));
+    assert!(matches!(&result.value, CellValue::Array(rows) if rows.len() == 3));
+    let shown: Vec<String> = (1..=3)
+        .flat_map(|row| (4..=5).map(move |col| (row, col)))
+        .map(|(row, col)| engine.get_cell_value("Sheet1", row, col).unwrap())
+        .collect();
+    assert_eq!(shown, ["1", "10", "2", "20", "3", "0"]);
+
+    // Formulas reading spilled cells follow the array
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 7).unwrap(), "200");
+    engine.set_cell_value_js("Sheet1", 2, 1, "5").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 4).unwrap(), "5");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 7).unwrap(), "500");
+
+    // A single value stays in its cell
+    assert_eq!(engine.evaluate("=A1:A1", "Sheet1", 1, 9).unwrap().spill_range, None);
+
+    // Replacing the formula clears what it spilled
+    engine.set_cell_value_js("Sheet1", 1, 4, "7").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 5).unwrap(), "");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 7).unwrap(), "0");
+}
+
+#[test]
+fn test_blocked_spills() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=3 {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64)).unwrap();
+    }
+    engine.set_cell_value_js("Sheet1", 2, 3, "in the way").unwrap();
+
+    let result = engine.evaluate("=A1:A3", "Sheet1", 1, 3).unwrap();
+    assert_eq!(result.error, Some(ExcelError::Spill));
+    assert_eq!(result.spill_range, None);
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 3).unwrap(), "");
+
+    // Clearing the cell in the way lets the array spill
+    engine.set_cell_value("Sheet1", 2, 3, CellValue::Empty).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "1");
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 3).unwrap(), "3");
+
+    // Typing into the spill range blocks it again
+    engine.set_cell_value_js("Sheet1", 3, 3, "x").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "#SPILL!");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 3).unwrap(), "");
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 3).unwrap(), "x");
+
+    // Another array is in the way too, until it moves
+    engine.set_cell_value("Sheet1", 3, 3, CellValue::Empty).unwrap();
+    engine.evaluate("=A1:A3", "Sheet1", 2, 4).unwrap();
+    let result = engine.evaluate("=A1:B1", "Sheet1", 3, 3).unwrap();
+    assert_eq!(result.error, Some(ExcelError::Spill));
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "#SPILL!");
+    engine.set_cell_value("Sheet1", 2, 4, CellValue::Empty).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 3).unwrap(), "1");
+
+    // Arrays neither run off the sheet nor spill into tables
+    assert_eq!(engine.evaluate("=A1:A3", "Sheet1", 1048575, 1).unwrap().error, Some(ExcelError::Spill));
+    engine.set_cell_value_js("Sheet1", 10, 6, "Header").unwrap();
+    engine.create_table("Data", "Sheet1", "F10:F12", false).unwrap();
+    assert_eq!(engine.evaluate("=A1:B3", "Sheet1", 11, 5).unwrap().error, Some(ExcelError::Spill));
+}
+
+#[test]
+fn test_spill_ranges_grow_and_shrink() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "3").unwrap();
+    engine.evaluate("=MAKEARRAY(A1,1,LAMBDA(r,c,r*10))", "Sheet1", 1, 2).unwrap();
+    engine.evaluate("=B3+1", "Sheet1", 1, 4).unwrap();
+    engine.evaluate("=B4+1", "Sheet1", 2, 4).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "31");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 4).unwrap(), "1");
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 2).unwrap(), "");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "1");
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 4, 2).unwrap(), "40");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "31");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 4).unwrap(), "41");
+}
+
+#[test]
+fn test_spill_references() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+    engine.evaluate("=MAKEARRAY(A1,2,LAMBDA(r,c,r+c))", "Sheet1", 1, 2).unwrap();
+    engine.evaluate("=SUM(B1#)", "Sheet1", 1, 5).unwrap();
+    engine.evaluate("=COUNT(Sheet1!B1#)", "Sheet1", 2, 5).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 5).unwrap(), "12");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 5).unwrap(), "4");
+
+    // The reference follows the spill range as it changes
+    engine.set_cell_value_js("Sheet1", 1, 1, "3").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 5).unwrap(), "21");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 5).unwrap(), "6");
+
+    // A spill reference can itself spill
+    let result = engine.evaluate("=B1#", "Sheet1", 1, 7).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("G1:H3"));
+
+    // Blocked, the reference is #SPILL!; without a formula it is #REF!, and
+    // a formula with a single value refers to its own cell
+    engine.set_cell_value_js("Sheet1", 3, 3, "x").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 5).unwrap(), "#SPILL!");
+    assert_eq!(engine.evaluate("=A1#", "Sheet1", 6, 1).unwrap().error, Some(ExcelError::Ref));
+    engine.evaluate("=A1*2", "Sheet1", 6, 2).unwrap();
+    assert_eq!(engine.evaluate("=B6#", "Sheet1", 6, 3).unwrap().value, CellValue::Number(6.0));
+}
+
+#[test]
+fn test_spills_past_the_last_populated_row() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "1").unwrap();
+
+    // The array has the size of the range, not of the cells holding values
+    let result = engine.evaluate("=A1:A5", "Sheet1", 1, 3).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("C1:C5"));
+    assert_eq!(engine.get_cell_value("Sheet1", 5, 3).unwrap(), "0");
+    let result = engine.evaluate("=C1#", "Sheet1", 1, 5).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("E1:E5"));
+
+    // Its blank rows are checked for cells in the way
+    engine.set_cell_value_js("Sheet1", 5, 7, "x").unwrap();
+    assert_eq!(engine.evaluate("=A1:A5", "Sheet1", 1, 7).unwrap().error, Some(ExcelError::Spill));
+    engine.set_cell_value("Sheet1", 5, 7, CellValue::Empty).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 7).unwrap(), "1");
+}
+
+#[test]
+fn test_implicit_intersection() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=3 {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64 * 10.0)).unwrap();
+    }
+
+    assert_eq!(engine.evaluate("=@A1:A3", "Sheet1", 2, 3).unwrap().value, CellValue::Number(20.0));
+    assert_eq!(engine.evaluate("=@$A:$A+1", "Sheet1", 3, 3).unwrap().value, CellValue::Number(31.0));
+    assert_eq!(engine.evaluate("=@A1:A3", "Sheet1", 5, 3).unwrap().error, Some(ExcelError::Value));
+    assert_eq!(engine.evaluate("=@A2:A2", "Sheet1", 9, 9).unwrap().value, CellValue::Number(20.0));
+
+    // An array is reduced to its first element and does not spill
+    let result = engine.evaluate("=@MAKEARRAY(2,2,LAMBDA(r,c,r*c+1))", "Sheet1", 7, 3).unwrap();
+    assert_eq!(result.value, CellValue::Number(2.0));
+    assert_eq!(result.spill_range, None);
+}
+
+#[test]
+fn test_spills_in_manual_mode() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+    engine.evaluate("=MAKEARRAY(A1,1,LAMBDA(r,c,r))", "Sheet1", 1, 2).unwrap();
+    engine.evaluate("=B3*2", "Sheet1", 1, 3).unwrap();
+    engine.change_calculation_mode(CalculationMode::Manual);
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "3").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 2).unwrap(), "");
+    engine.recalculate_js();
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 2).unwrap(), "3");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "6");
+}
+
+#[test]
+fn test_array_constants_and_operators() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for (row, (item, amount)) in [("x", "10"), ("y", "20"), ("x", "30")].into_iter().enumerate() {
+        engine.set_cell_value_js("Sheet1", row as u32 + 1, 1, item).unwrap();
+        engine.set_cell_value_js("Sheet1", row as u32 + 1, 2, amount).unwrap();
+    }
+
+    let result = engine.evaluate("={1,2,3;4,5,6}", "Sheet1", 1, 4).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("D1:F2"));
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 6).unwrap(), "6");
+
+    let result = engine.evaluate("=B1:B3*{1;2}", "Sheet1", 5, 1).unwrap();
+    assert_eq!(result.spill_range.as_deref(), Some("A5:A7"));
+    assert_eq!(engine.get_cell_value("Sheet1", 6, 1).unwrap(), "40");
+    assert_eq!(engine.get_cell_value("Sheet1", 7, 1).unwrap(), "#N/A");
+
+    engine.evaluate("=SUMPRODUCT((A1:A3=\"x\")*B1:B3)", "Sheet1", 9, 1).unwrap();
+    engine.evaluate("=SUMPRODUCT(B1:B3,{1;0;2})", "Sheet1", 10, 1).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 9, 1).unwrap(), "40");
+    assert_eq!(engine.get_cell_value("Sheet1", 10, 1).unwrap(), "70");
+    assert_eq!(engine.evaluate("=SUMPRODUCT(B1:B3,{1,2})", "Sheet1", 11, 1).unwrap().error, Some(ExcelError::Value));
+}
+
+#[test]
+fn test_legacy_array_formulas() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    for row in 1..=3 {
+        engine.set_cell_value("Sheet1", row, 1, CellValue::Number(row as f64)).unwrap();
+        engine.set_cell_value("Sheet1", row, 2, CellValue::Number(row as f64 * 10.0)).unwrap();
+    }
+    engine.set_cell_value_js("Sheet1", 2, 3, "replaced").unwrap();
+    engine.evaluate("=SUM(C1:C4)", "Sheet1", 1, 5).unwrap();
+
+    // The result is fitted to the range, over what it held
+    let result = engine.set_array_formula("=A1:A3*B1:B3", "Sheet1", "C1:C4").unwrap();
+    assert_eq!(result.cell_address, "C1");
+    assert_eq!(result.spill_range.as_deref(), Some("C1:C4"));
+    let shown: Vec<String> = (1..=4).map(|row| engine.get_cell_value("Sheet1", row, 3).unwrap()).collect();
+    assert_eq!(shown, ["10", "40", "90", "#N/A"]);
+    engine.set_array_formula("=SUM(A1:A3*B1:B3)", "Sheet1", "D1").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "140");
+
+    // Its cells change together, and follow their precedents
+    assert!(engine.set_cell_value("Sheet1", 2, 3, CellValue::Number(1.0)).is_err());
+    assert!(engine.evaluate("=1", "Sheet1", 1, 3).is_err());
+    assert!(engine.set_array_formula("=1", "Sheet1", "C3:C5").is_err());
+    assert!(engine.create_table("Data", "Sheet1", "B1:C3", false).is_err());
+    engine.set_cell_value_js("Sheet1", 3, 1, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 3).unwrap(), "120");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "170");
+
+    // Entering one over a larger range replaces it
+    engine.set_array_formula("={1,2}", "Sheet1", "C1:C5").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 5, 3).unwrap(), "1");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 5).unwrap(), "4");
+
+    engine.delete_array_formula("Sheet1", 3, 3).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "");
+    assert_eq!(engine.get_cell_value("Sheet1", 5, 3).unwrap(), "");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 5).unwrap(), "0");
+    assert!(engine.delete_array_formula("Sheet1", 3, 3).is_err());
+    engine.set_cell_value_js("Sheet1", 2, 3, "1").unwrap();
+
+    // Blank cells past the last populated row are still part of the range
+    engine.set_array_formula("=A1:A5*2", "Sheet1", "F1:F5").unwrap();
+    let shown: Vec<String> = (1..=5).map(|row| engine.get_cell_value("Sheet1", row, 6).unwrap()).collect();
+    assert_eq!(shown, ["2", "4", "8", "0", "0"]);
+}
+
+#[test]
+fn test_lookup_functions() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    let rows = [("Code", "Name", "Price"), ("10", "Apple", "1.5"), ("20", "Banana", "0.25"), ("30", "Cherry", "")];
+    for (row, (code, name, price)) in rows.into_iter().enumerate() {
+        for (col, value) in [code, name, price].into_iter().enumerate() {
+            engine.set_cell_value_js("Sheet1", row as u32 + 1, col as u32 + 1, value).unwrap();
+        }
+    }
+    let value = |engine: &mut IronCalcEngine, formula: &str| engine.evaluate(formula, "Sheet1", 10, 10).unwrap().value;
+    let n = CellValue::Number;
+    let text = |s: &str| CellValue::Text(s.to_string());
+
+    assert_eq!(value(&mut engine, "=VLOOKUP(20,A2:C4,2,FALSE)"), text("Banana"));
+    assert_eq!(value(&mut engine, "=VLOOKUP(25,A2:C4,3)"), n(0.25));
+    assert_eq!(value(&mut engine, "=VLOOKUP(\"b*\",B2:C4,2,FALSE)"), n(0.25));
+    assert_eq!(value(&mut engine, "=VLOOKUP(5,A2:C4,2)"), CellValue::Error(ExcelError::NA));
+    assert_eq!(value(&mut engine, "=VLOOKUP(10,A2:C4,4)"), CellValue::Error(ExcelError::Ref));
+    assert_eq!(value(&mut engine, "=HLOOKUP(\"name\",A1:C4,3,FALSE)"), text("Banana"));
+    assert_eq!(value(&mut engine, "=VLOOKUP(30,A:C,3,FALSE)"), n(0.0));
+
+    assert_eq!(value(&mut engine, "=MATCH(\"cherry\",B1:B4,0)"), n(4.0));
+    assert_eq!(value(&mut engine, "=MATCH(29,A2:A4)"), n(2.0));
+    assert_eq!(value(&mut engine, "=INDEX(A1:C4,3,2)"), text("Banana"));
+    assert_eq!(value(&mut engine, "=INDEX(B2:B4,MATCH(30,A2:A4,0))"), text("Cherry"));
+    assert_eq!(value(&mut engine, "=SUM(INDEX(A2:C4,0,1))"), n(60.0));
+    assert_eq!(value(&mut engine, "=INDEX(A2:C4,4,1)"), CellValue::Error(ExcelError::Ref));
+
+    assert_eq!(value(&mut engine, "=XLOOKUP(\"apple\",B2:B4,C2:C4)"), n(1.5));
+    assert_eq!(value(&mut engine, "=XLOOKUP(15,A2:A4,B2:B4,\"none\")"), text("none"));
+    assert_eq!(value(&mut engine, "=XLOOKUP(15,A2:A4,B2:B4,,1)"), text("Banana"));
+    assert_eq!(value(&mut engine, "=XLOOKUP(15,A2:A4,B2:B4,,-1,2)"), text("Apple"));
+    assert_eq!(value(&mut engine, "=XLOOKUP(15,A2:A4,B2:B4)"), CellValue::Error(ExcelError::NA));
+    assert_eq!(value(&mut engine, "=XLOOKUP(1,A2:A4,B2:B3)"), CellValue::Error(ExcelError::Value));
+    assert_eq!(value(&mut engine, "=XMATCH(\"?a*\",B2:B4,2)"), n(2.0));
+    assert_eq!(value(&mut engine, "=XMATCH(30,A2:A4,0,-2)"), CellValue::Error(ExcelError::NA));
+
+    // A whole row comes back as an array, and arrays of lookup values give
+    // arrays of results
+    assert_eq!(value(&mut engine, "=XLOOKUP(20,A2:A4,A2:C4)"), CellValue::Array(vec![vec![n(20.0), text("Banana"), n(0.25)]]));
+    assert_eq!(value(&mut engine, "=XLOOKUP({10;30},A2:A4,B2:B4)"), CellValue::Array(vec![vec![text("Apple")], vec![text("Cherry")]]));
+}
+
+#[test]
+fn test_lookups_follow_their_ranges() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.create_sheet("Prices").unwrap();
+    engine.set_cell_value_js("Prices", 1, 1, "apple").unwrap();
+    engine.set_cell_value_js("Prices", 1, 2, "3").unwrap();
+    engine.evaluate("=VLOOKUP(\"apple\",Prices!A:B,2,FALSE)", "Sheet1", 1, 1).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "3");
+
+    engine.set_cell_value_js("Prices", 1, 2, "4").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "4");
+}
+
+#[test]
+fn test_split_text_spills() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "EU-DE-1234").unwrap();
+    let result = engine.evaluate("=TEXTSPLIT(A1,\"-\")", "Sheet1", 1, 2).unwrap();
+    let text = |s: &str| CellValue::Text(s.to_string());
+    assert_eq!(result.value, CellValue::Array(vec![vec![text("EU"), text("DE"), text("1234")]]));
+    assert_eq!(result.spill_range.as_deref(), Some("B1:D1"));
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "1234");
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "US-CA").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "CA");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "");
+
+    // An array formula shows the parts in a fixed range instead
+    engine.set_array_formula("=TEXTSPLIT(A1,,\"-\")", "Sheet1", "F1:F3").unwrap();
+    let shown: Vec<String> = (1..=3).map(|row| engine.get_cell_value("Sheet1", row, 6).unwrap()).collect();
+    assert_eq!(shown, ["US", "CA", "#N/A"]);
+}
+
+#[test]
+fn test_text_splitting() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "EU-DE-1234").unwrap();
+    engine.evaluate("=TEXTAFTER(A1,\"-\",-1)", "Sheet1", 2, 1).unwrap();
+    engine.evaluate("=TEXTSPLIT(A1,\"-\")", "Sheet1", 3, 1).unwrap();
+
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "1234");
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 2).unwrap(), "DE");
+}
+
+#[test]
+fn test_1904_date_system() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    engine.set_cell_value_js("Sheet1", 1, 1, "45306").unwrap();
+    engine.evaluate("=YEAR(A1)", "Sheet1", 1, 2).unwrap();
+    engine.evaluate("=DATE(2024,1,15)", "Sheet1", 2, 2).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "2024");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 2).unwrap(), "45306");
+
+    // The same serial numbers are dates four years and a day later, and
+    // formulas recalculate in the new system
+    engine.change_date_system(DateSystem::Excel1904);
+    assert_eq!(engine.get_date_system(), "1904");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "2028");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 2).unwrap(), "43844");
+    assert_eq!(engine.evaluate("=\"2024-01-15\"+1", "Sheet1", 5, 2).unwrap().value, CellValue::Number(43845.0));
+    assert_eq!(engine.evaluate("=DATE(1904,1,1)", "Sheet1", 3, 2).unwrap().value, CellValue::Number(0.0));
+    assert_eq!(engine.evaluate("=DATE(1903,12,31)", "Sheet1", 4, 2).unwrap().error, Some(ExcelError::Num));
+}
@@ -0,0 +1,317 @@
+//! Typed cell values
+//!
+//! [`CellValue`] is what worksheets store and what formulas evaluate to. The
+//! coercion helpers implement Excel's conversion rules between types: blanks
+//! read as 0, "" or FALSE depending on the context, booleans as 1/0, and text
+//! only converts to a number when it looks like one, or for operators like
+//! a date.
+
+use crate::date::{self, DateSystem};
+use crate::error::ExcelError;
+use crate::scope::Lambda;
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum CellValue {
+    /// A blank cell
+    #[default]
+    Empty,
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    /// An Excel error such as `#DIV/0!`
+    Error(ExcelError),
+    /// Row-major array result, e.g. the value of `=A1:B3`
+    Array(Vec<Vec<CellValue>>),
+    /// A function value created by `LAMBDA`. It only lives while a formula
+    /// is evaluated; a formula whose result is a lambda shows `#CALC!`.
+    Lambda(Rc<Lambda>),
+}
+
+impl CellValue {
+    /// Interpret text typed into a cell the way Excel does: numbers,
+    /// `TRUE`/`FALSE` and error literals keep their type, a leading
+    /// apostrophe forces the rest to be stored as text.
+    pub fn from_input(input: &str) -> CellValue {
+        if input.is_empty() {
+            return CellValue::Empty;
+        }
+        if let Some(text) = input.strip_prefix('\'') {
+            return CellValue::Text(text.to_string());
+        }
+        if let Some(n) = parse_number(input) {
+            return CellValue::Number(n);
+        }
+        if input.eq_ignore_ascii_case("TRUE") {
+            return CellValue::Boolean(true);
+        }
+        if input.eq_ignore_ascii_case("FALSE") {
+            return CellValue::Boolean(false);
+        }
+        if let Some(error) = ExcelError::from_code(input) {
+            return CellValue::Error(error);
+        }
+        CellValue::Text(input.to_string())
+    }
+
+    /// Coerce to a number following Excel's rules for function arguments,
+    /// where only text written as a number converts.
+    pub fn to_number(&self) -> Result<f64, ExcelError> {
+        match self {
+            CellValue::Number(n) => Ok(*n),
+            CellValue::Boolean(b) => Ok(if *b { 1.0 } else { 0.0 }),
+            CellValue::Text(s) => parse_number(s).ok_or(ExcelError::Value),
+            CellValue::Empty => Ok(0.0),
+            CellValue::Error(e) => Err(*e),
+            CellValue::Array(_) => Err(ExcelError::Value),
+            CellValue::Lambda(_) => Err(ExcelError::Calc),
+        }
+    }
+
+    /// Coerce to a number as operators do, where text that looks like a
+    /// date or time, such as "2024-01-15", is its serial number in
+    /// `system`.
+    pub fn to_number_in(&self, system: DateSystem) -> Result<f64, ExcelError> {
+        match self {
+            CellValue::Text(s) => parse_number(s)
+                .or_else(|| {
+                    let (date, time) = date::parse(system, s)?;
+                    Some(date.unwrap_or(0) as f64 + time.unwrap_or(0.0))
+                })
+                .ok_or(ExcelError::Value),
+            _ => self.to_number(),
+        }
+    }
+
+    /// Coerce to a boolean following Excel's rules for logical arguments.
+    pub fn to_bool(&self) -> Result<bool, ExcelError> {
+        match self {
+            CellValue::Number(n) => Ok(*n != 0.0),
+            CellValue::Boolean(b) => Ok(*b),
+            CellValue::Text(s) if s.eq_ignore_ascii_case("TRUE") => Ok(true),
+            CellValue::Text(s) if s.eq_ignore_ascii_case("FALSE") => Ok(false),
+            CellValue::Text(_) | CellValue::Array(_) => Err(ExcelError::Value),
+            CellValue::Empty => Ok(false),
+            CellValue::Error(e) => Err(*e),
+            CellValue::Lambda(_) => Err(ExcelError::Calc),
+        }
+    }
+
+    /// Coerce to text following Excel's rules, as `&` does.
+    pub fn to_text(&self) -> Result<String, ExcelError> {
+        match self {
+            CellValue::Error(e) => Err(*e),
+            CellValue::Array(_) => Err(ExcelError::Value),
+            CellValue::Lambda(_) => Err(ExcelError::Calc),
+            _ => Ok(self.to_display_string()),
+        }
+    }
+
+    /// Text shown for the value, as returned by `getCellValue`.
+    pub fn to_display_string(&self) -> String {
+        match self {
+            CellValue::Empty => String::new(),
+            CellValue::Number(n) => format_number(*n),
+            CellValue::Text(s) => s.clone(),
+            CellValue::Boolean(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+            CellValue::Error(e) => e.to_string(),
+            CellValue::Array(rows) => rows
+                .first()
+                .and_then(|row| row.first())
+                .map(CellValue::to_display_string)
+                .unwrap_or_default(),
+            CellValue::Lambda(_) => ExcelError::Calc.to_string(),
+        }
+    }
+
+    /// The value as the rows of an array; a single value is a 1x1 array.
+    pub fn into_rows(self) -> Vec<Vec<CellValue>> {
+        match self {
+            CellValue::Array(rows) => rows,
+            value => vec![vec![value]],
+        }
+    }
+
+    /// Height and width of the value as an array; a single value is 1x1.
+    pub fn dimensions(&self) -> (usize, usize) {
+        match self {
+            CellValue::Array(rows) => (rows.len(), rows.first().map_or(0, Vec::len)),
+            _ => (1, 1),
+        }
+    }
+
+    /// The element at `row`, `col` when the value is stretched over a
+    /// larger array: a single row or column repeats along the other
+    /// dimension and a single value everywhere. `None` past the end of a
+    /// dimension that does not repeat.
+    pub fn broadcast_at(&self, row: usize, col: usize) -> Option<&CellValue> {
+        match self {
+            CellValue::Array(rows) => {
+                let cells = rows.get(if rows.len() == 1 { 0 } else { row })?;
+                cells.get(if cells.len() == 1 { 0 } else { col })
+            }
+            value => Some(value),
+        }
+    }
+
+    /// Excel orders mixed types as numbers < text < booleans, and compares
+    /// text case-insensitively. A blank cell compares as the zero value of
+    /// the other operand's type.
+    pub fn compare(&self, other: &CellValue) -> Ordering {
+        fn rank(value: &CellValue) -> u8 {
+            match value {
+                CellValue::Number(_) | CellValue::Empty => 0,
+                CellValue::Text(_) => 1,
+                CellValue::Boolean(_) => 2,
+                CellValue::Error(_) | CellValue::Array(_) | CellValue::Lambda(_) => 3,
+            }
+        }
+
+        fn blank_like(value: &CellValue) -> CellValue {
+            match value {
+                CellValue::Text(_) => CellValue::Text(String::new()),
+                CellValue::Boolean(_) => CellValue::Boolean(false),
+                _ => CellValue::Number(0.0),
+            }
+        }
+
+        match (self, other) {
+            (CellValue::Empty, CellValue::Empty) => Ordering::Equal,
+            (CellValue::Empty, _) => blank_like(other).compare(other),
+            (_, CellValue::Empty) => self.compare(&blank_like(self)),
+            (CellValue::Number(a), CellValue::Number(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+            (CellValue::Text(a), CellValue::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            (CellValue::Boolean(a), CellValue::Boolean(b)) => a.cmp(b),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+/// Values serialize to their natural JSON types: numbers, strings, booleans,
+/// `null` for blanks, the error code string for errors and nested arrays for
+/// array results.
+impl Serialize for CellValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            CellValue::Empty => serializer.serialize_none(),
+            CellValue::Number(n) => serializer.serialize_f64(*n),
+            CellValue::Text(s) => serializer.serialize_str(s),
+            CellValue::Error(e) => e.serialize(serializer),
+            CellValue::Boolean(b) => serializer.serialize_bool(*b),
+            CellValue::Array(rows) => {
+                let mut seq = serializer.serialize_seq(Some(rows.len()))?;
+                for row in rows {
+                    seq.serialize_element(row)?;
+                }
+                seq.end()
+            }
+            CellValue::Lambda(_) => ExcelError::Calc.serialize(serializer),
+        }
+    }
+}
+
+/// The longest text a cell holds, in UTF-16 code units
+pub const MAX_TEXT: usize = 32_767;
+
+/// Text built by a function or by `&`, `#VALUE!` past the length a cell
+/// holds.
+pub fn limited_text(text: String) -> Result<CellValue, ExcelError> {
+    if text.len() > MAX_TEXT && text.encode_utf16().count() > MAX_TEXT {
+        return Err(ExcelError::Value);
+    }
+    Ok(CellValue::Text(text))
+}
+
+/// Parse text as a number the way Excel coerces text operands, accepting
+/// surrounding whitespace and a trailing percent sign.
+fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (digits, scale) = match text.strip_suffix('%') {
+        Some(digits) => (digits.trim_end(), 100.0),
+        None => (text, 1.0),
+    };
+
+    // Rust also accepts "inf" and "NaN", which Excel does not
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '.' | '-' | '+')) {
+        return None;
+    }
+
+    digits.parse::<f64>().ok().map(|n| n / scale).filter(|n| n.is_finite())
+}
+
+/// Format a number as Excel's General format does when converting to text:
+/// at most 15 significant digits, switching to scientific notation for very
+/// large or very small magnitudes.
+pub fn format_number(n: f64) -> String {
+    if n == 0.0 {
+        return "0".to_string();
+    }
+
+    // Round to 15 significant digits, then let Rust print the shortest
+    // representation of the rounded value
+    let rounded: f64 = format!("{:.14e}", n).parse().unwrap_or(n);
+    let exponent = rounded.abs().log10().floor() as i32;
+
+    if (-9..15).contains(&exponent) {
+        return rounded.to_string();
+    }
+
+    let scientific = format!("{:e}", rounded);
+    let (mantissa, exp) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let exp: i32 = exp.parse().unwrap_or(0);
+    format!("{}E{}{:02}", mantissa, if exp < 0 { '-' } else { '+' }, exp.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_input() {
+        assert_eq!(CellValue::from_input(""), CellValue::Empty);
+        assert_eq!(CellValue::from_input(" 42 "), CellValue::Number(42.0));
+        assert_eq!(CellValue::from_input("12.5%"), CellValue::Number(0.125));
+        assert_eq!(CellValue::from_input("true"), CellValue::Boolean(true));
+        assert_eq!(CellValue::from_input("#n/a"), CellValue::Error(ExcelError::NA));
+        assert_eq!(CellValue::from_input("'42"), CellValue::Text("42".to_string()));
+        assert_eq!(CellValue::from_input("inf"), CellValue::Text("inf".to_string()));
+    }
+
+    #[test]
+    fn test_coercion() {
+        assert_eq!(CellValue::Text(" 3 ".to_string()).to_number(), Ok(3.0));
+        assert_eq!(CellValue::Text("abc".to_string()).to_number(), Err(ExcelError::Value));
+        assert_eq!(CellValue::Boolean(true).to_number(), Ok(1.0));
+        assert_eq!(CellValue::Empty.to_text(), Ok(String::new()));
+        assert_eq!(CellValue::Number(0.0).to_bool(), Ok(false));
+        assert_eq!(CellValue::Error(ExcelError::Ref).to_number(), Err(ExcelError::Ref));
+    }
+
+    #[test]
+    fn test_broadcasting() {
+        let n = CellValue::Number;
+        let column = CellValue::Array(vec![vec![n(1.0)], vec![n(2.0)]]);
+        let block = CellValue::Array(vec![vec![n(1.0), n(2.0)], vec![n(3.0), n(4.0)]]);
+
+        assert_eq!(column.dimensions(), (2, 1));
+        assert_eq!(column.broadcast_at(1, 5), Some(&n(2.0)));
+        assert_eq!(column.broadcast_at(2, 0), None);
+        assert_eq!(block.broadcast_at(1, 1), Some(&n(4.0)));
+        assert_eq!(block.broadcast_at(0, 2), None);
+        assert_eq!(n(7.0).broadcast_at(3, 3), Some(&n(7.0)));
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(6.0), "6");
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(-2.5), "-2.5");
+        assert_eq!(format_number(1.0 / 3.0), "0.333333333333333");
+        assert_eq!(format_number(123456789012345.0), "123456789012345");
+        assert_eq!(format_number(1e15), "1E+15");
+        assert_eq!(format_number(1.5e-10), "1.5E-10");
+        assert_eq!(format_number(0.0001), "0.0001");
+    }
+}
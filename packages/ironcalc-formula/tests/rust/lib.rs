@@ -21,7 +21,7 @@ fn test_simple_arithmetic() {
     let result_val = result.unwrap();
     // Parse the JSON result to check the value
     // In a real test, we'd use proper JSON parsing
-    assert!(js_sys::JSON::stringify(&result_val).unwrap().as_string().unwrap().contains("\"6\""));
+    assert!(js_sys::JSON::stringify(&result_val).unwrap().as_string().unwrap().contains("\"value\":6"));
 }
 
 #[wasm_bindgen_test]
@@ -151,4 +151,143 @@ fn test_complex_formulas() {
     // Test multiple arithmetic operations
     let result = engine.evaluate_formula("=((10+5)*2-3)/7", "Sheet1", 1, 2);
     assert!(result.is_ok(), "Complex arithmetic should work");
-}
\ No newline at end of file
+}
+#[wasm_bindgen_test]
+fn test_cell_references() {
+    let mut engine = IronCalcEngine::new().unwrap();
+    
+    engine.set_cell_value_js("Sheet1", 1, 1, "6").unwrap();
+    engine.set_cell_value_js("Sheet1", 2, 2, "7").unwrap();
+    
+    let result = engine.evaluate_formula("=A1*B2", "Sheet1", 3, 3);
+    assert!(result.is_ok(), "Formula with cell references should succeed");
+    assert!(js_sys::JSON::stringify(&result.unwrap()).unwrap().as_string().unwrap().contains("\"value\":42"));
+}
+
+#[wasm_bindgen_test]
+fn test_custom_functions() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+    engine.set_cell_value_js("Sheet1", 2, 1, "3").unwrap();
+
+    // Ranges arrive as arrays of rows
+    let total = js_sys::Function::new_with_args("rows", "return rows.flat().reduce((a, b) => a + b, 0)");
+    engine.register_custom_function("TOTAL", 1, Some(1), total).unwrap();
+    engine.evaluate_formula("=TOTAL(A1:A2)*10", "Sheet1", 1, 2).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "50");
+
+    // Exceptions become #VALUE!
+    let fails = js_sys::Function::new_no_args("throw new Error('boom')");
+    engine.register_custom_function("FAILS", 0, None, fails).unwrap();
+    engine.evaluate_formula("=FAILS()", "Sheet1", 1, 3).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "#VALUE!");
+
+    // Built-in functions cannot be replaced
+    let sum = js_sys::Function::new_no_args("return 0");
+    assert!(engine.register_custom_function("SUM", 0, None, sum).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_defined_names() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "0.2").unwrap();
+    engine.define_name_js("TaxRate", "=Sheet1!$A$1", None).unwrap();
+    engine.evaluate_formula("=100*TaxRate", "Sheet1", 1, 2).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "20");
+
+    engine.rename_name_js("TaxRate", "Vat", None).unwrap();
+    let names = js_sys::JSON::stringify(&engine.get_names().unwrap()).unwrap().as_string().unwrap();
+    assert!(names.contains("\"name\":\"Vat\""));
+
+    engine.delete_name_js("Vat", None).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "#NAME?");
+    assert!(engine.update_name_js("Vat", "=1", None).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_tables() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "Amount").unwrap();
+    engine.set_cell_value_js("Sheet1", 2, 1, "10").unwrap();
+    engine.create_table_js("Sales", "Sheet1", "A1:A2", false).unwrap();
+    engine.evaluate_formula("=SUM(Sales[Amount])", "Sheet1", 1, 3).unwrap();
+
+    engine.set_cell_value_js("Sheet1", 3, 1, "5").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "15");
+    let tables = js_sys::JSON::stringify(&engine.get_tables().unwrap()).unwrap().as_string().unwrap();
+    assert!(tables.contains("\"range\":\"A1:A3\""));
+
+    engine.delete_table_js("Sales").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "#REF!");
+}
+
+#[wasm_bindgen_test]
+fn test_spilled_arrays() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "1").unwrap();
+    engine.set_cell_value_js("Sheet1", 2, 1, "2").unwrap();
+    let result = engine.evaluate_formula("=A1:A2", "Sheet1", 1, 2).unwrap();
+    let json = js_sys::JSON::stringify(&result).unwrap().as_string().unwrap();
+    assert!(json.contains("\"spill_range\":\"B1:B2\""));
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 2).unwrap(), "2");
+
+    engine.evaluate_formula("=SUM(B1#)", "Sheet1", 1, 3).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 3).unwrap(), "3");
+
+    engine.set_cell_value_js("Sheet1", 2, 2, "x").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "#SPILL!");
+}
+
+#[wasm_bindgen_test]
+fn test_array_formulas() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.set_cell_value_js("Sheet1", 1, 1, "2").unwrap();
+    engine.set_cell_value_js("Sheet1", 2, 1, "3").unwrap();
+    engine.set_array_formula_js("=A1:A2*{10;100}", "Sheet1", "B1:B3").unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 2).unwrap(), "300");
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 2).unwrap(), "#N/A");
+    assert!(engine.set_cell_value_js("Sheet1", 2, 2, "1").is_err());
+
+    engine.delete_array_formula_js("Sheet1", 3, 2).unwrap();
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 2).unwrap(), "");
+}
+
+#[wasm_bindgen_test]
+fn test_lookup_functions() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    for (row, (key, value)) in [("a", "1"), ("b", "2"), ("c", "3")].into_iter().enumerate() {
+        engine.set_cell_value_js("Sheet1", row as u32 + 1, 1, key).unwrap();
+        engine.set_cell_value_js("Sheet1", row as u32 + 1, 2, value).unwrap();
+    }
+    engine.evaluate_formula("=VLOOKUP(\"b\",A1:B3,2,FALSE)", "Sheet1", 1, 4).unwrap();
+    engine.evaluate_formula("=XLOOKUP(\"z\",A1:A3,B1:B3,\"missing\")", "Sheet1", 2, 4).unwrap();
+    engine.evaluate_formula("=INDEX(B1:B3,MATCH(\"c\",A1:A3,0))", "Sheet1", 3, 4).unwrap();
+
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 4).unwrap(), "2");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 4).unwrap(), "missing");
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 4).unwrap(), "3");
+}
+
+#[wasm_bindgen_test]
+fn test_date_functions() {
+    let mut engine = IronCalcEngine::new().unwrap();
+
+    engine.evaluate_formula("=DATE(2024,1,31)", "Sheet1", 1, 1).unwrap();
+    engine.evaluate_formula("=EDATE(A1,1)", "Sheet1", 2, 1).unwrap();
+    engine.evaluate_formula("=DATEDIF(\"2020-03-15\",A1,\"Y\")", "Sheet1", 3, 1).unwrap();
+
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "45322");
+    assert_eq!(engine.get_cell_value("Sheet1", 2, 1).unwrap(), "45351");
+    assert_eq!(engine.get_cell_value("Sheet1", 3, 1).unwrap(), "3");
+
+    engine.set_date_system("1904").unwrap();
+    assert_eq!(engine.get_date_system(), "1904");
+    assert_eq!(engine.get_cell_value("Sheet1", 1, 1).unwrap(), "43860");
+    assert!(engine.set_date_system("2000").is_err());
+}
@@ -12,6 +12,8 @@ import type {
 } from './mock-plugins.js';
 
 import { 
+  CellValue,
+  CustomFunction,
   FormulaResult, 
   IronCalcConfig, 
   FormulaColumn,
@@ -39,6 +41,10 @@ export class IronCalcFormulaPlugin implements IDataProcessorPlugin, IIntegration
       memoryLimitMB: 512,
       calculationTimeout: 30000,
       autoRecalculation: true,
+      iterativeCalculation: false,
+      maxIterations: 100,
+      maxChange: 0.001,
+      dateSystem: '1900',
       cacheSize: 10000,
       logLevel: 'info'
     };
@@ -132,6 +138,15 @@ export class IronCalcFormulaPlugin implements IDataProcessorPlugin, IIntegration
   async configure(settings: Partial<IronCalcConfig>): Promise<void> {
     this.config = { ...this.config, ...settings };
     this.context?.logger.info('IronCalc configured:', this.config);
+
+    this.engine?.setCalculationMode(this.config.autoRecalculation ? 'automatic' : 'manual');
+    this.engine?.setIterativeCalculation(
+      this.config.iterativeCalculation,
+      this.config.maxIterations,
+      this.config.maxChange
+    );
+    this.engine?.setDateSystem(this.config.dateSystem);
+    this.engine?.setCacheSize(this.config.cacheSize);
     
     // Apply memory limits if engine is available
     if (this.engine && this.config.memoryLimitMB) {
@@ -189,10 +204,30 @@ export class IronCalcFormulaPlugin implements IDataProcessorPlugin, IIntegration
           default: true, 
           description: 'Enable automatic recalculation on data changes' 
         },
+        iterativeCalculation: {
+          type: 'boolean',
+          default: false,
+          description: 'Iterate circular references until they converge'
+        },
+        maxIterations: {
+          type: 'number',
+          default: 100,
+          description: 'Maximum iterations for circular references'
+        },
+        maxChange: {
+          type: 'number',
+          default: 0.001,
+          description: 'Largest change between iterations that counts as converged'
+        },
+        dateSystem: {
+          type: 'string',
+          default: '1900',
+          description: 'Serial date numbering: from 1900-01-00 as in Excel for Windows, or from 1904-01-01'
+        },
         cacheSize: {
           type: 'number',
           default: 10000,
-          description: 'Formula cache size'
+          description: 'Number of formula results cached; least recently used results are evicted beyond it'
         },
         logLevel: {
           type: 'string',
@@ -445,6 +480,30 @@ export class IronCalcFormulaPlugin implements IDataProcessorPlugin, IIntegration
         return this.getPerformanceMetrics();
       case 'clearCache':
         return this.clearCache();
+      case 'recalculate':
+        return this.recalculate(params?.sheet, params?.full);
+      case 'registerCustomFunction':
+        return this.registerCustomFunction(params.name, params.fn, params.minArgs, params.maxArgs);
+      case 'defineName':
+        return this.engine!.defineName(params.name, params.refersTo, params.sheet);
+      case 'updateName':
+        return this.engine!.updateName(params.name, params.refersTo, params.sheet);
+      case 'deleteName':
+        return this.engine!.deleteName(params.name, params.sheet);
+      case 'renameName':
+        return this.engine!.renameName(params.name, params.newName, params.sheet);
+      case 'getNames':
+        return this.engine!.getNames();
+      case 'createTable':
+        return this.engine!.createTable(params.name, params.sheet, params.range, params.totalsRow ?? false);
+      case 'deleteTable':
+        return this.engine!.deleteTable(params.name);
+      case 'getTables':
+        return this.engine!.getTables();
+      case 'setArrayFormula':
+        return this.engine!.setArrayFormula(params.formula, params.sheet, params.range);
+      case 'deleteArrayFormula':
+        return this.engine!.deleteArrayFormula(params.sheet, params.row, params.col);
       default:
         throw new Error(`Unsupported operation: ${operation}`);
     }
@@ -654,6 +713,38 @@ export class IronCalcFormulaPlugin implements IDataProcessorPlugin, IIntegration
     this.engine!.clearCache();
   }
 
+  private recalculate(sheet?: string, full: boolean = false): void {
+    this.ensureInitialized();
+
+    if (full) {
+      this.engine!.recalculateAll();
+    } else if (sheet) {
+      this.engine!.recalculateSheet(sheet);
+    } else {
+      this.engine!.recalculate();
+    }
+  }
+
+  private registerCustomFunction(
+    name: string,
+    fn: CustomFunction,
+    minArgs: number = 0,
+    maxArgs?: number
+  ): void {
+    this.ensureInitialized();
+
+    if (!this.config.enableCustomFunctions) {
+      throw new Error('Custom functions are disabled (enableCustomFunctions is false)');
+    }
+
+    if (typeof fn !== 'function') {
+      throw new Error(`Custom function ${name} must be a function`);
+    }
+
+    this.engine!.registerCustomFunction(name, minArgs, maxArgs, fn);
+    this.context?.logger.debug(`Registered custom function ${name}`);
+  }
+
   private substituteColumnReferences(formula: string, rowData: any): string {
     let processedFormula = formula;
     
@@ -676,15 +767,17 @@ export class IronCalcFormulaPlugin implements IDataProcessorPlugin, IIntegration
     return processedFormula;
   }
 
-  private convertValueToType(value: string, type: string): any {
+  private convertValueToType(value: CellValue, type: string): any {
     switch (type) {
       case 'number':
-        const num = parseFloat(value);
+        if (typeof value === 'number') return value;
+        const num = parseFloat(String(value));
         return isNaN(num) ? null : num;
       case 'boolean':
-        return value.toLowerCase() === 'true' || value === '1';
+        if (typeof value === 'boolean') return value;
+        return String(value).toLowerCase() === 'true' || value === 1 || value === '1';
       case 'date':
-        const date = new Date(value);
+        const date = new Date(String(value));
         return isNaN(date.getTime()) ? null : date.toISOString().split('T')[0];
       default:
         return value;
@@ -23,12 +23,74 @@ export type {
 
 // IronCalc-specific types
 
+/**
+ * A typed cell value: numbers, text and booleans keep their JavaScript type,
+ * Excel errors are their code (e.g. "#DIV/0!"), blanks are null and array
+ * results are row-major nested arrays.
+ */
+export type CellValue = number | string | boolean | null | CellValue[][];
+
+/** Excel error values a formula can evaluate to */
+export type ExcelErrorCode =
+  | '#NULL!'
+  | '#DIV/0!'
+  | '#VALUE!'
+  | '#REF!'
+  | '#NAME?'
+  | '#NUM!'
+  | '#N/A'
+  | '#SPILL!'
+  | '#CALC!'
+  | '#GETTING_DATA'
+  /** Not an Excel error: marks cells caught in a circular reference */
+  | '#CIRC!';
+
 export interface FormulaResult {
-  value: string;
-  error?: string;
+  value: CellValue;
+  /** Set when `value` is an Excel error; engine failures throw instead */
+  error?: ExcelErrorCode;
+  /**
+   * When the formula closes a circular reference, the chain of cells it
+   * follows, e.g. ['A1', 'B1', 'C1', 'A1']
+   */
+  circular_reference?: string[];
+  /** Set when the formula is part of a circular reference that was iterated */
+  iteration?: IterationStatus;
   execution_time_ms: number;
   cell_address: string;
   formula_type: string;
+  /** The cells an array result spilled into, e.g. 'D1:E3' */
+  spill_range?: string;
+}
+
+export interface DefinedName {
+  name: string;
+  /** The formula the name stands for, e.g. '=Sheet1!$B$1' */
+  refers_to: string;
+  /** The sheet the name is scoped to; null for workbook names */
+  sheet: string | null;
+}
+
+export interface TableInfo {
+  name: string;
+  sheet: string;
+  /** The whole table, header and totals rows included, e.g. 'A1:C10' */
+  range: string;
+  /** Column names, read from the header row */
+  columns: string[];
+  totals_row: boolean;
+}
+
+export type CalculationMode = 'automatic' | 'automatic-except-tables' | 'manual';
+
+/** Day 0 of serial dates: 1900-01-00, as in Excel for Windows, or 1904-01-01 */
+export type DateSystem = '1900' | '1904';
+
+export interface IterationStatus {
+  iterations: number;
+  converged: boolean;
+  /** Largest change of a cell in the last iteration */
+  max_change: number;
 }
 
 export interface PerformanceMetrics {
@@ -39,12 +101,28 @@ export interface PerformanceMetrics {
   cache_hit_rate: number;
 }
 
+/**
+ * Implementation of a custom function. Arguments are numbers, strings,
+ * booleans, null for blank cells and arrays of rows for ranges; errors inside
+ * ranges arrive as their codes. Returning an error code string makes the
+ * formula that error, and throwing makes it #VALUE!.
+ */
+export type CustomFunction = (...args: any[]) => CellValue | CellValue[] | CellValue[][];
+
 export interface IronCalcConfig {
   maxCells: number;
   enableCustomFunctions: boolean;
   memoryLimitMB: number;
   calculationTimeout: number;
+  /** Recalculate dependents on every change; when false, call the recalculate operation */
   autoRecalculation: boolean;
+  /** Let circular references converge instead of failing with #CIRC! */
+  iterativeCalculation: boolean;
+  maxIterations: number;
+  maxChange: number;
+  /** Serial date numbering; changing it recalculates every formula */
+  dateSystem: DateSystem;
+  /** Formula results cached, evicted least recently used first */
   cacheSize: number;
   logLevel: 'debug' | 'info' | 'warn' | 'error';
 }
@@ -97,8 +175,42 @@ export interface IronCalcWasmEngine {
   setCellValue(sheet: string, row: number, col: number, value: string): void;
   getCellValue(sheet: string, row: number, col: number): string;
   createSheet(name: string): void;
+  insertSheet(name: string, index: number): void;
+  getSheetNames(): string[];
+  setCalculationMode(mode: CalculationMode): void;
+  getCalculationMode(): CalculationMode;
+  /** Recalculate dirty formulas (F9) */
+  recalculate(): void;
+  /** Recalculate dirty formulas on one sheet (Shift+F9) */
+  recalculateSheet(name: string): void;
+  /** Recalculate every formula (Ctrl+Alt+F9) */
+  recalculateAll(): void;
+  setIterativeCalculation(enabled: boolean, maxIterations: number, maxChange: number): void;
+  getIterativeCalculation(): { enabled: boolean; max_iterations: number; max_change: number };
+  getIterationStatus(): IterationStatus | undefined;
+  setDateSystem(system: DateSystem): void;
+  getDateSystem(): DateSystem;
+  /** Register a function callable from formulas; maxArgs undefined means any number */
+  registerCustomFunction(name: string, minArgs: number, maxArgs: number | undefined, fn: CustomFunction): void;
+  /** Define a name for a cell, range, constant or formula; sheet-scoped when `sheet` is given */
+  defineName(name: string, refersTo: string, sheet?: string): void;
+  updateName(name: string, refersTo: string, sheet?: string): void;
+  deleteName(name: string, sheet?: string): void;
+  /** Rename a name, rewriting the formulas that use it */
+  renameName(name: string, newName: string, sheet?: string): void;
+  getNames(): DefinedName[];
+  /** Create a table over a range whose first row holds the column names */
+  createTable(name: string, sheet: string, range: string, totalsRow: boolean): void;
+  deleteTable(name: string): void;
+  getTables(): TableInfo[];
+  /** Enter a legacy array formula whose result fills `range`, e.g. 'C1:C10' */
+  setArrayFormula(formula: string, sheet: string, range: string): FormulaResult;
+  /** Clear the array formula covering a cell */
+  deleteArrayFormula(sheet: string, row: number, col: number): void;
   getPerformanceMetrics(): string;
   clearCache(): void;
+  /** Least recently used results are evicted beyond `size`; 0 disables caching */
+  setCacheSize(size: number): void;
   getMemoryUsage(): number;
 }
 